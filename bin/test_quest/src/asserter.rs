@@ -1,15 +1,40 @@
 use core::fmt;
 use std::fmt::Display;
+use std::ops::Range;
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
 
+use cookie::Cookie;
 use flume::Receiver;
 use flume::Sender;
 use reqwest::StatusCode;
 use reqwest::header::HeaderMap;
+use reqwest::header::HeaderName;
+use reqwest::header::SET_COOKIE;
+use similar::ChangeTag;
+use similar::TextDiff;
+use url::Url;
 
+use crate::asserter::backend::backend;
+use crate::parser::NumberOrString;
 use crate::parser::StringOrStrings;
+use crate::runner::RequestDetails;
 use crate::runner::RunnerResult;
+use crate::setup::database::any_db::AnyRow;
+use crate::setup::database::any_db::DbValue;
+use crate::setup::database::any_db::SqlRows;
 use crate::validator::Assertion;
+use crate::validator::ExpectedCookie;
+use crate::validator::HeaderAssertion;
+use crate::validator::HeaderMatch;
+use crate::validator::JsonPath;
+use crate::validator::SourceLocation;
+
+mod backend;
+
+pub use backend::AssertionBackend;
 
 pub struct Asserter {}
 
@@ -24,15 +49,103 @@ pub struct AssertResult {
     pub status: TestResult,
     pub expected: Assertion,
     pub actual: Actual,
+    /// Where the assertion was defined in the config.
+    pub location: Option<SourceLocation>,
+}
+
+/// A finished test as it is sent from the asserter to the outputter.
+#[derive(Debug, Clone)]
+pub struct TestOutcome {
+    pub name: String,
+    pub group: String,
+    pub path: String,
+    pub method: String,
+    pub results: Arc<[AssertResult]>,
+    pub duration: Duration,
+    pub location: Option<SourceLocation>,
+    pub url: Url,
+    pub request: RequestDetails,
+    pub ran_during: Range<Instant>,
+    /// Why the test wasn't run, if it was skipped. Skipped tests have no
+    /// results.
+    pub skipped: Option<String>,
 }
 
 #[derive(Debug, Clone)]
 pub enum Actual {
     Header(HeaderMap),
+    /// The trailers that followed the body, or `None` if they couldn't be
+    /// read, e.g. because the body failed to download.
+    Trailers(Option<HeaderMap>),
+    /// The value of the asserted header as an integer, or why there is none.
+    HeaderInt(Result<i64, String>),
+    /// The `Content-Encoding` of the response, and whether the body looked
+    /// encoded if that was checked.
+    ContentEncoding {
+        header: Option<String>,
+        body_encoded: Option<bool>,
+    },
     Status(reqwest::StatusCode),
-    Sql(Vec<String>),
+    /// The HTTP version the response came back with.
+    HttpVersion(reqwest::Version),
+    /// The rows an SQL query returned as lines, or why it failed.
+    Sql(Result<Vec<String>, String>),
+    /// The single value an SQL query returned, or why there is none to
+    /// compare.
+    SqlCompare(Result<String, String>),
     Json(serde_json::Value),
+    /// The cookie with the asserted name, if the response set it.
+    Cookie(Option<Cookie<'static>>),
+    Body(String),
+    /// The length of the array at the asserted path, or why there is none.
+    JsonArrayLen(Result<usize, String>),
+    /// The asserted paths that were missing or null, or why none could be
+    /// looked up.
+    JsonHas(Result<Vec<String>, String>),
+    /// The body compared to a captured one, or why they couldn't be compared.
+    JsonEqualsVar(Result<serde_json::Value, String>),
+    /// The first message a WebSocket test received, if any arrived in time.
+    WsMessage(Option<String>),
+    /// The two values of a JSON relation, or why they couldn't be found.
+    JsonRelation {
+        left: Result<serde_json::Value, String>,
+        right: Result<serde_json::Value, String>,
+    },
+    /// Whether the body parsed as JSON, with the parse error if it didn't.
+    ValidJson(Result<(), String>),
+    /// The length of the body in bytes.
+    BodySize(usize),
+    Snapshot(Snapshot),
+    /// Every URL the request went through, starting with its own.
+    Redirect(Vec<Url>),
+    /// The status of a test checked for server errors, with the body to show
+    /// what went wrong.
+    ServerError {
+        status: reqwest::StatusCode,
+        body: Option<String>,
+    },
     RequestFailed(String),
+    /// The test was aborted by its group's timeout.
+    TimedOut,
+}
+
+/// What comparing a body to its snapshot file found.
+#[derive(Debug, Clone)]
+pub enum Snapshot {
+    Matched,
+    /// The file was missing or `--update-snapshots` was given, so the body
+    /// was written to it.
+    Recorded,
+    Differs {
+        snapshot: String,
+        body: String,
+    },
+    /// The body was captured to `body_file`, which differs from the snapshot.
+    DiffersFromFile {
+        body_file: PathBuf,
+    },
+    /// The file couldn't be read or written.
+    Error(String),
 }
 
 impl Display for AssertResult {
@@ -59,6 +172,43 @@ impl Display for AssertResult {
                 )
             }
 
+            (TestResult::Fail, Assertion::NoServerError, Actual::ServerError { status, body }) => {
+                writeln!(
+                    f,
+                    "{} {}",
+                    console::style("✘").red().bold(),
+                    console::style("FAIL!").red().bold(),
+                )?;
+                writeln!(
+                    f,
+                    "  {}",
+                    console::style("Expected no server error, as fail_on_server_error is set")
+                        .green()
+                )?;
+                writeln!(
+                    f,
+                    "  {}",
+                    console::style(format!("Got status {status}")).red()
+                )?;
+                match body {
+                    Some(body) if !body.trim().is_empty() => {
+                        writeln!(f, "  {}", console::style(truncate_body(body)).red())
+                    }
+                    _ => Ok(()),
+                }
+            }
+
+            (TestResult::Fail, Assertion::HttpVersion(exp), Actual::HttpVersion(act)) => {
+                write!(
+                    f,
+                    "{} {}\n  Expected: {}\n  Actual:   {}",
+                    console::style("✘").red().bold(),
+                    console::style("FAIL!").red().bold(),
+                    console::style(format!("Expected {exp:?}")).green(),
+                    console::style(format!("Got {act:?}")).red(),
+                )
+            }
+
             (
                 TestResult::Fail,
                 Assertion::Headers(expected_headers),
@@ -70,12 +220,52 @@ impl Display for AssertResult {
                     console::style("✖").red().bold(),
                     console::style("FAIL!").red().bold(),
                 )?;
-                writeln!(f, "  {}", console::style("Expected headers:").green())?;
-                print_headers(f, expected_headers)?;
-                writeln!(f, "  {}", console::style("Actual headers:").red())?;
-                print_headers(f, actual_headers)
+                writeln!(
+                    f,
+                    "  {} {}",
+                    console::style("- Expected headers").green(),
+                    console::style("+ Actual headers").red()
+                )?;
+                print_header_diff(f, expected_headers, actual_headers)
+            }
+            (
+                TestResult::Fail,
+                Assertion::Trailers(expected_trailers),
+                Actual::Trailers(Some(actual_trailers)),
+            ) => {
+                writeln!(
+                    f,
+                    "{} {}",
+                    console::style("✖").red().bold(),
+                    console::style("FAIL!").red().bold(),
+                )?;
+                writeln!(
+                    f,
+                    "  {} {}",
+                    console::style("- Expected trailers").green(),
+                    console::style("+ Actual trailers").red()
+                )?;
+                print_header_diff(f, expected_trailers, actual_trailers)
+            }
+            (TestResult::Fail, Assertion::Trailers(_), actual @ Actual::Trailers(None)) => {
+                writeln!(
+                    f,
+                    "{} {}",
+                    console::style("✘").red().bold(),
+                    console::style("FAIL!").red().bold(),
+                )?;
+                writeln!(f, "  {}", console::style(actual).red())
             }
-            (TestResult::Fail, Assertion::Sql { query, expect, .. }, Actual::Sql(got)) => {
+            (
+                TestResult::Fail,
+                Assertion::Sql {
+                    query,
+                    expect,
+                    poll,
+                    ..
+                },
+                Actual::Sql(Ok(got)),
+            ) => {
                 writeln!(
                     f,
                     "{} {}",
@@ -84,6 +274,17 @@ impl Display for AssertResult {
                 )?;
                 writeln!(f, "  {}", console::style("SQL query:").yellow().bold())?;
                 writeln!(f, "    {}", console::style(query).dim())?;
+                if let Some(poll) = poll {
+                    writeln!(
+                        f,
+                        "  {}",
+                        console::style(format!(
+                            "Still didn't match after polling for {}ms",
+                            poll.timeout.as_millis()
+                        ))
+                        .yellow()
+                    )?;
+                }
                 writeln!(f, "  {}", console::style("Expected rows:").green().bold())?;
                 match expect {
                     StringOrStrings::Single(s) => {
@@ -135,29 +336,115 @@ impl Display for AssertResult {
                 }
             }
 
-            (TestResult::Fail, Assertion::Json(expected_json), Actual::Json(actual_json)) => {
+            (
+                TestResult::Fail,
+                Assertion::Json {
+                    expected: expected_json,
+                    ignore_keys,
+                    ..
+                },
+                Actual::Json(actual_json),
+            ) => {
                 writeln!(
                     f,
                     "{} {}",
                     console::style("✘").red().bold(),
                     console::style("FAIL!").red().bold(),
                 )?;
-                writeln!(f, "  {}", console::style("Expected JSON:").green())?;
                 writeln!(
                     f,
-                    "{}",
-                    console::style(serde_json::to_string_pretty(expected_json).unwrap_or_default())
-                        .green()
+                    "  {} {}",
+                    console::style("- Expected JSON").green(),
+                    console::style("+ Actual JSON").red()
+                )?;
+                print_json_diff(f, &without_keys(expected_json, ignore_keys), actual_json)
+            }
+            (
+                TestResult::Fail,
+                Assertion::JsonEqualsVar {
+                    var,
+                    captured: Some(captured),
+                },
+                Actual::JsonEqualsVar(Ok(actual_json)),
+            ) => {
+                writeln!(
+                    f,
+                    "{} {}",
+                    console::style("✘").red().bold(),
+                    console::style("FAIL!").red().bold(),
+                )?;
+                writeln!(
+                    f,
+                    "  {} {}",
+                    console::style(format!("- Captured `{var}`")).green(),
+                    console::style("+ Actual JSON").red()
+                )?;
+                print_json_diff(f, captured, actual_json)
+            }
+            (TestResult::Fail, Assertion::JsonEqualsVar { .. }, actual) => {
+                writeln!(
+                    f,
+                    "{} {}",
+                    console::style("✘").red().bold(),
+                    console::style("FAIL!").red().bold(),
+                )?;
+                writeln!(f, "  {}", console::style(actual).red())
+            }
+            (TestResult::Fail, Assertion::JsonAnyOf(alternatives), Actual::Json(actual_json)) => {
+                writeln!(
+                    f,
+                    "{} {}",
+                    console::style("✘").red().bold(),
+                    console::style("FAIL!").red().bold(),
+                )?;
+                writeln!(
+                    f,
+                    "  {}",
+                    console::style(format!(
+                        "Expected the body to equal one of {} alternatives:",
+                        alternatives.len()
+                    ))
+                    .green()
                 )?;
-                writeln!(f, "  {}", console::style("Actual JSON:").red())?;
+                for (i, alternative) in alternatives.iter().enumerate() {
+                    writeln!(
+                        f,
+                        "    {}",
+                        console::style(format!("{:>2}: {alternative}", i + 1)).green()
+                    )?;
+                }
                 writeln!(
                     f,
-                    "{}",
-                    console::style(serde_json::to_string_pretty(actual_json).unwrap_or_default())
-                        .red()
+                    "  {}",
+                    console::style(format!("Got {actual_json}")).red()
                 )
             }
-            (TestResult::Fail, _, Actual::RequestFailed(err)) => {
+            (TestResult::Fail, Assertion::Cookie(expected), Actual::Cookie(actual)) => {
+                writeln!(
+                    f,
+                    "{} {}",
+                    console::style("✘").red().bold(),
+                    console::style("FAIL!").red().bold(),
+                )?;
+                let Some(actual) = actual else {
+                    return writeln!(
+                        f,
+                        "  {}",
+                        console::style(format!("Cookie `{}` was not set", expected.name)).red()
+                    );
+                };
+                for (attribute, expected, actual) in cookie_mismatches(expected, actual) {
+                    writeln!(
+                        f,
+                        "  {}: {} {}",
+                        console::style(attribute).yellow().bold(),
+                        console::style(format!("expected {expected}")).green(),
+                        console::style(format!("got {actual}")).red(),
+                    )?;
+                }
+                Ok(())
+            }
+            (TestResult::Fail, Assertion::ValidJson, Actual::ValidJson(Err(error))) => {
                 writeln!(
                     f,
                     "{} {}",
@@ -167,132 +454,652 @@ impl Display for AssertResult {
                 writeln!(
                     f,
                     "  {} {}",
-                    console::style("Request failed with error:").red(),
-                    console::style(err).red().bold()
+                    console::style("Expected a JSON body, but it failed to parse:").green(),
+                    console::style(error).red()
                 )
             }
-
-            _ => {
+            (
+                TestResult::Fail,
+                Assertion::Snapshot { path, .. },
+                Actual::Snapshot(Snapshot::Differs { snapshot, body }),
+            ) => {
                 writeln!(
                     f,
-                    "{} {} (unhandled combination)",
-                    console::style("⚠").yellow(),
-                    console::style("UNKNOWN RESULT").yellow().bold()
+                    "{} {}",
+                    console::style("✘").red().bold(),
+                    console::style("FAIL!").red().bold(),
+                )?;
+                writeln!(
+                    f,
+                    "  {} {}",
+                    console::style("Body differs from snapshot").yellow().bold(),
+                    console::style(path.display()).dim(),
+                )?;
+                writeln!(
+                    f,
+                    "  {} / {}",
+                    console::style("- Snapshot").green(),
+                    console::style("+ Actual body").red()
+                )?;
+                print_text_diff(f, snapshot, body)
+            }
+            (
+                TestResult::Fail,
+                Assertion::Snapshot { path, .. },
+                Actual::Snapshot(Snapshot::DiffersFromFile { body_file }),
+            ) => {
+                writeln!(
+                    f,
+                    "{} {}",
+                    console::style("✘").red().bold(),
+                    console::style("FAIL!").red().bold(),
+                )?;
+                writeln!(
+                    f,
+                    "  {} {}",
+                    console::style("Body differs from snapshot").yellow().bold(),
+                    console::style(path.display()).dim(),
+                )?;
+                writeln!(
+                    f,
+                    "  {}",
+                    console::style(format!("The body was saved to {}", body_file.display())).red()
                 )
             }
-        }
-    }
-}
-
-fn print_headers(f: &mut fmt::Formatter<'_>, headers: &HeaderMap) -> fmt::Result {
-    for (k, v) in headers.iter() {
-        let value = v.to_str().unwrap_or("<invalid utf8>");
-        writeln!(
-            f,
-            "    {}: {}",
-            console::style(k.as_str()).yellow().bold(),
-            console::style(value)
-        )?;
-    }
-    Ok(())
-}
-
-impl Display for Assertion {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Assertion::Status(_) => write!(f, "Status test"),
-            Assertion::Headers(_) => {
-                write!(f, "Header test")
+            (
+                TestResult::Fail,
+                Assertion::Snapshot { path, .. },
+                Actual::Snapshot(Snapshot::Error(error)),
+            ) => {
+                writeln!(
+                    f,
+                    "{} {}",
+                    console::style("✘").red().bold(),
+                    console::style("FAIL!").red().bold(),
+                )?;
+                writeln!(
+                    f,
+                    "  {}",
+                    console::style(format!("Snapshot {} failed: {error}", path.display())).red()
+                )
             }
-            Assertion::Sql { .. } => write!(f, "SQL test"),
-            Assertion::Json(..) => write!(f, "JSON test"),
-            Assertion::RequestFailed => write!(f, "Request failed"),
-        }
-    }
-}
-
-impl Display for Actual {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Actual::Header(header_map) => {
-                let headers: Vec<String> = header_map
-                    .iter()
-                    .map(|(k, v)| format!("{}: {}", k, v.to_str().unwrap_or("<invalid utf8>")))
-                    .collect();
-                write!(f, "Got headers {{{}}}", headers.join(", "))
+            (TestResult::Fail, Assertion::Redirect { url, hops, .. }, Actual::Redirect(chain)) => {
+                writeln!(
+                    f,
+                    "{} {}",
+                    console::style("✘").red().bold(),
+                    console::style("FAIL!").red().bold(),
+                )?;
+                let expected = match (url, hops) {
+                    (Some(url), Some(hops)) => format!("to end at {url} after {hops} redirects"),
+                    (Some(url), None) => format!("to end at {url}"),
+                    (None, Some(hops)) => format!("{hops} redirects"),
+                    (None, None) => "a redirect".to_string(),
+                };
+                writeln!(
+                    f,
+                    "  {}",
+                    console::style(format!("Expected {expected}")).green()
+                )?;
+                writeln!(
+                    f,
+                    "  {}",
+                    console::style(format!(
+                        "Got {} redirects: {}",
+                        chain.len().saturating_sub(1),
+                        redirect_chain(chain)
+                    ))
+                    .red()
+                )
             }
-            Actual::Status(status_code) => write!(f, "Got status {}", status_code),
-            Actual::Sql(sqls) => {
-                if sqls.len() == 1 {
-                    write!(f, "Got response from database: {}", sqls[0])
-                } else {
-                    write!(f, "Got responses from database: [{}]", sqls.join(", "))
-                }
+            (
+                TestResult::Fail,
+                Assertion::SqlCompare {
+                    query, op, value, ..
+                },
+                actual @ Actual::SqlCompare(_),
+            ) => {
+                writeln!(
+                    f,
+                    "{} {}",
+                    console::style("✘").red().bold(),
+                    console::style("FAIL!").red().bold(),
+                )?;
+                writeln!(f, "  {}", console::style("SQL query:").yellow().bold())?;
+                writeln!(f, "    {}", console::style(query).dim())?;
+                writeln!(
+                    f,
+                    "  {}",
+                    console::style(format!("Expected value {op} {value}")).green()
+                )?;
+                writeln!(f, "  {}", console::style(actual).red())
             }
-            Actual::Json(value) => write!(f, "Got json: {value}"),
-            Actual::RequestFailed(_) => write!(f, "Request failed"),
-        }
-    }
-}
-
-pub trait Assert {
-    fn assert(&self) -> Arc<[AssertResult]>;
-}
-
-impl Assert for RunnerResult {
-    fn assert(&self) -> Arc<[AssertResult]> {
-        if let Some(error) = &self.error {
-            return Arc::from([AssertResult {
-                status: TestResult::Fail,
-                expected: Assertion::RequestFailed,
-                actual: Actual::RequestFailed(error.to_string()),
-            }]);
-        }
-
-        let Some(response) = &self.response else {
-            return Arc::from([AssertResult {
-                status: TestResult::Fail,
-                expected: Assertion::RequestFailed,
-                actual: Actual::RequestFailed(self.error.clone().unwrap_or_default()),
-            }]);
-        };
-
-        Arc::from(
-            self.assertions
-                .iter()
-                .map(|a| {
-                    let result = match a {
-                        Assertion::Status(expected_status) => {
-                            assert_status(expected_status, response.status)
-                        }
-                        Assertion::Headers(expected_headermap) => {
-                            assert_header(expected_headermap, &response.headers)
-                        }
-                        Assertion::Sql { expect, got, .. } => assert_sql(expect, got.as_ref()),
-                        Assertion::Json(expected_json) => {
-                            assert_json(expected_json, response.body_json.as_ref())
-                        }
-                        Assertion::RequestFailed => todo!(),
-                    };
-
-                    AssertResult {
-                        status: result,
-                        expected: a.clone(),
-                        actual: match a {
-                            Assertion::Status(_) => Actual::Status(response.status),
-                            Assertion::Headers(_) => Actual::Header(response.headers.clone()),
-                            Assertion::Sql { got, .. } => {
-                                if let Some(g) = got {
-                                    Actual::Sql(g.clone())
-                                } else {
-                                    Actual::Sql(vec![])
-                                }
-                            }
-                            Assertion::Json(_) => {
-                                Actual::Json(response.body_json.clone().unwrap_or_default())
-                            }
-                            Assertion::RequestFailed => todo!(),
-                        },
+            (
+                TestResult::Fail,
+                Assertion::HeaderInt { name, op, value },
+                Actual::HeaderInt(actual),
+            ) => {
+                writeln!(
+                    f,
+                    "{} {}",
+                    console::style("✘").red().bold(),
+                    console::style("FAIL!").red().bold(),
+                )?;
+                writeln!(
+                    f,
+                    "  {}",
+                    console::style(format!("Expected {name} {op} {value}")).green()
+                )?;
+                let got = match actual {
+                    Ok(actual) => format!("Got {name}: {actual}"),
+                    Err(error) => error.clone(),
+                };
+                writeln!(f, "  {}", console::style(got).red())
+            }
+            (
+                TestResult::Fail,
+                Assertion::ContentEncoding { encoding, .. },
+                actual @ Actual::ContentEncoding { .. },
+            ) => {
+                writeln!(
+                    f,
+                    "{} {}",
+                    console::style("✘").red().bold(),
+                    console::style("FAIL!").red().bold(),
+                )?;
+                writeln!(
+                    f,
+                    "  {}",
+                    console::style(format!("Expected a {encoding} compressed response")).green()
+                )?;
+                writeln!(f, "  {}", console::style(actual).red())
+            }
+            (TestResult::Fail, Assertion::BodySize(expected), Actual::BodySize(bytes)) => {
+                writeln!(
+                    f,
+                    "{} {}",
+                    console::style("✘").red().bold(),
+                    console::style("FAIL!").red().bold(),
+                )?;
+                writeln!(
+                    f,
+                    "  {}",
+                    console::style(format!("Expected a body of {expected}")).green()
+                )?;
+                writeln!(
+                    f,
+                    "  {}",
+                    console::style(format!("Got a body of {bytes} bytes")).red()
+                )
+            }
+            (TestResult::Fail, Assertion::EmptyBody, Actual::Body(body)) => {
+                writeln!(
+                    f,
+                    "{} {}",
+                    console::style("✘").red().bold(),
+                    console::style("FAIL!").red().bold(),
+                )?;
+                writeln!(
+                    f,
+                    "  {}",
+                    console::style("Expected an empty body, got:").green()
+                )?;
+                writeln!(f, "    {}", console::style(truncate_body(body)).red())
+            }
+            (
+                TestResult::Fail,
+                Assertion::JsonRelation { left, op, right },
+                Actual::JsonRelation {
+                    left: left_value,
+                    right: right_value,
+                },
+            ) => {
+                writeln!(
+                    f,
+                    "{} {}",
+                    console::style("✘").red().bold(),
+                    console::style("FAIL!").red().bold(),
+                )?;
+                writeln!(
+                    f,
+                    "  {}",
+                    console::style(format!("Expected {left} {op} {right}")).green()
+                )?;
+                for (operand, value) in [(left, left_value), (right, right_value)] {
+                    let got = match value {
+                        Ok(value) => format!("{operand} = {value}"),
+                        Err(error) => error.clone(),
+                    };
+                    writeln!(f, "  {}", console::style(got).red())?;
+                }
+                Ok(())
+            }
+            (
+                TestResult::Fail,
+                Assertion::JsonArrayLen { path, expected },
+                Actual::JsonArrayLen(actual),
+            ) => {
+                writeln!(
+                    f,
+                    "{} {}",
+                    console::style("✘").red().bold(),
+                    console::style("FAIL!").red().bold(),
+                )?;
+                writeln!(
+                    f,
+                    "  {}",
+                    console::style(format!("Expected {path} to have {expected} elements")).green()
+                )?;
+                let actual = match actual {
+                    Ok(len) => format!("Got {len} elements"),
+                    Err(error) => error.clone(),
+                };
+                writeln!(f, "  {}", console::style(actual).red())
+            }
+            (TestResult::Fail, Assertion::JsonHas(paths), Actual::JsonHas(actual)) => {
+                writeln!(
+                    f,
+                    "{} {}",
+                    console::style("✘").red().bold(),
+                    console::style("FAIL!").red().bold(),
+                )?;
+                let paths: Vec<String> = paths.iter().map(ToString::to_string).collect();
+                writeln!(
+                    f,
+                    "  {}",
+                    console::style(format!("Expected a value at {}", paths.join(", "))).green()
+                )?;
+                let actual = match actual {
+                    Ok(missing) => format!("Missing or null: {}", missing.join(", ")),
+                    Err(error) => error.clone(),
+                };
+                writeln!(f, "  {}", console::style(actual).red())
+            }
+            (TestResult::Fail, Assertion::WsMessage(expected), Actual::WsMessage(actual)) => {
+                writeln!(
+                    f,
+                    "{} {}",
+                    console::style("✘").red().bold(),
+                    console::style("FAIL!").red().bold(),
+                )?;
+                writeln!(
+                    f,
+                    "  {}",
+                    console::style(format!("Expected a message {expected}")).green()
+                )?;
+                let actual = match actual {
+                    Some(message) => format!("Got `{message}`"),
+                    None => "No message was received before the timeout".to_string(),
+                };
+                writeln!(f, "  {}", console::style(actual).red())
+            }
+            (TestResult::Fail, Assertion::RequestError(expected), actual) => {
+                writeln!(
+                    f,
+                    "{} {}",
+                    console::style("✘").red().bold(),
+                    console::style("FAIL!").red().bold(),
+                )?;
+                writeln!(
+                    f,
+                    "  {}",
+                    console::style(format!(
+                        "Expected the request to fail with an error {expected}"
+                    ))
+                    .green()
+                )?;
+                let actual = match actual {
+                    Actual::RequestFailed(err) => format!("Got error: {err}"),
+                    Actual::Status(status) => format!("Got a response with status {status}"),
+                    actual => actual.to_string(),
+                };
+                writeln!(f, "  {}", console::style(actual).red())
+            }
+            (TestResult::Fail, Assertion::GroupTimeout(timeout), Actual::TimedOut) => {
+                writeln!(
+                    f,
+                    "{} {}",
+                    console::style("⏱").red().bold(),
+                    console::style("TIMED OUT!").red().bold(),
+                )?;
+                writeln!(
+                    f,
+                    "  {}",
+                    console::style(format!(
+                        "Aborted: the group ran past its timeout of {}s",
+                        timeout.as_secs()
+                    ))
+                    .red()
+                )
+            }
+            (TestResult::Fail, _, Actual::RequestFailed(err)) => {
+                writeln!(
+                    f,
+                    "{} {}",
+                    console::style("✘").red().bold(),
+                    console::style("FAIL!").red().bold(),
+                )?;
+                writeln!(
+                    f,
+                    "  {} {}",
+                    console::style("Request failed with error:").red(),
+                    console::style(err).red().bold()
+                )
+            }
+
+            _ => {
+                writeln!(
+                    f,
+                    "{} {} (unhandled combination)",
+                    console::style("⚠").yellow(),
+                    console::style("UNKNOWN RESULT").yellow().bold()
+                )
+            }
+        }
+    }
+}
+
+/// Prints only the headers that differ: expected headers with a different
+/// value or missing from the response, followed by response headers that
+/// weren't expected, dimmed since they don't fail the assertion.
+fn print_header_diff(
+    f: &mut fmt::Formatter<'_>,
+    expected: &[HeaderAssertion],
+    actual: &HeaderMap,
+) -> fmt::Result {
+    for assertion in expected {
+        let name = &assertion.name;
+        let actual_value = if actual.contains_key(name) {
+            if header_matches(assertion, actual) {
+                continue;
+            }
+            header_values(actual, name)
+        } else {
+            "<missing>".into()
+        };
+
+        let line = format!("- {assertion}");
+        writeln!(f, "  {}", console::style(line).green())?;
+        let line = format!("+ {name}: {actual_value}");
+        writeln!(f, "  {}", console::style(line).red())?;
+    }
+
+    let is_expected = |name: &HeaderName| expected.iter().any(|a| a.name == name);
+    for name in actual.keys().filter(|name| !is_expected(name)) {
+        let line = format!("+ {name}: {}", header_values(actual, name));
+        writeln!(f, "  {}", console::style(line).dim())?;
+    }
+
+    Ok(())
+}
+
+/// All values of the header `name`, joined the way they would be folded into
+/// a single header line.
+fn header_values(headers: &HeaderMap, name: &HeaderName) -> String {
+    headers
+        .get_all(name)
+        .iter()
+        .map(|v| v.to_str().unwrap_or("<invalid utf8>"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Prints a line based diff of the pretty printed JSON documents, only showing
+/// the changed lines and a few lines of context around them.
+fn print_json_diff(
+    f: &mut fmt::Formatter<'_>,
+    expected: &serde_json::Value,
+    actual: &serde_json::Value,
+) -> fmt::Result {
+    let expected = serde_json::to_string_pretty(expected).unwrap_or_default();
+    let actual = serde_json::to_string_pretty(actual).unwrap_or_default();
+    print_text_diff(f, &expected, &actual)
+}
+
+fn print_text_diff(f: &mut fmt::Formatter<'_>, expected: &str, actual: &str) -> fmt::Result {
+    let diff = TextDiff::from_lines(expected, actual);
+
+    for hunk in diff.unified_diff().context_radius(3).iter_hunks() {
+        writeln!(f, "  {}", console::style(hunk.header()).cyan())?;
+        for change in hunk.iter_changes() {
+            let line = change.value().trim_end_matches('\n');
+            match change.tag() {
+                ChangeTag::Delete => {
+                    writeln!(f, "  {}", console::style(format!("- {line}")).green())?
+                }
+                ChangeTag::Insert => {
+                    writeln!(f, "  {}", console::style(format!("+ {line}")).red())?
+                }
+                ChangeTag::Equal => writeln!(f, "  {}", console::style(format!("  {line}")).dim())?,
+            }
+        }
+    }
+
+    Ok(())
+}
+
+impl Display for Assertion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Assertion::Status(_) => write!(f, "Status test"),
+            Assertion::Headers(_) => {
+                write!(f, "Header test")
+            }
+            Assertion::Trailers(_) => write!(f, "Trailer test"),
+            Assertion::HeaderInt { .. } => write!(f, "Header integer test"),
+            Assertion::HttpVersion(_) => write!(f, "HTTP version test"),
+            Assertion::ContentEncoding { .. } => write!(f, "Content encoding test"),
+            Assertion::Sql { .. } => write!(f, "SQL test"),
+            Assertion::SqlCompare { .. } => write!(f, "SQL comparison test"),
+            Assertion::Json { .. } => write!(f, "JSON test"),
+            Assertion::JsonAnyOf(_) => write!(f, "JSON any-of test"),
+            Assertion::Cookie(_) => write!(f, "Cookie test"),
+            Assertion::EmptyBody => write!(f, "Empty body test"),
+            Assertion::ValidJson => write!(f, "Valid JSON test"),
+            Assertion::BodySize(_) => write!(f, "Body size test"),
+            Assertion::Snapshot { .. } => write!(f, "Snapshot test"),
+            Assertion::Redirect { .. } => write!(f, "Redirect test"),
+            Assertion::JsonArrayLen { .. } => write!(f, "JSON length test"),
+            Assertion::JsonHas(_) => write!(f, "JSON presence test"),
+            Assertion::JsonEqualsVar { .. } => write!(f, "Captured JSON test"),
+            Assertion::JsonRelation { .. } => write!(f, "JSON relation test"),
+            Assertion::RequestError(_) => write!(f, "Request error test"),
+            Assertion::WsMessage(_) => write!(f, "WebSocket message test"),
+            Assertion::NoServerError => write!(f, "Server error test"),
+            Assertion::RequestFailed => write!(f, "Request failed"),
+            Assertion::GroupTimeout(_) => write!(f, "Group timeout"),
+        }
+    }
+}
+
+impl Display for HeaderAssertion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.mode {
+            HeaderMatch::Exact => write!(f, "{}: {}", self.name, self.value)?,
+            HeaderMatch::Contains => write!(f, "{}: contains {:?}", self.name, self.value)?,
+            HeaderMatch::Regex(_) => write!(f, "{}: matches /{}/", self.name, self.value)?,
+        }
+        if self.ignore_case {
+            write!(f, " (ignoring case)")?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Actual {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Actual::Header(header_map) => {
+                let headers: Vec<String> = header_map
+                    .iter()
+                    .map(|(k, v)| format!("{}: {}", k, v.to_str().unwrap_or("<invalid utf8>")))
+                    .collect();
+                write!(f, "Got headers {{{}}}", headers.join(", "))
+            }
+            Actual::Trailers(Some(trailers)) if trailers.is_empty() => {
+                write!(f, "Got no trailers")
+            }
+            Actual::Trailers(Some(trailers)) => {
+                let trailers: Vec<String> = trailers
+                    .iter()
+                    .map(|(k, v)| format!("{}: {}", k, v.to_str().unwrap_or("<invalid utf8>")))
+                    .collect();
+                write!(f, "Got trailers {{{}}}", trailers.join(", "))
+            }
+            Actual::Trailers(None) => write!(f, "The response's trailers couldn't be read"),
+            Actual::HeaderInt(Ok(value)) => write!(f, "Got {value}"),
+            Actual::HeaderInt(Err(error)) => write!(f, "{error}"),
+            Actual::SqlCompare(Ok(value)) => write!(f, "Got {value}"),
+            Actual::SqlCompare(Err(error)) => write!(f, "{error}"),
+            Actual::ContentEncoding { header: None, .. } => {
+                write!(f, "Got no Content-Encoding header")
+            }
+            Actual::ContentEncoding {
+                header: Some(header),
+                body_encoded: Some(false),
+            } => write!(
+                f,
+                "Got Content-Encoding: {header}, but the body isn't {header} data"
+            ),
+            Actual::ContentEncoding {
+                header: Some(header),
+                ..
+            } => write!(f, "Got Content-Encoding: {header}"),
+            Actual::Status(status_code) => write!(f, "Got status {}", status_code),
+            Actual::HttpVersion(version) => write!(f, "Got {version:?}"),
+            Actual::Sql(Err(error)) => write!(f, "The query failed: {error}"),
+            Actual::Sql(Ok(sqls)) => {
+                if sqls.len() == 1 {
+                    write!(f, "Got response from database: {}", sqls[0])
+                } else {
+                    write!(f, "Got responses from database: [{}]", sqls.join(", "))
+                }
+            }
+            Actual::Json(value) => write!(f, "Got json: {value}"),
+            Actual::Cookie(Some(cookie)) => write!(f, "Got cookie {cookie}"),
+            Actual::Cookie(None) => write!(f, "Cookie not set"),
+            Actual::Body(body) if body.trim().is_empty() => write!(f, "Got an empty body"),
+            Actual::Body(body) => write!(f, "Got body: {}", truncate_body(body)),
+            Actual::JsonArrayLen(Ok(len)) => write!(f, "Got an array of {len} elements"),
+            Actual::JsonArrayLen(Err(error)) => write!(f, "{error}"),
+            Actual::JsonHas(Ok(missing)) if missing.is_empty() => {
+                write!(f, "Got a value at every path")
+            }
+            Actual::JsonHas(Ok(missing)) => write!(f, "Missing {}", missing.join(", ")),
+            Actual::JsonHas(Err(error)) => write!(f, "{error}"),
+            Actual::JsonEqualsVar(Ok(value)) => write!(f, "Got json: {value}"),
+            Actual::JsonEqualsVar(Err(error)) => write!(f, "{error}"),
+            Actual::WsMessage(Some(message)) => write!(f, "Got message {message}"),
+            Actual::WsMessage(None) => write!(f, "No message was received"),
+            Actual::JsonRelation { left, right } => {
+                let show = |value: &Result<serde_json::Value, String>| match value {
+                    Ok(value) => value.to_string(),
+                    Err(error) => error.clone(),
+                };
+                write!(f, "Got {} and {}", show(left), show(right))
+            }
+            Actual::ValidJson(Ok(())) => write!(f, "Got valid JSON"),
+            Actual::ValidJson(Err(error)) => write!(f, "Got invalid JSON: {error}"),
+            Actual::BodySize(bytes) => write!(f, "Got a body of {bytes} bytes"),
+            Actual::Snapshot(Snapshot::Matched) => write!(f, "Body matches the snapshot"),
+            Actual::Snapshot(Snapshot::Recorded) => write!(f, "Recorded the body as the snapshot"),
+            Actual::Snapshot(Snapshot::Differs { .. }) => {
+                write!(f, "Body differs from the snapshot")
+            }
+            Actual::Snapshot(Snapshot::DiffersFromFile { body_file }) => write!(
+                f,
+                "Body in {} differs from the snapshot",
+                body_file.display()
+            ),
+            Actual::Snapshot(Snapshot::Error(error)) => write!(f, "{error}"),
+            Actual::Redirect(chain) => write!(
+                f,
+                "Got {} redirects: {}",
+                chain.len().saturating_sub(1),
+                redirect_chain(chain)
+            ),
+            Actual::ServerError { status, .. } => write!(f, "Got status {status}"),
+            Actual::RequestFailed(_) => write!(f, "Request failed"),
+            Actual::TimedOut => write!(f, "Aborted by the group timeout"),
+        }
+    }
+}
+
+pub trait Assert {
+    fn assert(&self) -> Arc<[AssertResult]>;
+}
+
+impl Assert for RunnerResult {
+    fn assert(&self) -> Arc<[AssertResult]> {
+        self.verdict.get_or_init(|| self.evaluate()).clone()
+    }
+}
+
+impl RunnerResult {
+    fn evaluate(&self) -> Arc<[AssertResult]> {
+        if self.skipped.is_some() {
+            return Arc::from([]);
+        }
+
+        if let Some(timeout) = self.timed_out {
+            return Arc::from([AssertResult {
+                status: TestResult::Fail,
+                expected: Assertion::GroupTimeout(timeout),
+                actual: Actual::TimedOut,
+                location: self.location.clone(),
+            }]);
+        }
+
+        if let Some(error) = &self.error {
+            let expected = self
+                .assertions
+                .iter()
+                .position(|a| matches!(a, Assertion::RequestError(_)));
+            let Some(idx) = expected else {
+                return Arc::from([AssertResult {
+                    status: TestResult::Fail,
+                    expected: Assertion::RequestFailed,
+                    actual: Actual::RequestFailed(error.to_string()),
+                    location: self.location.clone(),
+                }]);
+            };
+
+            let expected = self.assertions[idx].clone();
+            let status = match &expected {
+                Assertion::RequestError(expected_error) if expected_error.matches(error) => {
+                    TestResult::Pass
+                }
+                _ => TestResult::Fail,
+            };
+            return Arc::from([AssertResult {
+                status,
+                expected,
+                actual: Actual::RequestFailed(error.to_string()),
+                location: self.assertion_locations.get(idx).cloned().flatten(),
+            }]);
+        }
+
+        let Some(response) = &self.response else {
+            return Arc::from([AssertResult {
+                status: TestResult::Fail,
+                expected: Assertion::RequestFailed,
+                actual: Actual::RequestFailed(self.error.clone().unwrap_or_default()),
+                location: self.location.clone(),
+            }]);
+        };
+
+        Arc::from(
+            self.assertions
+                .iter()
+                .enumerate()
+                .map(|(idx, a)| {
+                    let (status, actual) = backend(a).evaluate(response);
+
+                    AssertResult {
+                        status,
+                        expected: a.clone(),
+                        actual,
+                        location: self.assertion_locations.get(idx).cloned().flatten(),
                     }
                 })
                 .collect::<Vec<AssertResult>>(),
@@ -301,19 +1108,34 @@ impl Assert for RunnerResult {
 }
 
 impl Asserter {
-    pub async fn run(
-        rx: Receiver<RunnerResult>,
-        output_tx: Sender<(String, String, String, Arc<[AssertResult]>)>,
-    ) -> Result<(), ()> {
+    pub async fn run(rx: Receiver<RunnerResult>, output_tx: Sender<TestOutcome>) -> Result<(), ()> {
         while let Ok(msg) = rx.recv_async().await {
             let assert_result = msg.assert();
 
+            // Bodies streamed to file are only kept for tests that failed.
+            if let Some(file) = msg.response.as_ref().and_then(|r| r.body_file.as_ref())
+                && assert_result.iter().all(|r| r.status == TestResult::Pass)
+            {
+                let _ = std::fs::remove_file(&file.path);
+            }
+
             let path = msg.url.path();
             let method = msg.method;
-            if let Err(error) = output_tx
-                .send_async((msg.name, path.into(), method, assert_result))
-                .await
-            {
+            let outcome = TestOutcome {
+                name: msg.name,
+                group: msg.group,
+                path: path.into(),
+                method,
+                results: assert_result,
+                duration: msg.duration,
+                location: msg.location,
+                url: msg.url.clone(),
+                request: msg.request,
+                ran_during: msg.ran_during,
+                skipped: msg.skipped,
+            };
+
+            if let Err(error) = output_tx.send_async(outcome).await {
                 todo!("{error}")
             };
         }
@@ -322,10 +1144,14 @@ impl Asserter {
     }
 }
 
-fn assert_json(expected: &serde_json::Value, got: Option<&serde_json::Value>) -> TestResult {
+fn assert_json(
+    expected: &serde_json::Value,
+    got: Option<&serde_json::Value>,
+    epsilon: Option<f64>,
+) -> TestResult {
     match got {
         Some(got) => {
-            if got == expected {
+            if json_matches(expected, got, epsilon) {
                 TestResult::Pass
             } else {
                 TestResult::Fail
@@ -335,52 +1161,198 @@ fn assert_json(expected: &serde_json::Value, got: Option<&serde_json::Value>) ->
     }
 }
 
-fn assert_sql(expect: &StringOrStrings, got: Option<&Vec<String>>) -> TestResult {
-    match expect {
-        StringOrStrings::Single(expected) => {
-            let Some(got) = got else {
-                return TestResult::Fail;
-            };
+/// `value` without the keys in `ignore_keys`. A plain key is removed from
+/// every object in `value`, a dotted path like `user.id` only from the object
+/// it leads to. Arrays on the way are looked into element by element.
+pub(crate) fn without_keys(value: &serde_json::Value, ignore_keys: &[String]) -> serde_json::Value {
+    let mut value = value.clone();
+    for key in ignore_keys {
+        let path: Vec<&str> = key.split('.').collect();
+        match path.as_slice() {
+            [key] => remove_key_everywhere(&mut value, key),
+            path => remove_path(&mut value, path),
+        }
+    }
+    value
+}
 
-            if expected.is_empty() && got.is_empty() {
-                return TestResult::Pass;
+fn remove_key_everywhere(value: &mut serde_json::Value, key: &str) {
+    match value {
+        serde_json::Value::Object(map) => {
+            map.remove(key);
+            for value in map.values_mut() {
+                remove_key_everywhere(value, key);
             }
+        }
+        serde_json::Value::Array(values) => {
+            for value in values {
+                remove_key_everywhere(value, key);
+            }
+        }
+        _ => {}
+    }
+}
 
-            if got.len() != 1 {
-                return TestResult::Fail;
+fn remove_path(value: &mut serde_json::Value, path: &[&str]) {
+    match (value, path) {
+        (serde_json::Value::Array(values), path) => {
+            for value in values {
+                remove_path(value, path);
             }
+        }
+        (serde_json::Value::Object(map), [key]) => {
+            map.remove(*key);
+        }
+        (serde_json::Value::Object(map), [key, rest @ ..]) => {
+            if let Some(value) = map.get_mut(*key) {
+                remove_path(value, rest);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Compares two JSON documents. With an `epsilon`, numbers anywhere in them
+/// match if they are at most `epsilon` apart, otherwise they have to be equal.
+fn json_matches(
+    expected: &serde_json::Value,
+    got: &serde_json::Value,
+    epsilon: Option<f64>,
+) -> bool {
+    let Some(epsilon) = epsilon else {
+        return expected == got;
+    };
 
-            if got[0] != *expected {
-                return TestResult::Fail;
+    match (expected, got) {
+        (serde_json::Value::Number(expected), serde_json::Value::Number(got)) => {
+            match (expected.as_f64(), got.as_f64()) {
+                (Some(expected), Some(got)) => (expected - got).abs() <= epsilon,
+                _ => expected == got,
             }
         }
+        (serde_json::Value::Array(expected), serde_json::Value::Array(got)) => {
+            expected.len() == got.len()
+                && expected
+                    .iter()
+                    .zip(got)
+                    .all(|(expected, got)| json_matches(expected, got, Some(epsilon)))
+        }
+        (serde_json::Value::Object(expected), serde_json::Value::Object(got)) => {
+            expected.len() == got.len()
+                && expected.iter().all(|(key, expected)| {
+                    got.get(key)
+                        .is_some_and(|got| json_matches(expected, got, Some(epsilon)))
+                })
+        }
+        _ => expected == got,
+    }
+}
 
-        StringOrStrings::Multiple(expected_items) => {
-            let Some(got) = got else {
-                return TestResult::Fail;
-            };
+pub(crate) fn assert_sql(
+    expect: &StringOrStrings,
+    got: Option<&SqlRows>,
+    epsilon: Option<f64>,
+) -> TestResult {
+    let Some(got) = got else {
+        return TestResult::Fail;
+    };
+
+    let expected = match expect {
+        StringOrStrings::Single(expected)
+            if expected.is_empty() && got.rows.is_empty() && !got.truncated =>
+        {
+            return TestResult::Pass;
+        }
+        StringOrStrings::Single(expected) => std::slice::from_ref(expected),
+        StringOrStrings::Multiple(expected_items) => expected_items.as_slice(),
+    };
+
+    if got.truncated || got.rows.len() != expected.len() {
+        return TestResult::Fail;
+    }
 
-            if got.len() != expected_items.len() {
-                return TestResult::Fail;
+    let matches = expected
+        .iter()
+        .zip(&got.rows)
+        .all(|(expected, row)| sql_row_matches(expected, row, epsilon));
+    if !matches {
+        return TestResult::Fail;
+    }
+
+    TestResult::Pass
+}
+
+/// Compares an expected row with the one the database returned. Spaces around
+/// the elements of an array are ignored, so `{1, 2}` matches `{1,2}`. With an
+/// `epsilon`, columns the database returned as numbers match an expected
+/// number at most `epsilon` away.
+fn sql_row_matches(expected: &str, row: &AnyRow, epsilon: Option<f64>) -> bool {
+    let actual = row.to_csv_line();
+
+    *expected == actual
+        || strip_array_spaces(expected) == actual
+        || epsilon.is_some_and(|epsilon| sql_numbers_match(expected, &row.values, epsilon))
+}
+
+fn sql_numbers_match(expected: &str, values: &[DbValue], epsilon: f64) -> bool {
+    let expected: Vec<&str> = expected.split(',').map(str::trim).collect();
+
+    expected.len() == values.len()
+        && expected.iter().zip(values).all(|(expected, value)| {
+            match (expected.parse::<f64>(), value.as_f64()) {
+                (Ok(expected), Some(actual)) => (expected - actual).abs() <= epsilon,
+                _ => *expected == value.to_string(),
             }
+        })
+}
 
-            for (expected, actual) in expected_items.iter().zip(got.iter()) {
-                if expected != actual {
-                    return TestResult::Fail;
-                }
+/// How the value an SQL query returned compares to `value`: as numbers if
+/// `value` is one, as text otherwise.
+pub(crate) fn compare_sql_value(
+    got: &str,
+    value: &NumberOrString,
+) -> Result<std::cmp::Ordering, String> {
+    match value {
+        NumberOrString::Number(value) => got
+            .parse::<f64>()
+            .ok()
+            .and_then(|got| got.partial_cmp(value))
+            .ok_or_else(|| format!("Got `{got}`, which can't be compared to the number {value}")),
+        NumberOrString::String(value) => Ok(got.cmp(value.as_str())),
+    }
+}
+
+fn strip_array_spaces(line: &str) -> String {
+    let mut stripped = String::with_capacity(line.len());
+    let mut depth = 0;
+    let mut quoted = false;
+    let mut escaped = false;
+
+    for c in line.chars() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' if depth > 0 => quoted = !quoted,
+            '{' if !quoted => depth += 1,
+            '}' if !quoted && depth > 0 => {
+                depth -= 1;
+                stripped.truncate(stripped.trim_end_matches(' ').len());
             }
+            ' ' if depth > 0 && !quoted && stripped.ends_with(['{', ',']) => continue,
+            ',' if depth > 0 && !quoted => {
+                stripped.truncate(stripped.trim_end_matches(' ').len());
+            }
+            _ => {}
         }
+        stripped.push(c);
     }
 
-    TestResult::Pass
+    stripped
 }
 
-fn assert_header(expected: &HeaderMap, actual: &HeaderMap) -> TestResult {
-    for (key, value_a) in expected {
-        let Some(value_b) = actual.get(key) else {
-            continue;
-        };
-        if value_a.as_bytes() != value_b.as_bytes() {
+fn assert_header(expected: &[HeaderAssertion], actual: &HeaderMap) -> TestResult {
+    for assertion in expected {
+        if !header_matches(assertion, actual) {
             return TestResult::Fail;
         }
     }
@@ -388,59 +1360,595 @@ fn assert_header(expected: &HeaderMap, actual: &HeaderMap) -> TestResult {
     TestResult::Pass
 }
 
+/// Whether any value of the asserted header in `actual` satisfies `assertion`,
+/// so a missing header never does.
+fn header_matches(assertion: &HeaderAssertion, actual: &HeaderMap) -> bool {
+    actual
+        .get_all(&assertion.name)
+        .iter()
+        .any(|value| assertion.matches(&String::from_utf8_lossy(value.as_bytes())))
+}
+
+/// How `left` compares to `right`. Numbers and strings are ordered, other
+/// values can only be equal.
+pub(crate) fn compare_json(
+    left: &serde_json::Value,
+    right: &serde_json::Value,
+) -> Option<std::cmp::Ordering> {
+    use serde_json::Value;
+
+    match (left, right) {
+        (Value::Number(left), Value::Number(right)) => left.as_f64()?.partial_cmp(&right.as_f64()?),
+        (Value::String(left), Value::String(right)) => Some(left.cmp(right)),
+        _ if left == right => Some(std::cmp::Ordering::Equal),
+        _ => None,
+    }
+}
+
+/// The number of elements of the array at `path` in the response body.
+fn json_array_len(path: &JsonPath, body: Option<&serde_json::Value>) -> Result<usize, String> {
+    let body = body.ok_or("Response body is not JSON")?;
+
+    match path.resolve(body) {
+        Some(serde_json::Value::Array(items)) => Ok(items.len()),
+        Some(value) => Err(format!("{path} is not an array, got {value}")),
+        None => Err(format!("{path} does not exist in the response body")),
+    }
+}
+
+/// The paths in `paths` that don't lead to a value in `body`, or lead to
+/// null.
+fn missing_json_paths(
+    paths: &[JsonPath],
+    body: Option<&serde_json::Value>,
+) -> Result<Vec<String>, String> {
+    let body = body.ok_or("Response body is not JSON")?;
+
+    Ok(paths
+        .iter()
+        .filter(|path| path.resolve(body).is_none_or(serde_json::Value::is_null))
+        .map(ToString::to_string)
+        .collect())
+}
+
+fn assert_empty_body(body: Option<&str>) -> TestResult {
+    match body {
+        Some(body) if !body.trim().is_empty() => TestResult::Fail,
+        _ => TestResult::Pass,
+    }
+}
+
+/// Whether the redirect `chain` ends at `url`, resolved against the URL it
+/// ended at, after `hops` redirects. Unset expectations always match.
+fn redirect_matches(url: Option<&str>, hops: Option<usize>, chain: &[Url]) -> TestResult {
+    let Some(last) = chain.last() else {
+        return TestResult::Fail;
+    };
+
+    let url_matches = url.is_none_or(|url| last.join(url).is_ok_and(|url| &url == last));
+    let hops_matches = hops.is_none_or(|hops| hops == chain.len() - 1);
+
+    if url_matches && hops_matches {
+        TestResult::Pass
+    } else {
+        TestResult::Fail
+    }
+}
+
+/// E.g. `http://localhost/old -> http://localhost/new`.
+fn redirect_chain(chain: &[Url]) -> String {
+    chain
+        .iter()
+        .map(Url::as_str)
+        .collect::<Vec<_>>()
+        .join(" -> ")
+}
+
+/// Parses `body` as JSON again to get the error `CapturedResponse::body_json`
+/// threw away. A missing body is parsed as an empty one.
+fn assert_valid_json(body: Option<&str>) -> Result<(), String> {
+    serde_json::from_str::<serde_json::Value>(body.unwrap_or_default())
+        .map(|_| ())
+        .map_err(|error| error.to_string())
+}
+
+/// How many characters of an unexpected body are shown.
+const MAX_BODY_CHARS: usize = 200;
+
+/// Cuts `body` down to `MAX_BODY_CHARS` characters, marking where it was cut.
+fn truncate_body(body: &str) -> String {
+    match body.char_indices().nth(MAX_BODY_CHARS) {
+        Some((end, _)) => format!("{}… ({} bytes in total)", &body[..end], body.len()),
+        None => body.to_string(),
+    }
+}
+
+/// The value of the header `name` as an integer.
+fn header_int(headers: &HeaderMap, name: &HeaderName) -> Result<i64, String> {
+    let value = headers
+        .get(name)
+        .ok_or_else(|| format!("Header `{name}` is missing"))?;
+    let value = value.to_str().unwrap_or("<invalid utf8>");
+
+    value
+        .trim()
+        .parse()
+        .map_err(|_| format!("Header `{name}` is not an integer, got `{value}`"))
+}
+
+/// The last cookie named `name` in the response's `Set-Cookie` headers, the one
+/// a browser would end up keeping.
+fn find_cookie(headers: &HeaderMap, name: &str) -> Option<Cookie<'static>> {
+    headers
+        .get_all(SET_COOKIE)
+        .iter()
+        .rev()
+        .filter_map(|value| value.to_str().ok())
+        .filter_map(|value| Cookie::parse(value.to_string()).ok())
+        .find(|cookie| cookie.name() == name)
+}
+
+fn assert_cookie(expected: &ExpectedCookie, actual: Option<&Cookie>) -> TestResult {
+    match actual {
+        Some(actual) if cookie_mismatches(expected, actual).is_empty() => TestResult::Pass,
+        _ => TestResult::Fail,
+    }
+}
+
+/// The asserted attributes of the cookie that have a different value, as
+/// `(attribute, expected, actual)`.
+fn cookie_mismatches(
+    expected: &ExpectedCookie,
+    actual: &Cookie,
+) -> Vec<(&'static str, String, String)> {
+    let unset = || "<unset>".to_string();
+    let checks = [
+        ("value", expected.value.clone(), actual.value().to_string()),
+        (
+            "httponly",
+            expected.http_only.map(|b| b.to_string()),
+            actual.http_only().unwrap_or(false).to_string(),
+        ),
+        (
+            "secure",
+            expected.secure.map(|b| b.to_string()),
+            actual.secure().unwrap_or(false).to_string(),
+        ),
+        (
+            "samesite",
+            expected.same_site.map(|s| s.to_string()),
+            actual.same_site().map_or_else(unset, |s| s.to_string()),
+        ),
+        (
+            "max_age",
+            expected.max_age.map(|s| s.to_string()),
+            actual
+                .max_age()
+                .map_or_else(unset, |age| age.whole_seconds().to_string()),
+        ),
+    ];
+
+    checks
+        .into_iter()
+        .filter_map(|(attribute, expected, actual)| {
+            expected
+                .filter(|expected| *expected != actual)
+                .map(|expected| (attribute, expected, actual))
+        })
+        .collect()
+}
+
 fn assert_status(s: &i32, status: reqwest::StatusCode) -> TestResult {
     let inncomming_status_code = match StatusCode::from_u16(*s as u16) {
         Ok(status) => status,
         Err(_) => return TestResult::Fail,
     };
 
-    if inncomming_status_code != status {
-        return TestResult::Fail;
+    if inncomming_status_code != status {
+        return TestResult::Fail;
+    }
+
+    TestResult::Pass
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::OnceLock;
+    use std::time::Duration;
+    use std::time::Instant;
+
+    use cookie::SameSite;
+    use regex::Regex;
+    use reqwest::StatusCode;
+    use reqwest::Version;
+    use reqwest::header::CACHE_CONTROL;
+    use reqwest::header::CONTENT_ENCODING;
+    use reqwest::header::CONTENT_TYPE;
+    use reqwest::header::HOST;
+    use reqwest::header::HeaderMap;
+    use reqwest::header::HeaderName;
+    use reqwest::header::LOCATION;
+    use reqwest::header::SET_COOKIE;
+    use serde_json::json;
+    use url::Url;
+
+    use crate::asserter::Actual;
+    use crate::asserter::Assert;
+    use crate::asserter::AssertResult;
+    use crate::asserter::Asserter;
+    use crate::asserter::Snapshot;
+    use crate::asserter::TestOutcome;
+    use crate::asserter::TestResult;
+    use crate::asserter::assert_sql;
+    use crate::asserter::backend::backend;
+    use crate::parser::NumberOrString;
+    use crate::parser::StringOrStrings;
+    use crate::runner::BodyFile;
+    use crate::runner::CapturedResponse;
+    use crate::runner::RequestDetails;
+    use crate::runner::RunnerResult;
+    use crate::setup::database::any_db::AnyRow;
+    use crate::setup::database::any_db::DbValue;
+    use crate::setup::database::any_db::SqlRows;
+    use crate::validator::Assertion;
+    use crate::validator::BodySize;
+    use crate::validator::Comparison;
+    use crate::validator::ContentEncoding;
+    use crate::validator::ExpectedCookie;
+    use crate::validator::ExpectedError;
+    use crate::validator::HeaderAssertion;
+    use crate::validator::HeaderMatch;
+    use crate::validator::JsonOperand;
+    use crate::validator::JsonPath;
+    use crate::validator::WsMessage;
+
+    #[test]
+    fn assert_status_test() {
+        // TODO: Write tests
+    }
+    #[test]
+    fn assert_headers() {
+        let mut actual = HeaderMap::new();
+        actual.insert(
+            CONTENT_TYPE,
+            "application/json; charset=utf-8".parse().unwrap(),
+        );
+
+        let check = |assertion: HeaderAssertion| super::assert_header(&[assertion], &actual);
+
+        let exact = header(CONTENT_TYPE, "application/json", HeaderMatch::Exact);
+        assert_eq!(check(exact), TestResult::Fail);
+
+        let contains = header(CONTENT_TYPE, "application/json", HeaderMatch::Contains);
+        assert_eq!(check(contains), TestResult::Pass);
+
+        let mut contains = header(CONTENT_TYPE, "Application/JSON", HeaderMatch::Contains);
+        assert_eq!(check(contains.clone()), TestResult::Fail);
+        contains.ignore_case = true;
+        assert_eq!(check(contains), TestResult::Pass);
+
+        let regex = Regex::new(r"^application/json; charset=utf-\d+$").unwrap();
+        let regex = header(CONTENT_TYPE, "", HeaderMatch::Regex(regex));
+        assert_eq!(check(regex), TestResult::Pass);
+
+        let missing = header(CACHE_CONTROL, "no-store", HeaderMatch::Contains);
+        assert_eq!(check(missing), TestResult::Fail);
+    }
+
+    #[test]
+    fn cookie_failure_names_the_mismatched_attributes() {
+        let mut headers = HeaderMap::new();
+        headers.append(SET_COOKIE, "theme=dark".parse().unwrap());
+        headers.append(
+            SET_COOKIE,
+            "session=abc; HttpOnly; SameSite=Lax; Max-Age=3600"
+                .parse()
+                .unwrap(),
+        );
+        let expected = ExpectedCookie {
+            name: "session".into(),
+            value: Some("abc".into()),
+            http_only: Some(true),
+            secure: Some(true),
+            same_site: Some(SameSite::Strict),
+            max_age: Some(3600),
+        };
+
+        let cookie = super::find_cookie(&headers, "session");
+        assert_eq!(
+            super::assert_cookie(&expected, cookie.as_ref()),
+            TestResult::Fail
+        );
+
+        let result = AssertResult {
+            status: TestResult::Fail,
+            expected: Assertion::Cookie(expected.clone()),
+            actual: Actual::Cookie(cookie),
+            location: None,
+        };
+        let output = console::strip_ansi_codes(&result.to_string()).to_string();
+        assert!(output.contains("secure: expected true got false"));
+        assert!(output.contains("samesite: expected Strict got Lax"));
+        assert!(!output.contains("httponly"));
+        assert!(!output.contains("max_age"));
+
+        let missing = super::find_cookie(&headers, "csrf");
+        assert_eq!(
+            super::assert_cookie(&expected, missing.as_ref()),
+            TestResult::Fail
+        );
+    }
+
+    #[test]
+    fn empty_body_failure_shows_truncated_body() {
+        assert_eq!(super::assert_empty_body(Some(" \n")), TestResult::Pass);
+        assert_eq!(super::assert_empty_body(Some("{}")), TestResult::Fail);
+
+        let body = "x".repeat(500);
+        let result = AssertResult {
+            status: TestResult::Fail,
+            expected: Assertion::EmptyBody,
+            actual: Actual::Body(body),
+            location: None,
+        };
+        let output = console::strip_ansi_codes(&result.to_string()).to_string();
+        assert!(output.contains(&format!("{}… (500 bytes in total)", "x".repeat(200))));
+        assert!(!output.contains(&"x".repeat(201)));
+    }
+
+    #[test]
+    fn json_array_len_fails_when_path_is_not_an_array() {
+        let body = json!({ "items": [1, 2, 3], "total": 3 });
+        let len = |path: &str| super::json_array_len(&JsonPath::parse(path).unwrap(), Some(&body));
+
+        assert_eq!(len("$.items"), Ok(3));
+        assert_eq!(len("$.total"), Err("$.total is not an array, got 3".into()));
+        assert_eq!(
+            len("$.missing"),
+            Err("$.missing does not exist in the response body".into())
+        );
+        assert!(super::json_array_len(&JsonPath::parse("$").unwrap(), None).is_err());
+    }
+
+    fn header(name: HeaderName, value: &str, mode: HeaderMatch) -> HeaderAssertion {
+        HeaderAssertion {
+            name,
+            value: value.into(),
+            mode,
+            ignore_case: false,
+        }
     }
+    #[test]
+    fn header_failure_only_shows_differing_headers() {
+        let expected = vec![
+            header(CONTENT_TYPE, "application/json", HeaderMatch::Exact),
+            header(CACHE_CONTROL, "no-store", HeaderMatch::Exact),
+            header(HOST, "world", HeaderMatch::Exact),
+        ];
 
-    TestResult::Pass
-}
+        let mut actual = HeaderMap::new();
+        actual.insert(CONTENT_TYPE, "application/json".parse().unwrap());
+        actual.insert(CACHE_CONTROL, "max-age=60".parse().unwrap());
+        actual.insert(LOCATION, "/users/1".parse().unwrap());
 
-#[cfg(test)]
-mod test {
-    use std::sync::Arc;
+        let result = AssertResult {
+            status: TestResult::Fail,
+            expected: Assertion::Headers(expected),
+            actual: Actual::Header(actual),
+            location: None,
+        };
 
-    use reqwest::StatusCode;
-    use reqwest::header::HOST;
-    use reqwest::header::HeaderMap;
-    use reqwest::header::LOCATION;
-    use url::Url;
+        let output = console::strip_ansi_codes(&result.to_string()).to_string();
+        assert!(output.contains("- cache-control: no-store"));
+        assert!(output.contains("+ cache-control: max-age=60"));
+        assert!(output.contains("- host: world"));
+        assert!(output.contains("+ host: <missing>"));
+        assert!(output.contains("+ location: /users/1"));
+        assert!(!output.contains("content-type"));
+    }
 
-    use crate::asserter::AssertResult;
-    use crate::asserter::Asserter;
-    use crate::asserter::TestResult;
-    use crate::runner::CapturedResponse;
-    use crate::runner::RunnerResult;
-    use crate::validator::Assertion;
+    #[test]
+    fn assert_json() {
+        let expected = json!({ "id": 1, "name": "Alice" });
+
+        assert_eq!(
+            super::assert_json(&expected, Some(&json!({ "id": 1, "name": "Alice" })), None),
+            TestResult::Pass
+        );
+        assert_eq!(
+            super::assert_json(&expected, Some(&json!({ "id": 1, "name": "Bob" })), None),
+            TestResult::Fail
+        );
+    }
 
     #[test]
-    fn assert_status_test() {
-        // TODO: Write tests
+    fn epsilon_allows_close_numbers() {
+        let expected = json!({ "price": 2.75, "tags": [1.5], "name": "tea" });
+        let got = json!({ "price": 2.7500001, "tags": [1.5000002], "name": "tea" });
+
+        assert_eq!(
+            super::assert_json(&expected, Some(&got), None),
+            TestResult::Fail
+        );
+        assert_eq!(
+            super::assert_json(&expected, Some(&got), Some(0.001)),
+            TestResult::Pass
+        );
+        assert_eq!(
+            super::assert_json(
+                &expected,
+                Some(&json!({ "price": 2.8, "tags": [1.5], "name": "tea" })),
+                Some(0.001)
+            ),
+            TestResult::Fail
+        );
+
+        let expected = StringOrStrings::Single("1,3.14,Alice".into());
+        let row = |price: f64, name: &str| {
+            rows(vec![vec![
+                DbValue::I64(1),
+                DbValue::F64(price),
+                DbValue::String(name.into()),
+            ]])
+        };
+        let got = row(3.1400001, "Alice");
+        assert_eq!(assert_sql(&expected, Some(&got), None), TestResult::Fail);
+        assert_eq!(
+            assert_sql(&expected, Some(&got), Some(0.001)),
+            TestResult::Pass
+        );
+        let got = row(3.1400001, "Bob");
+        assert_eq!(
+            assert_sql(&expected, Some(&got), Some(0.001)),
+            TestResult::Fail
+        );
+        // Text that looks like a number is still compared as text.
+        let got = rows(vec![vec![
+            DbValue::I64(1),
+            DbValue::String("3.1400001".into()),
+            DbValue::String("Alice".into()),
+        ]]);
+        assert_eq!(
+            assert_sql(&expected, Some(&got), Some(0.001)),
+            TestResult::Fail
+        );
     }
+
     #[test]
-    fn assert_headers() {
-        // TODO: Write tests
+    fn json_failure_shows_diff_of_changed_lines() {
+        let result = AssertResult {
+            status: TestResult::Fail,
+            expected: Assertion::Json {
+                expected: json!({ "id": 1, "name": "Alice" }),
+                epsilon: None,
+                ignore_keys: vec![],
+            },
+            actual: Actual::Json(json!({ "id": 1, "name": "Bob" })),
+            location: None,
+        };
+
+        let output = console::strip_ansi_codes(&result.to_string()).to_string();
+        assert!(output.contains(r#"-   "name": "Alice""#));
+        assert!(output.contains(r#"+   "name": "Bob""#));
+        assert!(output.contains(r#"    "id": 1,"#));
     }
+
     #[test]
-    fn assert_json() {
-        // TODO: Write tests
+    fn json_ignores_keys_in_both_documents() {
+        let response = CapturedResponse {
+            status: StatusCode::OK,
+            version: Version::HTTP_11,
+            headers: HeaderMap::new(),
+            body_text: None,
+            body_json: Some(json!({
+                "id": 7,
+                "createdAt": "2024-05-01T10:00:00Z",
+                "user": { "id": 3, "name": "Alice", "createdAt": "2024-01-01" },
+                "tags": [{ "id": 1, "name": "new" }],
+            })),
+            body_file: None,
+            body_bytes: None,
+            trailers: None,
+        };
+        let assertion = |ignore_keys: &[&str]| Assertion::Json {
+            expected: json!({
+                "id": 1,
+                "user": { "id": 1, "name": "Alice" },
+                "tags": [{ "id": 2, "name": "new" }],
+            }),
+            epsilon: None,
+            ignore_keys: ignore_keys.iter().map(|k| k.to_string()).collect(),
+        };
+
+        let (status, _) = backend(&assertion(&["createdAt", "id"])).evaluate(&response);
+        assert_eq!(status, TestResult::Pass);
+
+        // A dotted path only ignores the key at that place.
+        let (status, actual) =
+            backend(&assertion(&["createdAt", "user.id", "tags.id"])).evaluate(&response);
+        assert_eq!(status, TestResult::Fail);
+        let Actual::Json(actual) = actual else {
+            panic!("expected the body");
+        };
+        assert_eq!(
+            actual,
+            json!({
+                "id": 7,
+                "user": { "name": "Alice" },
+                "tags": [{ "name": "new" }],
+            })
+        );
+    }
+
+    fn rows(rows: Vec<Vec<DbValue>>) -> SqlRows {
+        SqlRows {
+            rows: rows.into_iter().map(|values| AnyRow { values }).collect(),
+            truncated: false,
+        }
     }
 
     #[test]
     fn assert_db_state() {
-        // TODO: Write tests
+        let single = StringOrStrings::Single("Alice".into());
+        let multiple = StringOrStrings::Multiple(vec!["1,Alice".into(), "2,Bob".into()]);
+        let name = |name: &str| vec![DbValue::String(name.into())];
+        let user = |id: i64, name: &str| vec![DbValue::I64(id), DbValue::String(name.into())];
+
+        assert_eq!(
+            assert_sql(&single, Some(&rows(vec![name("Alice")])), None),
+            TestResult::Pass
+        );
+        assert_eq!(
+            assert_sql(&single, Some(&rows(vec![name("Bob")])), None),
+            TestResult::Fail
+        );
+        assert_eq!(assert_sql(&single, None, None), TestResult::Fail);
+        assert_eq!(
+            assert_sql(
+                &multiple,
+                Some(&rows(vec![user(1, "Alice"), user(2, "Bob")])),
+                None
+            ),
+            TestResult::Pass
+        );
+        assert_eq!(
+            assert_sql(&multiple, Some(&rows(vec![user(1, "Alice")])), None),
+            TestResult::Fail
+        );
+        let truncated = SqlRows {
+            truncated: true,
+            ..rows(vec![name("Alice")])
+        };
+        assert_eq!(
+            assert_sql(&single, Some(&truncated), None),
+            TestResult::Fail
+        );
+
+        let array = StringOrStrings::Single(r#"1,{1, 2, NULL},{a, "b, c"}"#.into());
+        let row = |last: &str| {
+            rows(vec![vec![
+                DbValue::I64(1),
+                DbValue::Array(vec![DbValue::I64(1), DbValue::I64(2), DbValue::Null]),
+                DbValue::Array(vec![
+                    DbValue::String("a".into()),
+                    DbValue::String(last.into()),
+                ]),
+            ]])
+        };
+        assert_eq!(
+            assert_sql(&array, Some(&row("b, c")), None),
+            TestResult::Pass
+        );
+        assert_eq!(
+            assert_sql(&array, Some(&row("b,c")), None),
+            TestResult::Fail
+        );
     }
 
     #[tokio::test]
     async fn test_full() {
         let (runner_tx, asserter_rx) = flume::unbounded::<RunnerResult>();
-        let (asserter_tx, outputter_rx) =
-            flume::unbounded::<(String, String, String, Arc<[AssertResult]>)>();
+        let (asserter_tx, outputter_rx) = flume::unbounded::<TestOutcome>();
 
         tokio::spawn(async move {
             Asserter::run(asserter_rx, asserter_tx).await.unwrap();
@@ -464,33 +1972,975 @@ mod test {
         runner_tx
             .send_async(RunnerResult {
                 name: "this-is-a-name".into(),
+                group: "group".into(),
                 method: "GET".into(),
                 url: Url::parse("http://test.com/some-path").unwrap(),
                 response: Some(CapturedResponse {
                     status: StatusCode::OK,
+                    version: Version::HTTP_11,
                     headers: header_map.clone(),
                     body_text: None,
                     body_json: Some(serde_json::from_str(json_data).unwrap()),
+                    body_file: None,
+                    body_bytes: None,
+                    trailers: None,
                 }),
                 error: None,
                 assertions: vec![
                     Assertion::Status(200),
-                    Assertion::Headers(header_map),
-                    Assertion::Json(serde_json::from_str(json_data).unwrap()),
-                ],
+                    Assertion::Headers(vec![
+                        header(HOST, "world", HeaderMatch::Exact),
+                        header(LOCATION, "this-is-a-location", HeaderMatch::Exact),
+                    ]),
+                    Assertion::Json {
+                        expected: serde_json::from_str(json_data).unwrap(),
+                        epsilon: None,
+                        ignore_keys: vec![],
+                    },
+                ]
+                .into(),
+                duration: Duration::from_millis(12),
+                location: None,
+                assertion_locations: vec![],
+                request: RequestDetails::default(),
+                ran_during: Instant::now()..Instant::now(),
+                skipped: None,
+                timed_out: None,
+                verdict: OnceLock::new(),
             })
             .await
             .unwrap();
 
-        let Ok((name, path, method, result)) = outputter_rx.recv_async().await else {
+        let Ok(outcome) = outputter_rx.recv_async().await else {
             todo!()
         };
-        assert_eq!(name, "this-is-a-name");
-        assert_eq!(path, "/some-path");
-        assert_eq!(method, "GET");
+        assert_eq!(outcome.name, "this-is-a-name");
+        assert_eq!(outcome.path, "/some-path");
+        assert_eq!(outcome.method, "GET");
+        assert_eq!(outcome.duration, Duration::from_millis(12));
 
-        for res in result.iter() {
+        for res in outcome.results.iter() {
             assert_eq!(res.status, TestResult::Pass);
         }
     }
+
+    #[test]
+    fn backends_evaluate_a_single_assertion() {
+        let response = CapturedResponse {
+            status: StatusCode::NOT_FOUND,
+            version: Version::HTTP_11,
+            headers: HeaderMap::new(),
+            body_text: Some("[1, 2]".into()),
+            body_json: Some(json!([1, 2])),
+            body_file: None,
+            body_bytes: None,
+            trailers: None,
+        };
+
+        let (status, actual) = backend(&Assertion::Status(200)).evaluate(&response);
+        assert_eq!(status, TestResult::Fail);
+        assert!(matches!(actual, Actual::Status(StatusCode::NOT_FOUND)));
+
+        let assertion = Assertion::JsonArrayLen {
+            path: JsonPath::parse("$").unwrap(),
+            expected: 2,
+        };
+        let (status, actual) = backend(&assertion).evaluate(&response);
+        assert_eq!(status, TestResult::Pass);
+        assert!(matches!(actual, Actual::JsonArrayLen(Ok(2))));
+    }
+
+    #[test]
+    fn server_errors_fail_with_the_body() {
+        let response = |status: StatusCode| CapturedResponse {
+            status,
+            version: Version::HTTP_11,
+            headers: HeaderMap::new(),
+            body_text: Some("database connection lost".into()),
+            body_json: None,
+            body_file: None,
+            body_bytes: None,
+            trailers: None,
+        };
+
+        let (status, _) =
+            backend(&Assertion::NoServerError).evaluate(&response(StatusCode::NOT_FOUND));
+        assert_eq!(status, TestResult::Pass);
+
+        let (status, actual) =
+            backend(&Assertion::NoServerError).evaluate(&response(StatusCode::BAD_GATEWAY));
+        assert_eq!(status, TestResult::Fail);
+
+        let result = AssertResult {
+            status,
+            expected: Assertion::NoServerError,
+            actual,
+            location: None,
+        };
+        let output = console::strip_ansi_codes(&result.to_string()).to_string();
+        assert!(output.contains("Got status 502 Bad Gateway"));
+        assert!(output.contains("database connection lost"));
+    }
+
+    #[test]
+    fn http_version_shows_the_negotiated_version() {
+        let response = CapturedResponse {
+            status: StatusCode::OK,
+            version: Version::HTTP_11,
+            headers: HeaderMap::new(),
+            body_text: None,
+            body_json: None,
+            body_file: None,
+            body_bytes: None,
+            trailers: None,
+        };
+
+        let (status, _) = backend(&Assertion::HttpVersion(Version::HTTP_11)).evaluate(&response);
+        assert_eq!(status, TestResult::Pass);
+
+        let assertion = Assertion::HttpVersion(Version::HTTP_2);
+        let (status, actual) = backend(&assertion).evaluate(&response);
+        assert_eq!(status, TestResult::Fail);
+
+        let result = AssertResult {
+            status,
+            expected: assertion,
+            actual,
+            location: None,
+        };
+        let output = console::strip_ansi_codes(&result.to_string()).to_string();
+        assert!(output.contains("Expected HTTP/2.0"));
+        assert!(output.contains("Got HTTP/1.1"));
+    }
+
+    #[test]
+    fn trailers_are_checked_like_headers() {
+        let response = |trailers: Option<HeaderMap>| CapturedResponse {
+            status: StatusCode::OK,
+            version: Version::HTTP_11,
+            headers: HeaderMap::new(),
+            body_text: Some("done".into()),
+            body_json: None,
+            body_file: None,
+            body_bytes: None,
+            trailers,
+        };
+        let mut sent = HeaderMap::new();
+        sent.insert("grpc-status", "0".parse().unwrap());
+        let assertion = Assertion::Trailers(vec![header(
+            HeaderName::from_static("grpc-status"),
+            "0",
+            HeaderMatch::Exact,
+        )]);
+
+        let (status, _) = backend(&assertion).evaluate(&response(Some(sent)));
+        assert_eq!(status, TestResult::Pass);
+
+        let (status, actual) = backend(&assertion).evaluate(&response(Some(HeaderMap::new())));
+        assert_eq!(status, TestResult::Fail);
+        let result = AssertResult {
+            status,
+            expected: assertion.clone(),
+            actual,
+            location: None,
+        };
+        let output = console::strip_ansi_codes(&result.to_string()).to_string();
+        assert!(output.contains("- grpc-status: 0"));
+        assert!(output.contains("+ grpc-status: <missing>"));
+
+        let (status, actual) = backend(&assertion).evaluate(&response(None));
+        assert_eq!(status, TestResult::Fail);
+        assert_eq!(
+            actual.to_string(),
+            "The response's trailers couldn't be read"
+        );
+    }
+
+    #[test]
+    fn json_equals_var_compares_to_the_captured_body() {
+        let response = CapturedResponse {
+            status: StatusCode::OK,
+            version: Version::HTTP_11,
+            headers: HeaderMap::new(),
+            body_text: None,
+            body_json: Some(json!({ "id": 1, "name": "Ada" })),
+            body_file: None,
+            body_bytes: None,
+            trailers: None,
+        };
+        let check = |captured: Option<serde_json::Value>| {
+            let assertion = Assertion::JsonEqualsVar {
+                var: "user".into(),
+                captured,
+            };
+            let (status, actual) = backend(&assertion).evaluate(&response);
+            let result = AssertResult {
+                status,
+                expected: assertion,
+                actual,
+                location: None,
+            };
+            let output = console::strip_ansi_codes(&result.to_string()).to_string();
+            (result.status, output)
+        };
+
+        let (status, _) = check(Some(json!({ "name": "Ada", "id": 1 })));
+        assert_eq!(status, TestResult::Pass);
+
+        let (status, output) = check(Some(json!({ "id": 1, "name": "Bob" })));
+        assert_eq!(status, TestResult::Fail);
+        assert!(output.contains("- Captured `user`"), "{output}");
+        assert!(output.contains(r#"-   "name": "Bob""#), "{output}");
+        assert!(output.contains(r#"+   "name": "Ada""#), "{output}");
+
+        let (status, output) = check(Some(json!([{ "id": 1 }])));
+        assert_eq!(status, TestResult::Fail);
+        assert!(output.contains("Expected an array like `user`, got an object"));
+
+        let (status, output) = check(None);
+        assert_eq!(status, TestResult::Fail);
+        assert!(output.contains("Nothing was stored as `user`"));
+    }
+
+    #[test]
+    fn json_has_reports_missing_and_null_paths() {
+        let response = CapturedResponse {
+            status: StatusCode::OK,
+            version: Version::HTTP_11,
+            headers: HeaderMap::new(),
+            body_text: None,
+            body_json: Some(json!({ "id": "4f1c", "owner": null, "items": [{ "sku": 1 }] })),
+            body_file: None,
+            body_bytes: None,
+            trailers: None,
+        };
+        let assertion = |paths: &[&str]| {
+            Assertion::JsonHas(paths.iter().map(|p| JsonPath::parse(p).unwrap()).collect())
+        };
+
+        let (status, _) = backend(&assertion(&["$.id", "$.items[0].sku"])).evaluate(&response);
+        assert_eq!(status, TestResult::Pass);
+
+        let expected = assertion(&["$.id", "$.owner", "$.createdAt"]);
+        let (status, actual) = backend(&expected).evaluate(&response);
+        assert_eq!(status, TestResult::Fail);
+
+        let result = AssertResult {
+            status,
+            expected,
+            actual,
+            location: None,
+        };
+        let output = console::strip_ansi_codes(&result.to_string()).to_string();
+        assert!(output.contains("Missing or null: $.owner, $.createdAt"));
+    }
+
+    #[test]
+    fn json_relation_compares_two_values_of_the_body() {
+        let response = CapturedResponse {
+            status: StatusCode::OK,
+            version: Version::HTTP_11,
+            headers: HeaderMap::new(),
+            body_text: None,
+            body_json: Some(json!({
+                "total": 2,
+                "count": 2.0,
+                "items": [1, 2],
+                "from": "2024-01-01",
+                "to": "2024-02-01",
+            })),
+            body_file: None,
+            body_bytes: None,
+            trailers: None,
+        };
+        let relation = |left: &str, op: Comparison, right: &str| Assertion::JsonRelation {
+            left: JsonOperand::parse(left).unwrap(),
+            op,
+            right: JsonOperand::parse(right).unwrap(),
+        };
+
+        for (left, op, right) in [
+            ("$.total", Comparison::Eq, "$.items.length()"),
+            ("$.total", Comparison::Eq, "$.count"),
+            ("$.to", Comparison::Ge, "$.from"),
+            ("$.items", Comparison::Eq, "$.items"),
+        ] {
+            let (status, _) = backend(&relation(left, op, right)).evaluate(&response);
+            assert_eq!(status, TestResult::Pass, "{left} {op} {right}");
+        }
+
+        let assertion = relation("$.to", Comparison::Le, "$.from");
+        let (status, actual) = backend(&assertion).evaluate(&response);
+        assert_eq!(status, TestResult::Fail);
+        let output = AssertResult {
+            status,
+            expected: assertion,
+            actual,
+            location: None,
+        }
+        .to_string();
+        assert!(output.contains("Expected $.to <= $.from"), "{output}");
+        assert!(output.contains(r#"$.to = "2024-02-01""#), "{output}");
+        assert!(output.contains(r#"$.from = "2024-01-01""#), "{output}");
+
+        let (status, actual) =
+            backend(&relation("$.missing", Comparison::Eq, "$.total")).evaluate(&response);
+        assert_eq!(status, TestResult::Fail);
+        assert!(matches!(
+            actual,
+            Actual::JsonRelation {
+                left: Err(_),
+                right: Ok(_)
+            }
+        ));
+    }
+
+    #[test]
+    fn timed_out_tests_fail_with_the_group_timeout() {
+        let result = RunnerResult {
+            name: "Hang".into(),
+            group: "group".into(),
+            method: "GET".into(),
+            url: Url::parse("http://localhost:1/").unwrap(),
+            response: None,
+            error: None,
+            assertions: vec![Assertion::Status(200)].into(),
+            duration: Duration::ZERO,
+            location: None,
+            assertion_locations: vec![],
+            request: RequestDetails::default(),
+            ran_during: Instant::now()..Instant::now(),
+            skipped: None,
+            timed_out: Some(Duration::from_secs(30)),
+            verdict: OnceLock::new(),
+        };
+
+        let results = result.assert();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].status, TestResult::Fail);
+        assert!(matches!(
+            results[0].expected,
+            Assertion::GroupTimeout(timeout) if timeout == Duration::from_secs(30)
+        ));
+        let output = results[0].to_string();
+        assert!(output.contains("TIMED OUT!"), "{output}");
+        assert!(
+            output.contains("Aborted: the group ran past its timeout of 30s"),
+            "{output}"
+        );
+    }
+
+    #[test]
+    fn request_error_passes_when_the_request_fails_as_expected() {
+        let result = |error: Option<&str>, expected: ExpectedError| RunnerResult {
+            name: "Unreachable".into(),
+            group: "group".into(),
+            method: "GET".into(),
+            url: Url::parse("http://localhost:1/").unwrap(),
+            response: error.is_none().then(|| CapturedResponse {
+                status: StatusCode::OK,
+                version: Version::HTTP_11,
+                headers: HeaderMap::new(),
+                body_text: None,
+                body_json: None,
+                body_file: None,
+                body_bytes: None,
+                trailers: None,
+            }),
+            error: error.map(String::from),
+            assertions: vec![Assertion::RequestError(expected)].into(),
+            duration: Duration::ZERO,
+            location: None,
+            assertion_locations: vec![],
+            request: RequestDetails::default(),
+            ran_during: Instant::now()..Instant::now(),
+            skipped: None,
+            timed_out: None,
+            verdict: OnceLock::new(),
+        };
+        let error = "error sending request: tcp connect error: Connection refused (os error 111)";
+        let refused = || ExpectedError::Contains("Connection refused".into());
+
+        let results = result(Some(error), refused()).assert();
+        assert_eq!(results[0].status, TestResult::Pass);
+
+        let dns = ExpectedError::Regex(Regex::new("dns error|failed to lookup").unwrap());
+        let results = result(Some(error), dns).assert();
+        assert_eq!(results[0].status, TestResult::Fail);
+        let output = results[0].to_string();
+        assert!(
+            output.contains("Expected the request to fail with an error matching"),
+            "{output}"
+        );
+        assert!(
+            output.contains("Connection refused (os error 111)"),
+            "{output}"
+        );
+
+        let results = result(None, refused()).assert();
+        assert_eq!(results[0].status, TestResult::Fail);
+        assert!(
+            results[0]
+                .to_string()
+                .contains("Got a response with status 200 OK")
+        );
+    }
+
+    #[test]
+    fn ws_message_checks_the_received_message() {
+        let response = |message: Option<&str>| CapturedResponse {
+            status: StatusCode::SWITCHING_PROTOCOLS,
+            version: Version::HTTP_11,
+            headers: HeaderMap::new(),
+            body_text: message.map(String::from),
+            body_json: message.and_then(|m| serde_json::from_str(m).ok()),
+            body_file: None,
+            body_bytes: None,
+            trailers: None,
+        };
+        let pong = response(Some(r#"{"type": "pong", "id": 1}"#));
+
+        for (expected, status) in [
+            (
+                WsMessage::Equals(r#"{"type": "pong", "id": 1}"#.into()),
+                TestResult::Pass,
+            ),
+            (WsMessage::Contains("pong".into()), TestResult::Pass),
+            (
+                WsMessage::Json(json!({ "id": 1, "type": "pong" })),
+                TestResult::Pass,
+            ),
+            (WsMessage::Json(json!({ "type": "ping" })), TestResult::Fail),
+        ] {
+            let (got, _) = backend(&Assertion::WsMessage(expected.clone())).evaluate(&pong);
+            assert_eq!(got, status, "{expected}");
+        }
+
+        let assertion = Assertion::WsMessage(WsMessage::Contains("pong".into()));
+        let (status, actual) = backend(&assertion).evaluate(&response(None));
+        assert_eq!(status, TestResult::Fail);
+        let output = AssertResult {
+            status,
+            expected: assertion,
+            actual,
+            location: None,
+        }
+        .to_string();
+        assert!(
+            output.contains("Expected a message containing `pong`"),
+            "{output}"
+        );
+        assert!(output.contains("No message was received"), "{output}");
+    }
+
+    #[test]
+    fn json_any_of_passes_on_any_alternative() {
+        let response = |body: serde_json::Value| CapturedResponse {
+            status: StatusCode::OK,
+            version: Version::HTTP_11,
+            headers: HeaderMap::new(),
+            body_text: Some(body.to_string()),
+            body_json: Some(body),
+            body_file: None,
+            body_bytes: None,
+            trailers: None,
+        };
+        let assertion = Assertion::JsonAnyOf(vec![
+            json!({ "type": "card", "last4": "4242" }),
+            json!({ "type": "bank", "iban": "NO93 8601 1117 947" }),
+        ]);
+
+        let paid_by_bank = response(json!({ "type": "bank", "iban": "NO93 8601 1117 947" }));
+        let (status, _) = backend(&assertion).evaluate(&paid_by_bank);
+        assert_eq!(status, TestResult::Pass);
+
+        let (status, actual) = backend(&assertion).evaluate(&response(json!({ "type": "cash" })));
+        assert_eq!(status, TestResult::Fail);
+        let output = AssertResult {
+            status,
+            expected: assertion,
+            actual,
+            location: None,
+        }
+        .to_string();
+        assert!(output.contains("one of 2 alternatives"), "{output}");
+        assert!(
+            output.contains(r#" 2: {"iban":"NO93 8601 1117 947","type":"bank"}"#),
+            "{output}"
+        );
+        assert!(output.contains(r#"Got {"type":"cash"}"#), "{output}");
+    }
+
+    #[test]
+    fn valid_json_reports_the_parse_error() {
+        let response = |body: &str| CapturedResponse {
+            status: StatusCode::OK,
+            version: Version::HTTP_11,
+            headers: HeaderMap::new(),
+            body_text: Some(body.into()),
+            body_json: serde_json::from_str(body).ok(),
+            body_file: None,
+            body_bytes: None,
+            trailers: None,
+        };
+
+        let (status, actual) = backend(&Assertion::ValidJson).evaluate(&response(r#"{"id": 1}"#));
+        assert_eq!(status, TestResult::Pass);
+        assert!(matches!(actual, Actual::ValidJson(Ok(()))));
+
+        let (status, actual) = backend(&Assertion::ValidJson).evaluate(&response(r#"{"id": 1"#));
+        assert_eq!(status, TestResult::Fail);
+        let Actual::ValidJson(Err(error)) = &actual else {
+            panic!("expected a parse error, got {actual:?}");
+        };
+        assert!(error.contains("EOF while parsing an object"), "{error}");
+
+        let (status, _) = backend(&Assertion::ValidJson).evaluate(&response(""));
+        assert_eq!(status, TestResult::Fail);
+    }
+
+    #[test]
+    fn header_int_compares_numeric_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-ratelimit-remaining", "5".parse().unwrap());
+        headers.insert("x-total", "many".parse().unwrap());
+        let response = CapturedResponse {
+            status: StatusCode::OK,
+            version: Version::HTTP_11,
+            headers,
+            body_text: None,
+            body_json: None,
+            body_file: None,
+            body_bytes: None,
+            trailers: None,
+        };
+        let evaluate = |name: &str, op: Comparison, value: i64| {
+            let assertion = Assertion::HeaderInt {
+                name: HeaderName::from_bytes(name.as_bytes()).unwrap(),
+                op,
+                value,
+            };
+            backend(&assertion).evaluate(&response)
+        };
+
+        let (status, actual) = evaluate("X-RateLimit-Remaining", Comparison::Ge, 1);
+        assert_eq!(status, TestResult::Pass);
+        assert!(matches!(actual, Actual::HeaderInt(Ok(5))));
+
+        let (status, _) = evaluate("X-RateLimit-Remaining", Comparison::Le, 4);
+        assert_eq!(status, TestResult::Fail);
+
+        let (status, _) = evaluate("X-RateLimit-Remaining", Comparison::Eq, 5);
+        assert_eq!(status, TestResult::Pass);
+
+        let (status, actual) = evaluate("X-Total", Comparison::Eq, 5);
+        assert_eq!(status, TestResult::Fail);
+        let Actual::HeaderInt(Err(error)) = actual else {
+            panic!("expected a non-numeric header to fail, got {actual:?}");
+        };
+        assert_eq!(error, "Header `x-total` is not an integer, got `many`");
+
+        let (status, actual) = evaluate("X-Missing", Comparison::Eq, 5);
+        assert_eq!(status, TestResult::Fail);
+        assert!(
+            matches!(actual, Actual::HeaderInt(Err(e)) if e == "Header `x-missing` is missing")
+        );
+    }
+
+    #[test]
+    fn sql_compare_coerces_to_the_type_of_the_value() {
+        let response = CapturedResponse {
+            status: StatusCode::OK,
+            version: Version::HTTP_11,
+            headers: HeaderMap::new(),
+            body_text: None,
+            body_json: None,
+            body_file: None,
+            body_bytes: None,
+            trailers: None,
+        };
+        let evaluate = |got: Result<&str, &str>, op: Comparison, value: NumberOrString| {
+            let assertion = Assertion::SqlCompare {
+                query: "SELECT count(*) FROM users".into(),
+                op,
+                value,
+                got: Some(got.map(String::from).map_err(String::from)),
+            };
+            backend(&assertion).evaluate(&response)
+        };
+        let number = NumberOrString::Number;
+        let string = |s: &str| NumberOrString::String(s.into());
+
+        let (status, actual) = evaluate(Ok("3"), Comparison::Gt, number(0.0));
+        assert_eq!(status, TestResult::Pass);
+        assert!(matches!(actual, Actual::SqlCompare(Ok(got)) if got == "3"));
+        // Compared as numbers, 10 isn't smaller than 9.
+        let (status, _) = evaluate(Ok("10"), Comparison::Lt, number(9.0));
+        assert_eq!(status, TestResult::Fail);
+        let (status, _) = evaluate(Ok("10.50"), Comparison::Eq, number(10.5));
+        assert_eq!(status, TestResult::Pass);
+        let (status, _) = evaluate(Ok("0"), Comparison::Ne, number(0.0));
+        assert_eq!(status, TestResult::Fail);
+
+        let (status, _) = evaluate(Ok("active"), Comparison::Ne, string("deleted"));
+        assert_eq!(status, TestResult::Pass);
+        let (status, _) = evaluate(Ok("10"), Comparison::Lt, string("9"));
+        assert_eq!(status, TestResult::Pass);
+
+        let (status, actual) = evaluate(Ok("active"), Comparison::Ge, number(0.0));
+        assert_eq!(status, TestResult::Fail);
+        let Actual::SqlCompare(Err(error)) = actual else {
+            panic!("expected a non-numeric value to fail, got {actual:?}");
+        };
+        assert_eq!(
+            error,
+            "Got `active`, which can't be compared to the number 0"
+        );
+
+        let (status, actual) = evaluate(
+            Err("The query returned no rows"),
+            Comparison::Gt,
+            number(0.0),
+        );
+        assert_eq!(status, TestResult::Fail);
+        assert!(matches!(actual, Actual::SqlCompare(Err(e)) if e == "The query returned no rows"));
+    }
+
+    #[test]
+    fn failed_sql_queries_show_their_error() {
+        let response = CapturedResponse {
+            status: StatusCode::OK,
+            version: Version::HTTP_11,
+            headers: HeaderMap::new(),
+            body_text: None,
+            body_json: None,
+            body_file: None,
+            body_bytes: None,
+            trailers: None,
+        };
+        let assertion = Assertion::Sql {
+            query: "SELECT name FROM userz".into(),
+            expect: StringOrStrings::Single("Alice".into()),
+            got: Some(Err("relation \"userz\" does not exist".into())),
+            epsilon: None,
+            poll: None,
+        };
+
+        let (status, actual) = backend(&assertion).evaluate(&response);
+        assert_eq!(status, TestResult::Fail);
+        assert_eq!(
+            actual.to_string(),
+            "The query failed: relation \"userz\" does not exist"
+        );
+    }
+
+    #[test]
+    fn content_encoding_checks_header_and_body() {
+        let response = |encoding: Option<&str>, body: &[u8]| {
+            let mut headers = HeaderMap::new();
+            if let Some(encoding) = encoding {
+                headers.insert(CONTENT_ENCODING, encoding.parse().unwrap());
+            }
+            CapturedResponse {
+                status: StatusCode::OK,
+                version: Version::HTTP_11,
+                headers,
+                body_text: None,
+                body_json: None,
+                body_file: None,
+                body_bytes: Some(body.to_vec()),
+                trailers: None,
+            }
+        };
+        let assertion = |verify_body| Assertion::ContentEncoding {
+            encoding: ContentEncoding::Gzip,
+            verify_body,
+        };
+        let gzip = [0x1f, 0x8b, 0x08, 0x00];
+
+        let (status, _) = backend(&assertion(true)).evaluate(&response(Some("gzip"), &gzip));
+        assert_eq!(status, TestResult::Pass);
+
+        let (status, actual) = backend(&assertion(true)).evaluate(&response(Some("gzip"), b"{}"));
+        assert_eq!(status, TestResult::Fail);
+        assert!(matches!(
+            actual,
+            Actual::ContentEncoding {
+                body_encoded: Some(false),
+                ..
+            }
+        ));
+
+        let (status, _) = backend(&assertion(false)).evaluate(&response(Some("gzip"), b"{}"));
+        assert_eq!(status, TestResult::Pass);
+
+        let (status, actual) = backend(&assertion(false)).evaluate(&response(None, &gzip));
+        assert_eq!(status, TestResult::Fail);
+        assert!(matches!(
+            actual,
+            Actual::ContentEncoding { header: None, .. }
+        ));
+
+        assert!(ContentEncoding::Deflate.looks_encoded(&[0x78, 0x9c, 0x01]));
+        assert!(!ContentEncoding::Deflate.looks_encoded(&gzip));
+    }
+
+    #[test]
+    fn body_size_counts_bytes() {
+        let response = CapturedResponse {
+            status: StatusCode::OK,
+            version: Version::HTTP_11,
+            headers: HeaderMap::new(),
+            body_text: Some("héllo".into()),
+            body_json: None,
+            body_file: None,
+            body_bytes: None,
+            trailers: None,
+        };
+
+        let evaluate = |size: BodySize| backend(&Assertion::BodySize(size)).evaluate(&response);
+
+        let (status, actual) = evaluate(BodySize::Exact(6));
+        assert_eq!(status, TestResult::Pass);
+        assert!(matches!(actual, Actual::BodySize(6)));
+
+        let (status, _) = evaluate(BodySize::Exact(5));
+        assert_eq!(status, TestResult::Fail);
+
+        let (status, _) = evaluate(BodySize::Range {
+            min: Some(1),
+            max: Some(6),
+        });
+        assert_eq!(status, TestResult::Pass);
+
+        let (status, _) = evaluate(BodySize::Range {
+            min: None,
+            max: Some(5),
+        });
+        assert_eq!(status, TestResult::Fail);
+
+        let (status, _) = evaluate(BodySize::Range {
+            min: Some(7),
+            max: None,
+        });
+        assert_eq!(status, TestResult::Fail);
+    }
+
+    #[test]
+    fn snapshots_are_recorded_then_compared() {
+        let dir = std::env::temp_dir().join(format!("tq-snapshot-{}", uuid::Uuid::new_v4()));
+        let path = dir.join("users.json");
+        let response = |body: &str| CapturedResponse {
+            status: StatusCode::OK,
+            version: Version::HTTP_11,
+            headers: HeaderMap::new(),
+            body_text: Some(body.into()),
+            body_json: None,
+            body_file: None,
+            body_bytes: None,
+            trailers: None,
+        };
+        let assertion = |update| Assertion::Snapshot {
+            path: path.clone(),
+            update,
+        };
+
+        let (status, actual) = backend(&assertion(false)).evaluate(&response("[1, 2]"));
+        assert_eq!(status, TestResult::Pass);
+        assert!(matches!(actual, Actual::Snapshot(Snapshot::Recorded)));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "[1, 2]");
+
+        let (status, actual) = backend(&assertion(false)).evaluate(&response("[1, 2]"));
+        assert_eq!(status, TestResult::Pass);
+        assert!(matches!(actual, Actual::Snapshot(Snapshot::Matched)));
+
+        let (status, actual) = backend(&assertion(false)).evaluate(&response("[1, 3]"));
+        assert_eq!(status, TestResult::Fail);
+        assert!(matches!(actual, Actual::Snapshot(Snapshot::Differs { .. })));
+
+        let (status, _) = backend(&assertion(true)).evaluate(&response("[1, 3]"));
+        assert_eq!(status, TestResult::Pass);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "[1, 3]");
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn results_are_evaluated_once() {
+        let dir = std::env::temp_dir().join(format!("tq-snapshot-{}", uuid::Uuid::new_v4()));
+        let path = dir.join("once.json");
+        let result = RunnerResult {
+            name: "Snapshot".into(),
+            group: "group".into(),
+            method: "GET".into(),
+            url: Url::parse("http://localhost:1/").unwrap(),
+            response: Some(CapturedResponse {
+                status: StatusCode::OK,
+                version: Version::HTTP_11,
+                headers: HeaderMap::new(),
+                body_text: Some("[1]".into()),
+                body_json: None,
+                body_file: None,
+                body_bytes: None,
+                trailers: None,
+            }),
+            error: None,
+            assertions: vec![Assertion::Snapshot {
+                path: path.clone(),
+                update: false,
+            }]
+            .into(),
+            duration: Duration::ZERO,
+            location: None,
+            assertion_locations: vec![],
+            request: RequestDetails::default(),
+            ran_during: Instant::now()..Instant::now(),
+            skipped: None,
+            timed_out: None,
+            verdict: OnceLock::new(),
+        };
+
+        let first = result.assert();
+        std::fs::write(&path, "[2]").unwrap();
+        let second = result.assert();
+
+        assert!(std::sync::Arc::ptr_eq(&first, &second));
+        assert!(matches!(
+            second[0].actual,
+            Actual::Snapshot(Snapshot::Recorded)
+        ));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "[2]");
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn snapshots_compare_bodies_captured_to_file() {
+        let dir = std::env::temp_dir().join(format!("tq-snapshot-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("export.csv");
+        let response = |name: &str, body: &str| {
+            let body_path = dir.join(name);
+            std::fs::write(&body_path, body).unwrap();
+            CapturedResponse {
+                status: StatusCode::OK,
+                version: Version::HTTP_11,
+                headers: HeaderMap::new(),
+                body_text: None,
+                body_json: None,
+                body_file: Some(BodyFile {
+                    path: body_path,
+                    size: body.len() as u64,
+                }),
+                body_bytes: None,
+                trailers: None,
+            }
+        };
+        let assertion = Assertion::Snapshot {
+            path: path.clone(),
+            update: false,
+        };
+
+        let (status, actual) = backend(&assertion).evaluate(&response("first", "a,b\n1,2\n"));
+        assert_eq!(status, TestResult::Pass);
+        assert!(matches!(actual, Actual::Snapshot(Snapshot::Recorded)));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "a,b\n1,2\n");
+
+        let (status, actual) = backend(&assertion).evaluate(&response("same", "a,b\n1,2\n"));
+        assert_eq!(status, TestResult::Pass);
+        assert!(matches!(actual, Actual::Snapshot(Snapshot::Matched)));
+
+        let (status, actual) = backend(&assertion).evaluate(&response("other", "a,b\n1,3\n"));
+        assert_eq!(status, TestResult::Fail);
+        assert!(matches!(
+            actual,
+            Actual::Snapshot(Snapshot::DiffersFromFile { body_file }) if body_file == dir.join("other")
+        ));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn body_files_are_only_kept_for_failed_tests() {
+        let dir = std::env::temp_dir().join(format!("tq-body-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (runner_tx, asserter_rx) = flume::unbounded::<RunnerResult>();
+        let (asserter_tx, outputter_rx) = flume::unbounded::<TestOutcome>();
+        let result = |name: &str, status: i32| {
+            let path = dir.join(name);
+            std::fs::write(&path, "body").unwrap();
+            RunnerResult {
+                name: name.into(),
+                group: "group".into(),
+                method: "GET".into(),
+                url: Url::parse("http://localhost:1/").unwrap(),
+                response: Some(CapturedResponse {
+                    status: StatusCode::OK,
+                    version: Version::HTTP_11,
+                    headers: HeaderMap::new(),
+                    body_text: None,
+                    body_json: None,
+                    body_file: Some(BodyFile { path, size: 4 }),
+                    body_bytes: None,
+                    trailers: None,
+                }),
+                error: None,
+                assertions: vec![Assertion::Status(status)].into(),
+                duration: Duration::ZERO,
+                location: None,
+                assertion_locations: vec![],
+                request: RequestDetails::default(),
+                ran_during: Instant::now()..Instant::now(),
+                skipped: None,
+                timed_out: None,
+                verdict: OnceLock::new(),
+            }
+        };
+
+        runner_tx.send(result("passed", 200)).unwrap();
+        runner_tx.send(result("failed", 404)).unwrap();
+        drop(runner_tx);
+        Asserter::run(asserter_rx, asserter_tx).await.unwrap();
+
+        assert_eq!(outputter_rx.drain().count(), 2);
+        assert!(!dir.join("passed").exists());
+        assert!(dir.join("failed").exists());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn redirects_match_on_final_url_and_hops() {
+        let response = CapturedResponse {
+            status: StatusCode::OK,
+            version: Version::HTTP_11,
+            headers: HeaderMap::new(),
+            body_text: None,
+            body_json: None,
+            body_file: None,
+            body_bytes: None,
+            trailers: None,
+        };
+        let chain = vec![
+            Url::parse("http://localhost:6969/old").unwrap(),
+            Url::parse("http://localhost:6969/login").unwrap(),
+            Url::parse("http://localhost:6969/dashboard").unwrap(),
+        ];
+        let evaluate = |url: Option<&str>, hops| {
+            let assertion = Assertion::Redirect {
+                url: url.map(String::from),
+                hops,
+                got: Some(chain.clone()),
+            };
+            backend(&assertion).evaluate(&response).0
+        };
+
+        assert_eq!(evaluate(Some("/dashboard"), Some(2)), TestResult::Pass);
+        assert_eq!(
+            evaluate(Some("http://localhost:6969/dashboard"), None),
+            TestResult::Pass
+        );
+        assert_eq!(evaluate(Some("/login"), None), TestResult::Fail);
+        assert_eq!(evaluate(None, Some(1)), TestResult::Fail);
+    }
 }
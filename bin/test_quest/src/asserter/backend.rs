@@ -0,0 +1,648 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use reqwest::Version;
+use reqwest::header::CONTENT_ENCODING;
+use reqwest::header::HeaderName;
+use url::Url;
+
+use crate::asserter::Actual;
+use crate::asserter::Snapshot;
+use crate::asserter::TestResult;
+use crate::asserter::assert_cookie;
+use crate::asserter::assert_empty_body;
+use crate::asserter::assert_header;
+use crate::asserter::assert_json;
+use crate::asserter::assert_sql;
+use crate::asserter::assert_status;
+use crate::asserter::assert_valid_json;
+use crate::asserter::compare_json;
+use crate::asserter::compare_sql_value;
+use crate::asserter::find_cookie;
+use crate::asserter::header_int;
+use crate::asserter::json_array_len;
+use crate::asserter::json_matches;
+use crate::asserter::missing_json_paths;
+use crate::asserter::redirect_matches;
+use crate::asserter::without_keys;
+use crate::parser::NumberOrString;
+use crate::parser::StringOrStrings;
+use crate::runner::CapturedResponse;
+use crate::setup::database::any_db::SqlRows;
+use crate::validator::Assertion;
+use crate::validator::BodySize;
+use crate::validator::Comparison;
+use crate::validator::ContentEncoding;
+use crate::validator::ExpectedCookie;
+use crate::validator::HeaderAssertion;
+use crate::validator::JsonOperand;
+use crate::validator::JsonPath;
+use crate::validator::WsMessage;
+
+/// Evaluates one kind of assertion against a response. Every `Assertion`
+/// variant has an implementation, so assertions for other protocols can be
+/// added next to the HTTP ones.
+pub trait AssertionBackend {
+    /// Whether the assertion holds for `response`, and what was found instead
+    /// of the expected value.
+    fn evaluate(&self, response: &CapturedResponse) -> (TestResult, Actual);
+}
+
+/// The backend that evaluates `assertion`.
+pub fn backend(assertion: &Assertion) -> Box<dyn AssertionBackend + '_> {
+    match assertion {
+        Assertion::Status(expected) => Box::new(StatusAssertion(*expected)),
+        Assertion::Headers(expected) => Box::new(HeadersAssertion(expected)),
+        Assertion::Trailers(expected) => Box::new(TrailersAssertion(expected)),
+        Assertion::HeaderInt { name, op, value } => Box::new(HeaderIntAssertion {
+            name,
+            op: *op,
+            value: *value,
+        }),
+        Assertion::HttpVersion(expected) => Box::new(HttpVersionAssertion(*expected)),
+        Assertion::ContentEncoding {
+            encoding,
+            verify_body,
+        } => Box::new(ContentEncodingAssertion {
+            encoding: *encoding,
+            verify_body: *verify_body,
+        }),
+        Assertion::Sql {
+            expect,
+            got,
+            epsilon,
+            ..
+        } => Box::new(SqlAssertion {
+            expect,
+            got: got.as_ref(),
+            epsilon: *epsilon,
+        }),
+        Assertion::SqlCompare { op, value, got, .. } => Box::new(SqlCompareAssertion {
+            op: *op,
+            value,
+            got: got.as_ref(),
+        }),
+        Assertion::JsonAnyOf(alternatives) => Box::new(JsonAnyOfAssertion { alternatives }),
+        Assertion::Json {
+            expected,
+            epsilon,
+            ignore_keys,
+        } => Box::new(JsonAssertion {
+            expected,
+            epsilon: *epsilon,
+            ignore_keys,
+        }),
+        Assertion::JsonArrayLen { path, expected } => Box::new(JsonArrayLenAssertion {
+            path,
+            expected: *expected,
+        }),
+        Assertion::JsonHas(paths) => Box::new(JsonHasAssertion { paths }),
+        Assertion::JsonEqualsVar { var, captured } => Box::new(JsonEqualsVarAssertion {
+            var,
+            captured: captured.as_ref(),
+        }),
+        Assertion::JsonRelation { left, op, right } => Box::new(JsonRelationAssertion {
+            left,
+            op: *op,
+            right,
+        }),
+        Assertion::Cookie(expected) => Box::new(expected),
+        Assertion::EmptyBody => Box::new(EmptyBodyAssertion),
+        Assertion::ValidJson => Box::new(ValidJsonAssertion),
+        Assertion::BodySize(expected) => Box::new(*expected),
+        Assertion::Snapshot { path, update } => Box::new(SnapshotAssertion {
+            path,
+            update: *update,
+        }),
+        Assertion::Redirect { url, hops, got } => Box::new(RedirectAssertion {
+            url: url.as_deref(),
+            hops: *hops,
+            got: got.as_deref().unwrap_or_default(),
+        }),
+        Assertion::RequestError(_) => Box::new(RequestErrorAssertion),
+        Assertion::WsMessage(expected) => Box::new(expected),
+        Assertion::NoServerError => Box::new(NoServerErrorAssertion),
+        Assertion::RequestFailed | Assertion::GroupTimeout(_) => {
+            unreachable!("{assertion} is only created by the asserter itself")
+        }
+    }
+}
+
+pub struct StatusAssertion(pub i32);
+
+impl AssertionBackend for StatusAssertion {
+    fn evaluate(&self, response: &CapturedResponse) -> (TestResult, Actual) {
+        (
+            assert_status(&self.0, response.status),
+            Actual::Status(response.status),
+        )
+    }
+}
+
+pub struct HeadersAssertion<'a>(pub &'a [HeaderAssertion]);
+
+impl AssertionBackend for HeadersAssertion<'_> {
+    fn evaluate(&self, response: &CapturedResponse) -> (TestResult, Actual) {
+        (
+            assert_header(self.0, &response.headers),
+            Actual::Header(response.headers.clone()),
+        )
+    }
+}
+
+/// Checks the trailers like `HeadersAssertion` checks the headers. A response
+/// without trailers fails: servers, proxies and HTTP/1.0 connections that
+/// don't support trailers drop them silently.
+pub struct TrailersAssertion<'a>(pub &'a [HeaderAssertion]);
+
+impl AssertionBackend for TrailersAssertion<'_> {
+    fn evaluate(&self, response: &CapturedResponse) -> (TestResult, Actual) {
+        let result = match &response.trailers {
+            Some(trailers) => assert_header(self.0, trailers),
+            None => TestResult::Fail,
+        };
+        (result, Actual::Trailers(response.trailers.clone()))
+    }
+}
+
+pub struct HttpVersionAssertion(pub Version);
+
+impl AssertionBackend for HttpVersionAssertion {
+    fn evaluate(&self, response: &CapturedResponse) -> (TestResult, Actual) {
+        let result = if response.version == self.0 {
+            TestResult::Pass
+        } else {
+            TestResult::Fail
+        };
+        (result, Actual::HttpVersion(response.version))
+    }
+}
+
+pub struct HeaderIntAssertion<'a> {
+    pub name: &'a HeaderName,
+    pub op: Comparison,
+    pub value: i64,
+}
+
+impl AssertionBackend for HeaderIntAssertion<'_> {
+    fn evaluate(&self, response: &CapturedResponse) -> (TestResult, Actual) {
+        let actual = header_int(&response.headers, self.name);
+        let result = match actual {
+            Ok(actual) if self.op.holds(actual.cmp(&self.value)) => TestResult::Pass,
+            _ => TestResult::Fail,
+        };
+
+        (result, Actual::HeaderInt(actual))
+    }
+}
+
+pub struct ContentEncodingAssertion {
+    pub encoding: ContentEncoding,
+    pub verify_body: bool,
+}
+
+impl AssertionBackend for ContentEncodingAssertion {
+    fn evaluate(&self, response: &CapturedResponse) -> (TestResult, Actual) {
+        let header = response
+            .headers
+            .get(CONTENT_ENCODING)
+            .map(|v| v.to_str().unwrap_or("<invalid utf8>").to_string());
+        let body_encoded = self
+            .verify_body
+            .then(|| body_start(response).is_ok_and(|start| self.encoding.looks_encoded(&start)));
+
+        let result = match (&header, body_encoded) {
+            (Some(header), None | Some(true))
+                if header.trim().eq_ignore_ascii_case(self.encoding.as_str()) =>
+            {
+                TestResult::Pass
+            }
+            _ => TestResult::Fail,
+        };
+
+        (
+            result,
+            Actual::ContentEncoding {
+                header,
+                body_encoded,
+            },
+        )
+    }
+}
+
+/// The first bytes of the body as it was sent, enough to recognize a
+/// compression header.
+fn body_start(response: &CapturedResponse) -> std::io::Result<Vec<u8>> {
+    const LEN: usize = 2;
+
+    if let Some(file) = &response.body_file {
+        let mut start = Vec::with_capacity(LEN);
+        File::open(&file.path)?
+            .take(LEN as u64)
+            .read_to_end(&mut start)?;
+        return Ok(start);
+    }
+
+    let bytes = response.body_bytes.as_deref().unwrap_or_default();
+    Ok(bytes[..bytes.len().min(LEN)].to_vec())
+}
+
+/// The rows are queried by the runner, so only `got` is compared here.
+pub struct SqlAssertion<'a> {
+    pub expect: &'a StringOrStrings,
+    pub got: Option<&'a Result<SqlRows, String>>,
+    pub epsilon: Option<f64>,
+}
+
+impl AssertionBackend for SqlAssertion<'_> {
+    fn evaluate(&self, _response: &CapturedResponse) -> (TestResult, Actual) {
+        match self.got {
+            Some(Err(error)) => (TestResult::Fail, Actual::Sql(Err(error.clone()))),
+            got => {
+                let rows = got.and_then(|got| got.as_ref().ok());
+                (
+                    assert_sql(self.expect, rows, self.epsilon),
+                    Actual::Sql(Ok(rows.map(SqlRows::lines).unwrap_or_default())),
+                )
+            }
+        }
+    }
+}
+
+pub struct SqlCompareAssertion<'a> {
+    pub op: Comparison,
+    pub value: &'a NumberOrString,
+    pub got: Option<&'a Result<String, String>>,
+}
+
+impl AssertionBackend for SqlCompareAssertion<'_> {
+    fn evaluate(&self, _response: &CapturedResponse) -> (TestResult, Actual) {
+        let got = self
+            .got
+            .cloned()
+            .unwrap_or_else(|| Err("The query wasn't run".into()))
+            .and_then(|got| compare_sql_value(&got, self.value).map(|ordering| (got, ordering)));
+
+        match got {
+            Ok((got, ordering)) if self.op.holds(ordering) => {
+                (TestResult::Pass, Actual::SqlCompare(Ok(got)))
+            }
+            Ok((got, _)) => (TestResult::Fail, Actual::SqlCompare(Ok(got))),
+            Err(error) => (TestResult::Fail, Actual::SqlCompare(Err(error))),
+        }
+    }
+}
+
+pub struct JsonAssertion<'a> {
+    pub expected: &'a serde_json::Value,
+    pub epsilon: Option<f64>,
+    pub ignore_keys: &'a [String],
+}
+
+impl AssertionBackend for JsonAssertion<'_> {
+    fn evaluate(&self, response: &CapturedResponse) -> (TestResult, Actual) {
+        let expected = without_keys(self.expected, self.ignore_keys);
+        let got = response
+            .body_json
+            .as_ref()
+            .map(|body| without_keys(body, self.ignore_keys));
+        (
+            assert_json(&expected, got.as_ref(), self.epsilon),
+            Actual::Json(got.unwrap_or_default()),
+        )
+    }
+}
+
+pub struct JsonAnyOfAssertion<'a> {
+    pub alternatives: &'a [serde_json::Value],
+}
+
+impl AssertionBackend for JsonAnyOfAssertion<'_> {
+    fn evaluate(&self, response: &CapturedResponse) -> (TestResult, Actual) {
+        let matched = response.body_json.as_ref().is_some_and(|got| {
+            self.alternatives
+                .iter()
+                .any(|expected| json_matches(expected, got, None))
+        });
+        let result = if matched {
+            TestResult::Pass
+        } else {
+            TestResult::Fail
+        };
+
+        (
+            result,
+            Actual::Json(response.body_json.clone().unwrap_or_default()),
+        )
+    }
+}
+
+pub struct JsonArrayLenAssertion<'a> {
+    pub path: &'a JsonPath,
+    pub expected: usize,
+}
+
+impl AssertionBackend for JsonArrayLenAssertion<'_> {
+    fn evaluate(&self, response: &CapturedResponse) -> (TestResult, Actual) {
+        let len = json_array_len(self.path, response.body_json.as_ref());
+        let result = match len {
+            Ok(len) if len == self.expected => TestResult::Pass,
+            _ => TestResult::Fail,
+        };
+
+        (result, Actual::JsonArrayLen(len))
+    }
+}
+
+pub struct JsonHasAssertion<'a> {
+    pub paths: &'a [JsonPath],
+}
+
+impl AssertionBackend for JsonHasAssertion<'_> {
+    fn evaluate(&self, response: &CapturedResponse) -> (TestResult, Actual) {
+        let missing = missing_json_paths(self.paths, response.body_json.as_ref());
+        let result = match &missing {
+            Ok(missing) if missing.is_empty() => TestResult::Pass,
+            _ => TestResult::Fail,
+        };
+
+        (result, Actual::JsonHas(missing))
+    }
+}
+
+pub struct JsonEqualsVarAssertion<'a> {
+    pub var: &'a str,
+    pub captured: Option<&'a serde_json::Value>,
+}
+
+impl AssertionBackend for JsonEqualsVarAssertion<'_> {
+    fn evaluate(&self, response: &CapturedResponse) -> (TestResult, Actual) {
+        let got = match (self.captured, &response.body_json) {
+            (None, _) => Err(format!(
+                "Nothing was stored as `{}`, the test capturing it didn't run or got no JSON body",
+                self.var
+            )),
+            (Some(_), None) => Err("Response body is not JSON".to_string()),
+            (Some(captured), Some(body)) if json_kind(captured) != json_kind(body) => Err(format!(
+                "Expected {} like `{}`, got {}",
+                json_kind(captured),
+                self.var,
+                json_kind(body)
+            )),
+            (Some(_), Some(body)) => Ok(body.clone()),
+        };
+        let result = match &got {
+            Ok(body) if Some(body) == self.captured => TestResult::Pass,
+            _ => TestResult::Fail,
+        };
+
+        (result, Actual::JsonEqualsVar(got))
+    }
+}
+
+/// What kind of JSON value `value` is, e.g. `an object`.
+fn json_kind(value: &serde_json::Value) -> &'static str {
+    match value {
+        serde_json::Value::Null => "null",
+        serde_json::Value::Bool(_) => "a boolean",
+        serde_json::Value::Number(_) => "a number",
+        serde_json::Value::String(_) => "a string",
+        serde_json::Value::Array(_) => "an array",
+        serde_json::Value::Object(_) => "an object",
+    }
+}
+
+pub struct JsonRelationAssertion<'a> {
+    pub left: &'a JsonOperand,
+    pub op: Comparison,
+    pub right: &'a JsonOperand,
+}
+
+impl AssertionBackend for JsonRelationAssertion<'_> {
+    fn evaluate(&self, response: &CapturedResponse) -> (TestResult, Actual) {
+        let resolve = |operand: &JsonOperand| {
+            let body = response
+                .body_json
+                .as_ref()
+                .ok_or("Response body is not JSON")?;
+            operand.resolve(body)
+        };
+        let (left, right) = (resolve(self.left), resolve(self.right));
+
+        let result = match (&left, &right) {
+            (Ok(left), Ok(right))
+                if compare_json(left, right).is_some_and(|ordering| self.op.holds(ordering)) =>
+            {
+                TestResult::Pass
+            }
+            _ => TestResult::Fail,
+        };
+
+        (result, Actual::JsonRelation { left, right })
+    }
+}
+
+impl AssertionBackend for &ExpectedCookie {
+    fn evaluate(&self, response: &CapturedResponse) -> (TestResult, Actual) {
+        let cookie = find_cookie(&response.headers, &self.name);
+
+        (assert_cookie(self, cookie.as_ref()), Actual::Cookie(cookie))
+    }
+}
+
+pub struct EmptyBodyAssertion;
+
+impl AssertionBackend for EmptyBodyAssertion {
+    fn evaluate(&self, response: &CapturedResponse) -> (TestResult, Actual) {
+        (
+            assert_empty_body(response.body_text.as_deref()),
+            Actual::Body(response.body_text.clone().unwrap_or_default()),
+        )
+    }
+}
+
+/// The runner puts the message a WebSocket test received in the body.
+impl AssertionBackend for &WsMessage {
+    fn evaluate(&self, response: &CapturedResponse) -> (TestResult, Actual) {
+        let message = response.body_text.as_deref();
+        let matches = match (self, message) {
+            (_, None) => false,
+            (WsMessage::Equals(expected), Some(message)) => message == expected,
+            (WsMessage::Contains(expected), Some(message)) => message.contains(expected.as_str()),
+            (WsMessage::Json(expected), Some(_)) => response
+                .body_json
+                .as_ref()
+                .is_some_and(|got| json_matches(expected, got, None)),
+        };
+        let result = if matches {
+            TestResult::Pass
+        } else {
+            TestResult::Fail
+        };
+
+        (result, Actual::WsMessage(message.map(String::from)))
+    }
+}
+
+/// Only evaluated when the request got a response, which is exactly what it
+/// expects not to happen. A failed request is matched by the asserter.
+pub struct NoServerErrorAssertion;
+
+impl AssertionBackend for NoServerErrorAssertion {
+    fn evaluate(&self, response: &CapturedResponse) -> (TestResult, Actual) {
+        let result = if response.status.is_server_error() {
+            TestResult::Fail
+        } else {
+            TestResult::Pass
+        };
+        (
+            result,
+            Actual::ServerError {
+                status: response.status,
+                body: response.body_text.clone(),
+            },
+        )
+    }
+}
+
+pub struct RequestErrorAssertion;
+
+impl AssertionBackend for RequestErrorAssertion {
+    fn evaluate(&self, response: &CapturedResponse) -> (TestResult, Actual) {
+        (TestResult::Fail, Actual::Status(response.status))
+    }
+}
+
+pub struct ValidJsonAssertion;
+
+impl AssertionBackend for ValidJsonAssertion {
+    fn evaluate(&self, response: &CapturedResponse) -> (TestResult, Actual) {
+        let parsed = assert_valid_json(response.body_text.as_deref());
+        let result = if parsed.is_ok() {
+            TestResult::Pass
+        } else {
+            TestResult::Fail
+        };
+
+        (result, Actual::ValidJson(parsed))
+    }
+}
+
+/// Compares the length of the body in bytes, not in characters.
+impl AssertionBackend for BodySize {
+    fn evaluate(&self, response: &CapturedResponse) -> (TestResult, Actual) {
+        let bytes = response.body_size();
+        let result = if self.contains(bytes) {
+            TestResult::Pass
+        } else {
+            TestResult::Fail
+        };
+
+        (result, Actual::BodySize(bytes))
+    }
+}
+
+/// Compares the body to a snapshot file, or records the body in it when the
+/// file doesn't exist yet or `update` is set.
+pub struct SnapshotAssertion<'a> {
+    pub path: &'a Path,
+    pub update: bool,
+}
+
+impl AssertionBackend for SnapshotAssertion<'_> {
+    fn evaluate(&self, response: &CapturedResponse) -> (TestResult, Actual) {
+        let body = response.body_text.as_deref().unwrap_or_default();
+
+        let snapshot = if let Some(file) = &response.body_file {
+            if self.update || !self.path.exists() {
+                record_snapshot_file(self.path, &file.path).map(|()| Snapshot::Recorded)
+            } else {
+                files_equal(self.path, &file.path).map(|equal| {
+                    if equal {
+                        Snapshot::Matched
+                    } else {
+                        Snapshot::DiffersFromFile {
+                            body_file: file.path.clone(),
+                        }
+                    }
+                })
+            }
+        } else if self.update || !self.path.exists() {
+            record_snapshot(self.path, body).map(|()| Snapshot::Recorded)
+        } else {
+            std::fs::read_to_string(self.path).map(|snapshot| {
+                if snapshot == body {
+                    Snapshot::Matched
+                } else {
+                    Snapshot::Differs {
+                        snapshot,
+                        body: body.to_string(),
+                    }
+                }
+            })
+        };
+
+        match snapshot {
+            Ok(snapshot @ (Snapshot::Matched | Snapshot::Recorded)) => {
+                (TestResult::Pass, Actual::Snapshot(snapshot))
+            }
+            Ok(snapshot) => (TestResult::Fail, Actual::Snapshot(snapshot)),
+            Err(error) => (
+                TestResult::Fail,
+                Actual::Snapshot(Snapshot::Error(error.to_string())),
+            ),
+        }
+    }
+}
+
+/// The redirects are followed by the runner, so only `got` is compared here.
+pub struct RedirectAssertion<'a> {
+    pub url: Option<&'a str>,
+    pub hops: Option<usize>,
+    pub got: &'a [Url],
+}
+
+impl AssertionBackend for RedirectAssertion<'_> {
+    fn evaluate(&self, _response: &CapturedResponse) -> (TestResult, Actual) {
+        (
+            redirect_matches(self.url, self.hops, self.got),
+            Actual::Redirect(self.got.to_vec()),
+        )
+    }
+}
+
+fn record_snapshot(path: &Path, body: &str) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, body)
+}
+
+fn record_snapshot_file(path: &Path, body_file: &Path) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::copy(body_file, path).map(|_| ())
+}
+
+/// Whether the files at `a` and `b` have the same contents. They are compared
+/// a chunk at a time, so neither has to fit in memory.
+fn files_equal(a: &Path, b: &Path) -> std::io::Result<bool> {
+    if std::fs::metadata(a)?.len() != std::fs::metadata(b)?.len() {
+        return Ok(false);
+    }
+
+    let (mut a, mut b) = (File::open(a)?, File::open(b)?);
+    let (mut chunk_a, mut chunk_b) = ([0; 8192], [0; 8192]);
+    loop {
+        let n = a.read(&mut chunk_a)?;
+        if n == 0 {
+            return Ok(true);
+        }
+        b.read_exact(&mut chunk_b[..n])?;
+        if chunk_a[..n] != chunk_b[..n] {
+            return Ok(false);
+        }
+    }
+}
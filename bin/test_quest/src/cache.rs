@@ -0,0 +1,143 @@
+use std::collections::BTreeMap;
+use std::collections::HashSet;
+use std::path::Path;
+
+use thiserror::Error;
+
+use crate::validator::IR;
+
+/// Where the failed tests of the last run of every config are kept, relative
+/// to the directory test_quest is run from.
+const CACHE_FILE: &str = ".test_quest/failed.json";
+
+/// A test as it is recognised across runs: its name, method and URL path.
+pub type TestKey = (String, String, String);
+
+/// The failed tests of each config, keyed on the config's canonical path so
+/// different suites don't overwrite each other.
+type Cache = BTreeMap<String, Vec<TestKey>>;
+
+#[derive(Error, Debug)]
+pub enum CacheError {
+    #[error("failed to access {CACHE_FILE}: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("failed to parse {CACHE_FILE}: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+/// The tests that failed the last time the config at `config_path` ran, or
+/// `None` if it hasn't run yet.
+pub fn load_failed(config_path: &str) -> Result<Option<HashSet<TestKey>>, CacheError> {
+    load_failed_from(Path::new(CACHE_FILE), config_path)
+}
+
+/// Replaces the failed tests recorded for the config at `config_path`.
+pub fn save_failed(config_path: &str, failed: &HashSet<TestKey>) -> Result<(), CacheError> {
+    save_failed_to(Path::new(CACHE_FILE), config_path, failed)
+}
+
+/// Drops every test that isn't in `failed`, along with groups left without
+/// tests.
+pub fn retain_failed(ir: &mut IR, failed: &HashSet<TestKey>) {
+    for group in &mut ir.tests {
+        group.tests.retain(|test| {
+            let key = (
+                test.name.clone(),
+                test.method.to_string(),
+                test.url.path().to_string(),
+            );
+            failed.contains(&key)
+        });
+    }
+    ir.tests.retain(|group| !group.tests.is_empty());
+}
+
+fn load_failed_from(
+    cache_file: &Path,
+    config_path: &str,
+) -> Result<Option<HashSet<TestKey>>, CacheError> {
+    if !cache_file.exists() {
+        return Ok(None);
+    }
+
+    let mut cache = read_cache(cache_file)?;
+    Ok(cache
+        .remove(&cache_key(config_path))
+        .map(|tests| tests.into_iter().collect()))
+}
+
+fn save_failed_to(
+    cache_file: &Path,
+    config_path: &str,
+    failed: &HashSet<TestKey>,
+) -> Result<(), CacheError> {
+    let mut cache = if cache_file.exists() {
+        read_cache(cache_file)?
+    } else {
+        Cache::new()
+    };
+
+    let mut failed: Vec<TestKey> = failed.iter().cloned().collect();
+    failed.sort();
+    cache.insert(cache_key(config_path), failed);
+
+    if let Some(dir) = cache_file.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(cache_file, serde_json::to_string_pretty(&cache)?)?;
+
+    Ok(())
+}
+
+fn read_cache(cache_file: &Path) -> Result<Cache, CacheError> {
+    let contents = std::fs::read_to_string(cache_file)?;
+    Ok(serde_json::from_str(&contents)?)
+}
+
+/// The canonical form of `config_path`, so `./a.toml` and `a.toml` share an
+/// entry.
+fn cache_key(config_path: &str) -> String {
+    Path::new(config_path)
+        .canonicalize()
+        .map(|path| path.display().to_string())
+        .unwrap_or_else(|_| config_path.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use crate::cache::TestKey;
+    use crate::cache::load_failed_from;
+    use crate::cache::save_failed_to;
+
+    #[test]
+    fn failed_tests_are_kept_per_config() {
+        let dir = std::env::temp_dir().join(format!("tq-cache-{}", uuid::Uuid::new_v4()));
+        let cache_file = dir.join("failed.json");
+        let key = |name: &str| -> TestKey { (name.into(), "GET".into(), "/users".into()) };
+
+        assert_eq!(load_failed_from(&cache_file, "a.toml").unwrap(), None);
+
+        save_failed_to(&cache_file, "a.toml", &HashSet::from([key("GetUser")])).unwrap();
+        save_failed_to(&cache_file, "b.toml", &HashSet::from([key("ListUsers")])).unwrap();
+
+        assert_eq!(
+            load_failed_from(&cache_file, "a.toml").unwrap(),
+            Some(HashSet::from([key("GetUser")]))
+        );
+        assert_eq!(
+            load_failed_from(&cache_file, "b.toml").unwrap(),
+            Some(HashSet::from([key("ListUsers")]))
+        );
+
+        save_failed_to(&cache_file, "a.toml", &HashSet::new()).unwrap();
+        assert_eq!(
+            load_failed_from(&cache_file, "a.toml").unwrap(),
+            Some(HashSet::new())
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,10 +1,55 @@
+use std::path::PathBuf;
+
+use clap::Args;
 use clap::Parser;
+use clap::Subcommand;
+use clap::ValueEnum;
+use tracing::level_filters::LevelFilter;
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    /// Human readable output
+    #[default]
+    Pretty,
+    /// Pretty output plus GitHub Actions annotations for failed assertions
+    Github,
+}
+
+/// How much of test_quest's own diagnostics to print to stderr.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogLevel {
+    Off,
+    Error,
+    #[default]
+    Warn,
+    /// Setup and teardown progress
+    Info,
+    /// Details like the migrations that run and every database readiness check
+    Debug,
+    Trace,
+}
+
+impl From<LogLevel> for LevelFilter {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Off => LevelFilter::OFF,
+            LogLevel::Error => LevelFilter::ERROR,
+            LogLevel::Warn => LevelFilter::WARN,
+            LogLevel::Info => LevelFilter::INFO,
+            LogLevel::Debug => LevelFilter::DEBUG,
+            LogLevel::Trace => LevelFilter::TRACE,
+        }
+    }
+}
 
 /// Simple program to greet a person
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Clone)]
 #[command(version, about, long_about = None)]
 pub struct Cli {
-    /// Name of the person to greet
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Path to the config file, or to a directory of config files
     #[arg(short, long, default_value = "test_quest/test_quest.toml")]
     pub path: String,
 
@@ -12,6 +57,11 @@ pub struct Cli {
     #[arg(short = 'a', long)]
     pub app_output: bool,
 
+    /// After a failed test, print the app stdout and stderr logged while that
+    /// test ran
+    #[arg(long)]
+    pub app_output_on_fail: bool,
+
     /// DB stdout and stderr at the end
     #[arg(short = 'd', long)]
     pub db_output: bool,
@@ -20,4 +70,172 @@ pub struct Cli {
     /// while be printed as it comes
     #[arg(long)]
     pub stream_app: bool,
+
+    /// Print the database container's stdout and stderr as it comes, like
+    /// --stream-app does for the application. Without it only the last lines
+    /// of the container's logs are printed, and only if the database fails to
+    /// become ready
+    #[arg(long)]
+    pub stream_db: bool,
+
+    /// Leave the database container running after the run so its state can be
+    /// inspected. It has to be stopped by hand afterwards
+    #[arg(long)]
+    pub keep_container: bool,
+
+    /// Print the setup and every test as they will run, after defaults,
+    /// globals and per-test overrides are applied, without running anything
+    #[arg(long)]
+    pub config_check: bool,
+
+    /// Print every group and test found in the config without running them
+    #[arg(long)]
+    pub list: bool,
+
+    /// Run the whole suite this many times against the same app and database,
+    /// reporting tests that only pass some of the time as flaky
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    pub repeat: u32,
+
+    /// Rerun the whole suite against the same app and database until a test
+    /// fails, stopping at that run. Gives up after MAX runs if it is set
+    #[arg(
+        long,
+        value_name = "MAX",
+        num_args = 0..=1,
+        conflicts_with = "repeat",
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    pub repeat_until_failure: Option<Option<u32>>,
+
+    /// Run up to N test groups at the same time. Groups share the app and
+    /// database, so only use this when they don't depend on each other's data.
+    /// Groups whose hooks reset the database always run on their own
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    pub parallel_groups: u32,
+
+    /// Allow at most N requests and SQL queries in flight at once across the
+    /// whole run, on top of `[setup] concurrency` and --parallel-groups, which
+    /// decide how many tests may run at the same time. Unlimited by default
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    pub max_concurrency: Option<u32>,
+
+    /// Only run the tests that failed the last time this config ran, or every
+    /// test if none did
+    #[arg(long)]
+    pub only_failed: bool,
+
+    /// Overwrite the files of every assert_body_snapshot with the body the
+    /// test got instead of comparing against them
+    #[arg(long)]
+    pub update_snapshots: bool,
+
+    /// Run the tests of every group in a random order to catch tests that
+    /// depend on each other. Tests with a before_run hook keep their place
+    #[arg(long)]
+    pub shuffle: bool,
+
+    /// Seed for --shuffle, a random one is picked and printed if not given
+    #[arg(long, requires = "shuffle")]
+    pub seed: Option<u64>,
+
+    /// Keep the app and database running and rerun the suite whenever the
+    /// config file, its init sql or its migrations change
+    #[arg(long)]
+    pub watch: bool,
+
+    /// Print the N slowest tests after the run, 5 if no count is given
+    #[arg(long, num_args = 0..=1, default_missing_value = "5")]
+    pub slowest: Option<usize>,
+
+    /// Also write the results as a JUnit XML report to this file
+    #[arg(long)]
+    pub junit: Option<PathBuf>,
+
+    /// Name of the `<testsuites>` element of the JUnit report
+    #[arg(long, default_value = "test_quest")]
+    pub junit_suite_name: String,
+
+    /// Write the JSON and JUnit reports, the app output and the resolved
+    /// config of every run to a timestamped folder in this directory. The
+    /// config's secrets are redacted unless --show-secrets is set
+    #[arg(long, value_name = "DIR")]
+    pub report_dir: Option<PathBuf>,
+
+    /// Only print failed tests and the final summary
+    #[arg(short, long)]
+    pub quiet: bool,
+
+    /// Disable colored output, this is also done when NO_COLOR is set
+    #[arg(long)]
+    pub no_color: bool,
+
+    /// Print a curl command reproducing the request of every failed test
+    #[arg(long)]
+    pub show_curl: bool,
+
+    /// Don't redact credentials like the Authorization header in --show-curl
+    /// or the database password in --verbose
+    #[arg(long)]
+    pub show_secrets: bool,
+
+    /// Print setup and teardown progress, and extra setup details like the
+    /// database URL and a command to connect to it
+    #[arg(short, long)]
+    pub verbose: bool,
+
+    /// How much of test_quest's own diagnostics to print. Defaults to warn,
+    /// or info with --verbose
+    #[arg(long, value_enum)]
+    pub log_level: Option<LogLevel>,
+
+    /// Output format of the test results
+    #[arg(long, value_enum, default_value_t = OutputFormat::Pretty)]
+    pub format: OutputFormat,
+}
+
+impl Cli {
+    /// The `--log-level` given, or the default for `--verbose`.
+    pub fn log_level(&self) -> LogLevel {
+        match self.log_level {
+            Some(level) => level,
+            None if self.verbose => LogLevel::Info,
+            None => LogLevel::default(),
+        }
+    }
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Generate a starter config file
+    Init(InitArgs),
+    /// Convert tests from another tool into a config file
+    Import(ImportArgs),
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct InitArgs {
+    /// URL or path of an OpenAPI 3 document to generate one test per operation
+    /// from. Without it a commented starter config is written instead
+    #[arg(long)]
+    pub openapi: Option<String>,
+
+    /// Where to write the generated config
+    #[arg(short, long, default_value = "test_quest.toml")]
+    pub output: PathBuf,
+
+    /// Overwrite the config if it already exists
+    #[arg(long)]
+    pub force: bool,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ImportArgs {
+    /// Path of a Postman v2.1 collection to convert
+    #[arg(long)]
+    pub postman: PathBuf,
+
+    /// Where to write the generated config
+    #[arg(short, long, default_value = "test_quest.toml")]
+    pub output: PathBuf,
 }
@@ -0,0 +1,572 @@
+use std::path::Path;
+use std::path::PathBuf;
+
+use serde::de::DeserializeOwned;
+
+use crate::TestQuestError;
+use crate::parser::ConfigFragment;
+use crate::parser::Setup;
+use crate::parser::TestQuest;
+use crate::validator::EnvSetup;
+use crate::validator::IR;
+use crate::validator::Validator;
+
+/// Name of the file that holds the shared `[setup]` and `[db]` when `--path`
+/// points at a directory.
+const MAIN_CONFIG: &str = "main.toml";
+
+/// Replaced with the port picked for the app when `[setup] port_env` is set.
+const PORT_PLACEHOLDER: &str = "{port}";
+
+/// Reads the config at `path`, deserializes it into a `TestQuest` and runs the
+/// validation pass over it. Returns the validated tests, the number of test
+/// groups and the environment setup.
+///
+/// `path` can either be a single config file or a directory of them, see
+/// `load_dir`. `port` takes the place of `{port}` when `port_env` is set, and
+/// is picked once by the caller so a reloaded config keeps pointing at the
+/// app that is already running.
+pub fn load_config(path: &str, port: u16) -> Result<(IR, usize, EnvSetup), TestQuestError> {
+    if Path::new(path).is_dir() {
+        return load_dir(Path::new(path), port);
+    }
+
+    let contents = read_config(Path::new(path))?;
+    let mut test_quest: TestQuest = parse(path, &contents)?;
+    assign_port(&mut test_quest.setup, port);
+
+    let (mut test_groups, setup) = Validator::new(&test_quest, contents.as_str(), path)
+        .validate()
+        .map_err(TestQuestError::ValidationError)?;
+
+    let includes = test_quest.include.clone().unwrap_or_default();
+    let mut stack = vec![Path::new(path).canonicalize()?];
+    load_includes(&test_quest, &includes, &mut stack, &mut test_groups)?;
+
+    let n_tests = test_groups.tests.len();
+
+    Ok((test_groups, n_tests, setup))
+}
+
+/// Validates the test groups of every file in `includes` and appends them to
+/// `ir`, following their own includes as well.
+///
+/// Includes are resolved relative to the file at the top of `stack`, which
+/// holds the chain of files currently being included and is used to detect
+/// cycles. Included files share the `[setup]`, `[db]` and `[global]` of `base`
+/// and may not define their own.
+fn load_includes(
+    base: &TestQuest,
+    includes: &[String],
+    stack: &mut Vec<PathBuf>,
+    ir: &mut IR,
+) -> Result<(), TestQuestError> {
+    let including = stack.last().cloned().unwrap_or_default();
+    let dir = including.parent().unwrap_or(Path::new("."));
+
+    for include in includes {
+        let path = dir.join(include).canonicalize()?;
+        if stack.contains(&path) {
+            return Err(TestQuestError::IncludeError(format!(
+                "{} is included in a cycle",
+                path.display()
+            )));
+        }
+
+        let contents = read_config(&path)?;
+        let fragment: ConfigFragment = parse(&path.to_string_lossy(), &contents)?;
+        let mut tables = shared_tables(&fragment);
+        if fragment.setup.is_some() || fragment.db.is_some() {
+            tables.insert(0, "[setup]/[db]");
+        }
+        if !tables.is_empty() {
+            return Err(TestQuestError::IncludeError(format!(
+                "{} defines {}, which only the including config may",
+                path.display(),
+                tables.join(", ")
+            )));
+        }
+
+        let test_quest = TestQuest {
+            test_groups: fragment.test_groups,
+            include: None,
+            ..base.clone()
+        };
+        let (included, _) = Validator::new(&test_quest, &contents, &path.to_string_lossy())
+            .validate()
+            .map_err(TestQuestError::ValidationError)?;
+        ir.tests.extend(included.tests);
+
+        stack.push(path);
+        load_includes(base, &fragment.include.unwrap_or_default(), stack, ir)?;
+        stack.pop();
+    }
+
+    Ok(())
+}
+
+/// The tables besides `[setup]` and `[db]` that `fragment` defines, which
+/// apply to the whole run and so can only be set by the main config.
+fn shared_tables(fragment: &ConfigFragment) -> Vec<&'static str> {
+    [
+        ("[global]", fragment.global.is_some()),
+        ("before_all", fragment.before_all.is_some()),
+        ("after_all", fragment.after_all.is_some()),
+        ("before_each_group", fragment.before_each_group.is_some()),
+    ]
+    .into_iter()
+    .filter_map(|(table, defined)| defined.then_some(table))
+    .collect()
+}
+
+/// Loads every config file in `dir` and merges their test groups, in file name
+/// order, into a single `IR`.
+///
+/// Exactly one file may define `[setup]` and `[db]`, which are shared by all
+/// the others, and only that file may define `[global]` and the hooks. If there is a `main.toml` it has to be that file. Every file is
+/// validated on its own, so errors still point at the right source.
+fn load_dir(dir: &Path, port: u16) -> Result<(IR, usize, EnvSetup), TestQuestError> {
+    let mut paths = std::fs::read_dir(dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<Vec<PathBuf>, _>>()?;
+    paths.retain(|p| p.is_file() && is_config_file(p));
+    paths.sort();
+
+    let mut fragments = Vec::with_capacity(paths.len());
+    for path in paths {
+        let contents = read_config(&path)?;
+        let fragment: ConfigFragment = parse(&path.to_string_lossy(), &contents)?;
+        fragments.push((path, contents, fragment));
+    }
+
+    let defines_setup = |f: &ConfigFragment| f.setup.is_some() || f.db.is_some();
+    let mut with_setup = fragments.iter().filter(|(_, _, f)| defines_setup(f));
+    let main = match (with_setup.next(), with_setup.next()) {
+        (Some(_), Some((path, ..))) => {
+            let hint = if dir.join(MAIN_CONFIG).exists() {
+                format!("only {MAIN_CONFIG} may define them")
+            } else {
+                "only one file may define them".to_string()
+            };
+            return Err(TestQuestError::ConfigDirError(format!(
+                "{} defines its own [setup]/[db], {hint}",
+                path.display()
+            )));
+        }
+        (Some((path, _, fragment)), None) => {
+            if dir.join(MAIN_CONFIG).exists() && !path.ends_with(MAIN_CONFIG) {
+                return Err(TestQuestError::ConfigDirError(format!(
+                    "{} defines [setup]/[db], but they belong in {MAIN_CONFIG}",
+                    path.display()
+                )));
+            }
+            fragment
+        }
+        (None, _) => {
+            return Err(TestQuestError::ConfigDirError(format!(
+                "no file in {} defines [setup] and [db]",
+                dir.display()
+            )));
+        }
+    };
+
+    let (Some(mut setup), Some(db)) = (main.setup.clone(), main.db.clone()) else {
+        return Err(TestQuestError::ConfigDirError(
+            "[setup] and [db] have to be defined in the same file".to_string(),
+        ));
+    };
+    assign_port(&mut setup, port);
+    let global = main.global.clone().unwrap_or_default();
+    let before_each_group = main.before_each_group.clone();
+    let before_all = main.before_all.clone();
+    let after_all = main.after_all.clone();
+
+    let main_path = fragments
+        .iter()
+        .find(|(_, _, f)| defines_setup(f))
+        .map(|(path, ..)| path.clone());
+    let mut merged: Option<(IR, EnvSetup)> = None;
+    for (path, contents, fragment) in fragments {
+        let shared = shared_tables(&fragment);
+        if Some(&path) != main_path.as_ref() && !shared.is_empty() {
+            return Err(TestQuestError::ConfigDirError(format!(
+                "{} defines {}, but they belong in the file with [setup]",
+                path.display(),
+                shared.join(", ")
+            )));
+        }
+
+        let test_quest = TestQuest {
+            setup: setup.clone(),
+            db: db.clone(),
+            before_all: before_all.clone(),
+            after_all: after_all.clone(),
+            before_each_group: before_each_group.clone(),
+            test_groups: fragment.test_groups,
+            global: global.clone(),
+            include: None,
+        };
+
+        let (mut ir, env_setup) = Validator::new(&test_quest, &contents, &path.to_string_lossy())
+            .validate()
+            .map_err(TestQuestError::ValidationError)?;
+
+        let includes = fragment.include.unwrap_or_default();
+        let mut stack = vec![path.canonicalize()?];
+        load_includes(&test_quest, &includes, &mut stack, &mut ir)?;
+
+        match &mut merged {
+            Some((merged_ir, _)) => merged_ir.tests.extend(ir.tests),
+            None => merged = Some((ir, env_setup)),
+        }
+    }
+
+    // There is at least the file with [setup] in `fragments`.
+    let (test_groups, setup) = merged.expect("config directory has at least one file");
+    let n_tests = test_groups.tests.len();
+
+    Ok((test_groups, n_tests, setup))
+}
+
+/// When `port_env` is set, passes `port` to the app in that env var and puts
+/// it in place of `{port}` in `base_url` and `ready_when`.
+pub fn assign_port(setup: &mut Setup, port: u16) {
+    let Some(port_env) = setup.port_env.clone() else {
+        return;
+    };
+    let port = port.to_string();
+
+    setup.base_url = setup.base_url.replace(PORT_PLACEHOLDER, &port);
+    setup.ready_when = setup.ready_when.replace(PORT_PLACEHOLDER, &port);
+    setup.env.get_or_insert_default().insert(port_env, port);
+}
+
+/// Asks the OS for a port nothing is listening on. The listener is closed
+/// right away so the app can bind the port itself.
+pub fn free_port() -> std::io::Result<u16> {
+    let listener = std::net::TcpListener::bind(("127.0.0.1", 0))?;
+    Ok(listener.local_addr()?.port())
+}
+
+/// Reads the config file at `path` and expands environment variables in it.
+fn read_config(path: &Path) -> Result<String, TestQuestError> {
+    let contents = std::fs::read_to_string(path).map_err(TestQuestError::FileError)?;
+    expand_env(&contents, |name| std::env::var(name).ok())
+}
+
+/// Replaces every `${VAR}` in `contents` with the value of `VAR`, which may be
+/// empty, and every `${VAR:-default}` with `default` when `VAR` is unset or
+/// empty. `$${` is an escaped `${` and is left as `${`.
+///
+/// This runs once on the raw config before it is parsed, so the values end up
+/// wherever the variables are used, comments and strings included. Errors
+/// name the line the variable is on.
+fn expand_env(
+    contents: &str,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Result<String, TestQuestError> {
+    let mut expanded = String::with_capacity(contents.len());
+    let mut rest = contents;
+    let line = |rest: &str| {
+        contents[..contents.len() - rest.len()]
+            .matches('\n')
+            .count()
+            + 1
+    };
+
+    while let Some(start) = rest.find('$') {
+        expanded.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        if let Some(escaped) = rest.strip_prefix("$${") {
+            expanded.push_str("${");
+            rest = escaped;
+            continue;
+        }
+        let Some(after) = rest.strip_prefix("${") else {
+            expanded.push('$');
+            rest = &rest[1..];
+            continue;
+        };
+
+        let Some(end) = after.find('}') else {
+            return Err(TestQuestError::EnvVarError(format!(
+                "unterminated `${{` on line {}: `{}`",
+                line(rest),
+                after.lines().next().unwrap_or_default()
+            )));
+        };
+
+        let (name, default) = match after[..end].split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (&after[..end], None),
+        };
+
+        match (lookup(name), default) {
+            (Some(value), Some(default)) if value.is_empty() => expanded.push_str(default),
+            (Some(value), _) => expanded.push_str(&value),
+            (None, Some(default)) => expanded.push_str(default),
+            (None, None) => {
+                return Err(TestQuestError::EnvVarError(format!(
+                    "environment variable `{name}` on line {} is not set and has no default, \
+                     write `$${{` for a literal `${{`",
+                    line(rest)
+                )));
+            }
+        }
+
+        rest = &after[end + 1..];
+    }
+    expanded.push_str(rest);
+
+    Ok(expanded)
+}
+
+fn is_config_file(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext == "toml" || ext == "yaml" || ext == "yml")
+}
+
+/// Deserializes a config file, as YAML if it ends in `.yaml`/`.yml` and as
+/// TOML otherwise.
+fn parse<T: DeserializeOwned>(path: &str, contents: &str) -> Result<T, TestQuestError> {
+    let is_yaml = Path::new(path)
+        .extension()
+        .is_some_and(|ext| ext == "yaml" || ext == "yml");
+
+    if is_yaml {
+        serde_yaml::from_str(contents).map_err(TestQuestError::YamlParsing)
+    } else {
+        toml::from_str(contents).map_err(TestQuestError::TomlParsing)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use crate::TestQuestError;
+    use crate::config::expand_env;
+    use crate::config::load_config;
+
+    const MAIN: &str = r#"
+        [setup]
+        base_url = "http://localhost:6969"
+        command = "cargo"
+        ready_when = "/health"
+
+        [db]
+        db_type = "postgres"
+        migration_dir = "./migrations"
+
+        [[test_groups]]
+        name = "health"
+
+        [[test_groups.tests]]
+        name = "Health"
+        method = "GET"
+        url = "/health"
+    "#;
+
+    const USERS: &str = r#"
+        [[test_groups]]
+        name = "users"
+
+        [[test_groups.tests]]
+        name = "GetUser"
+        method = "GET"
+        url = "/users/1"
+    "#;
+
+    fn config_dir(files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("tq-config-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for (name, contents) in files {
+            std::fs::write(dir.join(name), contents).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn directory_merges_test_groups_in_file_name_order() {
+        let dir = config_dir(&[("main.toml", MAIN), ("users.toml", USERS)]);
+
+        let (ir, n_tests, setup) = load_config(&dir.to_string_lossy(), 6969).unwrap();
+
+        let names: Vec<_> = ir.tests.iter().map(|g| g.name.as_str()).collect();
+        assert_eq!(names, ["health", "users"]);
+        assert_eq!(n_tests, 2);
+        assert_eq!(setup.base_url, "http://localhost:6969");
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn includes_append_test_groups_and_detect_cycles() {
+        let main = format!("include = [\"users.toml\"]\n{MAIN}");
+        let dir = config_dir(&[("main.toml", &main), ("users.toml", USERS)]);
+
+        let (ir, ..) = load_config(&dir.join("main.toml").to_string_lossy(), 6969).unwrap();
+        let names: Vec<_> = ir.tests.iter().map(|g| g.name.as_str()).collect();
+        assert_eq!(names, ["health", "users"]);
+
+        let cyclic = format!("include = [\"users.toml\"]\n{USERS}");
+        std::fs::write(dir.join("users.toml"), cyclic).unwrap();
+        let result = load_config(&dir.join("main.toml").to_string_lossy(), 6969);
+        assert!(matches!(result, Err(TestQuestError::IncludeError(_))));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn only_the_main_config_may_define_global_and_hooks() {
+        let users = format!("[global]\nheaders = {{ x-team = \"users\" }}\n{USERS}");
+        let main = format!("include = [\"users.toml\"]\n{MAIN}");
+        let dir = config_dir(&[("main.toml", &main), ("users.toml", &users)]);
+
+        let Err(TestQuestError::IncludeError(error)) =
+            load_config(&dir.join("main.toml").to_string_lossy(), 6969)
+        else {
+            panic!("expected an included [global] to be rejected");
+        };
+        assert!(error.contains("users.toml defines [global]"), "{error}");
+
+        std::fs::write(dir.join("main.toml"), MAIN).unwrap();
+        let users = format!("[before_each_group]\nreset = true\n{USERS}");
+        std::fs::write(dir.join("users.toml"), users).unwrap();
+        let Err(TestQuestError::ConfigDirError(error)) = load_config(&dir.to_string_lossy(), 6969)
+        else {
+            panic!("expected before_each_group outside main.toml to be rejected");
+        };
+        assert!(
+            error.contains("users.toml defines before_each_group"),
+            "{error}"
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn expands_env_vars_with_defaults() {
+        let lookup = |name: &str| match name {
+            "HOST" => Some("example.com".to_string()),
+            "EMPTY" => Some(String::new()),
+            _ => None,
+        };
+
+        let expanded = expand_env(r#"base_url = "http://${HOST}:${PORT:-6969}""#, lookup).unwrap();
+        assert_eq!(expanded, r#"base_url = "http://example.com:6969""#);
+
+        let expanded = expand_env(r#"a = "${EMPTY}", b = "${EMPTY:-x}""#, lookup).unwrap();
+        assert_eq!(expanded, r#"a = "", b = "x""#);
+
+        let result = expand_env(r#"image = "${IMAGE}""#, lookup);
+        assert!(matches!(result, Err(TestQuestError::EnvVarError(_))));
+    }
+
+    #[test]
+    fn escaped_env_vars_are_left_alone() {
+        let lookup = |name: &str| (name == "HOST").then(|| "example.com".to_string());
+
+        let expanded = expand_env(
+            "# uses $${HOST}\nbody = { tpl = \"$${name}\", host = \"${HOST}\", cost = \"$5\" }",
+            lookup,
+        )
+        .unwrap();
+        assert_eq!(
+            expanded,
+            "# uses ${HOST}\nbody = { tpl = \"${name}\", host = \"example.com\", cost = \"$5\" }"
+        );
+
+        let Err(TestQuestError::EnvVarError(error)) =
+            expand_env("a = 1\n# see ${MISSING}\nb = 2", lookup)
+        else {
+            panic!("expected an unset variable to be an error");
+        };
+        assert!(error.contains("`MISSING` on line 2"), "{error}");
+    }
+
+    #[test]
+    fn port_env_hands_the_port_to_the_app() {
+        let main = MAIN.replace(
+            r#"base_url = "http://localhost:6969""#,
+            "base_url = \"http://localhost:{port}\"\nport_env = \"PORT\"",
+        );
+        let dir = config_dir(&[("main.toml", &main)]);
+        let path = dir.join("main.toml");
+
+        let (ir, _, setup) = load_config(&path.to_string_lossy(), 4321).unwrap();
+
+        assert_eq!(setup.env["PORT"], "4321");
+        assert_eq!(setup.base_url, "http://localhost:4321");
+        assert_eq!(
+            ir.tests[0].tests[0].url.as_str(),
+            "http://localhost:4321/health"
+        );
+
+        // Reloading, as --watch does, keeps the port the app was started on.
+        let (_, _, reloaded) = load_config(&path.to_string_lossy(), 4321).unwrap();
+        assert_eq!(reloaded.base_url, setup.base_url);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn directory_rejects_more_than_one_setup() {
+        let dir = config_dir(&[("main.toml", MAIN), ("other.toml", MAIN)]);
+
+        let result = load_config(&dir.to_string_lossy(), 6969);
+        assert!(matches!(result, Err(TestQuestError::ConfigDirError(_))));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn misspelled_keys_are_rejected() {
+        let cases = [
+            (
+                r#"url = "/health""#,
+                "url = \"/health\"\nassert_staus = 200",
+                "assert_staus",
+            ),
+            (
+                r#"name = "health""#,
+                "name = \"health\"\ntimeout = 5",
+                "timeout",
+            ),
+            ("[setup]", "includes = []\n[setup]", "includes"),
+            (
+                r#"url = "/health""#,
+                "url = \"/health\"\nassert_cookie = { name = \"id\", secure = true, http_only = true }",
+                "http_only",
+            ),
+        ];
+
+        for (from, to, key) in cases {
+            let main = MAIN.replace(from, to);
+            let dir = config_dir(&[("main.toml", &main)]);
+
+            let result = load_config(&dir.join("main.toml").to_string_lossy(), 6969);
+            let Err(TestQuestError::TomlParsing(error)) = result else {
+                panic!("expected `{key}` to fail parsing");
+            };
+            assert!(
+                error
+                    .to_string()
+                    .contains(&format!("unknown field `{key}`"))
+            );
+
+            std::fs::remove_dir_all(dir).unwrap();
+        }
+
+        let users = USERS.replace(r#"name = "users""#, "name = \"users\"\ntimeout = 5");
+        let dir = config_dir(&[("main.toml", MAIN), ("users.toml", &users)]);
+
+        let Err(error) = load_config(&dir.to_string_lossy(), 6969) else {
+            panic!("expected a misspelled key in a fragment to fail parsing");
+        };
+        assert!(error.to_string().contains("unknown field `timeout`"));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
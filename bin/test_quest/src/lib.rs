@@ -0,0 +1,303 @@
+#![allow(clippy::result_large_err)]
+
+use std::sync::Arc;
+
+use flume::Sender;
+use miette::Diagnostic;
+use thiserror::Error;
+
+use crate::cache::CacheError;
+use crate::config::assign_port;
+use crate::parser::TestQuest;
+use crate::setup::database::any_db::AnyDbPool;
+use crate::validator::ValidationError;
+use crate::validator::Validator;
+
+mod asserter;
+mod cache;
+mod cli;
+mod config;
+mod init;
+mod outputter;
+pub mod parser;
+mod postman;
+mod report;
+mod runner;
+mod setup;
+mod validator;
+mod watch;
+
+pub use report::RunReport;
+
+// What the `tq` binary is built from, the library API is `run_suite`.
+pub use crate::asserter::Asserter;
+pub use crate::asserter::TestOutcome;
+pub use crate::cache::load_failed;
+pub use crate::cache::retain_failed;
+pub use crate::cli::Cli;
+pub use crate::cli::Command;
+pub use crate::config::free_port;
+pub use crate::config::load_config;
+pub use crate::init::init_from_openapi;
+pub use crate::init::init_starter;
+pub use crate::outputter::OutPutter;
+pub use crate::postman::import_postman;
+pub use crate::runner::Clients;
+pub use crate::runner::RunnerError;
+pub use crate::runner::RunnerResult;
+pub use crate::runner::run_tests;
+pub use crate::runner::run_until_failure;
+pub use crate::runner::shuffle_tests;
+pub use crate::setup::AppHandle;
+pub use crate::setup::StartUpError;
+pub use crate::setup::app::OutputLine;
+pub use crate::setup::app::OutputSource;
+pub use crate::setup::cleanup_and_teardown;
+pub use crate::setup::database::load_init_sql;
+pub use crate::setup::database::run_migrations;
+pub use crate::setup::docker::use_podman_socket;
+pub use crate::setup::start_db_and_app;
+pub use crate::validator::EnvSetup;
+pub use crate::validator::IR;
+pub use crate::watch::FileWatcher;
+
+#[derive(Error, Debug, Diagnostic)]
+pub enum TestQuestError {
+    #[error("Failed to read toml file")]
+    FileError(#[from] std::io::Error),
+
+    #[error("Failed in the startup process: {0}")]
+    StartUpError(StartUpError),
+
+    #[error("Failed to parse toml file: {0}")]
+    TomlParsing(#[from] toml::de::Error),
+
+    #[error("Failed to parse yaml file: {0}")]
+    YamlParsing(#[from] serde_yaml::Error),
+
+    #[error("Failed to render the config as toml: {0}")]
+    TomlRendering(#[from] toml::ser::Error),
+
+    #[error("Invalid config directory: {0}")]
+    ConfigDirError(String),
+
+    #[error("Failed to include config: {0}")]
+    IncludeError(String),
+
+    #[error("Failed to expand config: {0}")]
+    EnvVarError(String),
+
+    #[error("Failed to generate config: {0}")]
+    InitError(String),
+
+    #[error("Failed to find a free port for the app: {0}")]
+    PortError(std::io::Error),
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    ValidationError(#[from] ValidationError),
+
+    #[error("Failed in assert step")]
+    AssertError,
+
+    #[error("Failed to watch files for changes: {0}")]
+    WatchError(notify::Error),
+
+    #[error(transparent)]
+    ClientError(Box<RunnerError>),
+
+    #[error("Failed to run the tests: {0}")]
+    RunnerError(Box<RunnerError>),
+
+    #[error("Failed to load the failed tests of the last run: {0}")]
+    CacheError(#[from] CacheError),
+}
+
+/// How [`run_pipeline`] runs a suite, the binary fills this in from its flags.
+#[derive(Clone, Copy)]
+pub struct RunOptions {
+    /// Run the whole suite this many times in a row.
+    pub repeat: u32,
+    /// Keep running the suite until a test fails, at most the given number of
+    /// times if there is one. Takes the place of `repeat`.
+    pub repeat_until_failure: Option<Option<u32>>,
+    /// How many test groups may run at the same time.
+    pub parallel_groups: usize,
+    /// Shuffle the tests of every group with this seed first.
+    pub seed: Option<u64>,
+}
+
+impl Default for RunOptions {
+    fn default() -> Self {
+        Self {
+            repeat: 1,
+            repeat_until_failure: None,
+            parallel_groups: 1,
+            seed: None,
+        }
+    }
+}
+
+/// Runs `ir` through the runner and the asserter, which run at the same
+/// time, and sends the outcome of every test to `outcomes`. Both the binary
+/// and [`run_suite`] run their tests through this.
+///
+/// Every iteration of `options.repeat` still runs the `before_group` and
+/// `before_run` hooks. Returns the iteration a test failed in with
+/// `options.repeat_until_failure`.
+pub async fn run_pipeline(
+    mut ir: IR,
+    clients: &Clients,
+    pool: Arc<AnyDbPool>,
+    options: RunOptions,
+    outcomes: Sender<TestOutcome>,
+) -> Result<Option<u32>, RunnerError> {
+    if let Some(seed) = options.seed {
+        shuffle_tests(&mut ir, seed);
+    }
+
+    let (runner_tx, asserter_rx) = flume::unbounded::<RunnerResult>();
+    let parallel_groups = options.parallel_groups;
+
+    // The runner's sender is dropped once it returns, which ends the asserter.
+    let runner = async move {
+        if let Some(max) = options.repeat_until_failure {
+            return run_until_failure(ir, clients, runner_tx, pool, parallel_groups, max).await;
+        }
+
+        for _ in 0..options.repeat {
+            run_tests(
+                ir.clone(),
+                clients,
+                runner_tx.clone(),
+                pool.clone(),
+                parallel_groups,
+            )
+            .await?;
+        }
+        Ok(None)
+    };
+
+    let (ran, _) = futures::join!(runner, Asserter::run(asserter_rx, outcomes));
+    ran
+}
+
+/// Runs the suite described by `config` without printing any results, for
+/// driving test_quest from other test harnesses, e.g. a `#[tokio::test]`.
+///
+/// Like the binary, this starts the database and the app, runs every test
+/// group once and tears everything down again, `after_all` included. Paths in
+/// `config` are relative to the current directory. The caller's runtime is
+/// already running, so unlike the binary this doesn't point `DOCKER_HOST` at
+/// Podman's socket; set it before the runtime starts to use Podman. Validation
+/// errors point into `config` rendered as TOML. `include` is not supported,
+/// there is no config file to resolve the included files against.
+///
+/// # Errors
+/// Returns a `TestQuestError` if the config is invalid or uses `include`, the database or app
+/// fail to start, or the runner fails. Failing tests are not errors, they
+/// show up in the returned [`RunReport`].
+pub async fn run_suite(mut config: TestQuest) -> Result<RunReport, TestQuestError> {
+    if config.include.is_some() {
+        return Err(TestQuestError::IncludeError(
+            "run_suite can't follow `include`, add the test groups to the config instead".into(),
+        ));
+    }
+
+    let port = free_port().map_err(TestQuestError::PortError)?;
+    assign_port(&mut config.setup, port);
+    let source = toml::to_string(&config)?;
+    let (ir, setup) = Validator::new(&config, &source, "test_quest.toml").validate()?;
+    let clients = Clients::new(&ir.client).map_err(|e| TestQuestError::ClientError(Box::new(e)))?;
+
+    let mut app_handle = start_db_and_app(setup, false, false, false, false)
+        .await
+        .map_err(TestQuestError::StartUpError)?;
+
+    let (outcome_tx, outcome_rx) = flume::unbounded::<TestOutcome>();
+    let ran = run_pipeline(
+        ir,
+        &clients,
+        app_handle.pool.clone(),
+        RunOptions::default(),
+        outcome_tx,
+    )
+    .await;
+    let outcomes: Vec<TestOutcome> = outcome_rx.drain().collect();
+
+    cleanup_and_teardown(&mut app_handle, false, false)
+        .await
+        .map_err(TestQuestError::StartUpError)?;
+    ran.map_err(|e| TestQuestError::RunnerError(Box::new(e)))?;
+
+    Ok(RunReport::from_outcomes(outcomes))
+}
+
+#[cfg(test)]
+mod test {
+    use miette::Diagnostic;
+
+    use crate::TestQuestError;
+    use crate::parser::TestQuest;
+    use crate::run_suite;
+
+    #[tokio::test]
+    async fn validation_errors_point_into_the_config() {
+        let config: TestQuest = toml::from_str(
+            r#"
+            [setup]
+            base_url = "http://localhost:6969"
+            command = "cargo"
+            ready_when = "/health"
+
+            [db]
+            db_type = "postgres"
+
+            [[test_groups]]
+            name = "health"
+            timeout_secs = 0
+
+            [[test_groups.tests]]
+            name = "Health"
+            method = "GET"
+            url = "/health"
+            assert_status = 200
+            "#,
+        )
+        .unwrap();
+
+        let Err(TestQuestError::ValidationError(error)) = run_suite(config).await else {
+            panic!("expected a zero group timeout to fail validation");
+        };
+        let label = error.labels().and_then(|mut labels| labels.next()).unwrap();
+        let source = error.source_code().unwrap();
+        let snippet = source.read_span(label.inner(), 0, 0).unwrap();
+        assert_eq!(std::str::from_utf8(snippet.data()).unwrap(), "health");
+    }
+
+    #[tokio::test]
+    async fn includes_are_refused() {
+        let config: TestQuest = toml::from_str(
+            r#"
+            include = ["users.toml"]
+
+            [setup]
+            base_url = "http://localhost:6969"
+            command = "cargo"
+            ready_when = "/health"
+
+            [db]
+            db_type = "postgres"
+
+            [[test_groups]]
+            name = "health"
+            tests = []
+            "#,
+        )
+        .unwrap();
+
+        let Err(TestQuestError::IncludeError(_)) = run_suite(config).await else {
+            panic!("expected include to be refused");
+        };
+    }
+}
@@ -1,164 +1,258 @@
-#![allow(clippy::result_large_err)]
-#![allow(dead_code)]
-
-use std::env;
+use std::path::PathBuf;
 use std::sync::Arc;
 
 use clap::Parser;
-use miette::Diagnostic;
+use miette::IntoDiagnostic;
 use miette::Result;
-use thiserror::Error;
 use tokio::task::JoinHandle;
+use tq::AppHandle;
+use tq::Cli;
+use tq::Clients;
+use tq::Command;
+use tq::EnvSetup;
+use tq::FileWatcher;
+use tq::IR;
+use tq::OutPutter;
+use tq::OutputLine;
+use tq::OutputSource;
+use tq::RunOptions;
+use tq::RunnerError;
+use tq::StartUpError;
+use tq::TestOutcome;
+use tq::TestQuestError;
+use tq::cleanup_and_teardown;
+use tq::free_port;
+use tq::import_postman;
+use tq::init_from_openapi;
+use tq::init_starter;
+use tq::load_config;
+use tq::load_failed;
+use tq::load_init_sql;
+use tq::retain_failed;
+use tq::run_migrations;
+use tq::run_pipeline;
+use tq::start_db_and_app;
+use tq::use_podman_socket;
+use tracing::level_filters::LevelFilter;
+
+type PipelineHandles = (JoinHandle<Result<Option<u32>, RunnerError>>, JoinHandle<()>);
 
-use crate::asserter::AssertResult;
-use crate::asserter::Asserter;
-use crate::cli::Cli;
-use crate::outputter::OutPutter;
-use crate::parser::TestQuest;
-use crate::runner::RunnerError;
-use crate::runner::RunnerResult;
-use crate::runner::run_tests;
-use crate::setup::StartUpError;
-use crate::setup::app::AppProcess;
-use crate::setup::app::OutputLine;
-use crate::setup::app::OutputSource;
-use crate::setup::database::any_db::AnyDbPool;
-use crate::setup::start_db_and_app;
-use crate::validator::EnvSetup;
-use crate::validator::IR;
-use crate::validator::ValidationError;
-use crate::validator::Validator;
-
-mod asserter;
-mod cli;
-mod outputter;
-mod parser;
-mod runner;
-mod setup;
-mod validator;
-
-#[derive(Error, Debug, Diagnostic)]
-pub enum TestQuestError {
-    #[error("Failed to read toml file")]
-    FileError(#[from] std::io::Error),
-
-    #[error("Failed in the startup process: {0}")]
-    StartUpError(StartUpError),
-
-    #[error("Failed to parse toml file")]
-    TomlParsing(#[from] toml::de::Error),
-
-    #[error(transparent)]
-    #[diagnostic(transparent)]
-    ValidationError(#[from] ValidationError),
-
-    #[error("Failed in assert step")]
-    AssertError,
-}
 /// Loads the test configuration file and validates its contents.
 ///
 /// This function:
-/// - Parses CLI arguments to locate the configuration file.
-/// - Reads and deserializes the file into a `TestQuest` structure from TOML.
-/// - Runs a validation pass over the configuration to ensure correctness.
-/// - Returns the parsed CLI options, validated test definitions (`IR`), the
-///   total number of tests, and the environment setup information.
+/// - Uses the CLI arguments to locate the configuration file.
+/// - Loads and validates the configuration through [`load_config`], which
+///   accepts a single file or a directory of config files. `port` stands in
+///   for `{port}` when `[setup] port_env` is set.
+/// - Returns the validated test definitions (`IR`), the total number of
+///   tests, and the environment setup information.
+/// - With `--update-snapshots`, makes every snapshot assertion rewrite its
+///   file.
+/// - With `--only-failed`, drops every test that didn't fail in the last run
+///   of this config.
 ///
 /// # Errors
 /// Returns a `TestQuestError` if:
 /// - The file cannot be read,
 /// - The TOML fails to parse,
 /// - Or the configuration validation fails.
-async fn load_and_validate_config() -> Result<(Cli, IR, usize, EnvSetup), TestQuestError> {
-    let cli = Cli::parse();
-
-    let contents = std::fs::read_to_string(&cli.path).map_err(TestQuestError::FileError)?;
-    let test_quest: TestQuest = toml::from_str(&contents).map_err(TestQuestError::TomlParsing)?;
-
-    if let Some(ref env_vars) = test_quest.setup.env {
-        for (key, value) in env_vars {
-            // SAFETY:
-            // No other threads or child processes have been spawned yet, and environment
-            // variables are only being modified in the current process. Therefore, calling
-            // `env::set_var` here is safe.
-            unsafe {
-                env::set_var(key, value);
-            }
-        }
+async fn load_and_validate_config(
+    cli: &Cli,
+    port: u16,
+) -> Result<(IR, usize, EnvSetup), TestQuestError> {
+    let (mut test_groups, n_tests, setup) = load_config(&cli.path, port)?;
+
+    if cli.update_snapshots {
+        test_groups.update_snapshots();
     }
 
-    let mut validator = Validator::new(&test_quest, contents.as_str(), cli.path.as_str());
+    if !cli.only_failed {
+        return Ok((test_groups, n_tests, setup));
+    }
 
-    let (test_groups, setup) = validator
-        .validate()
-        .map_err(TestQuestError::ValidationError)?;
-    let n_tests = test_groups.tests.len();
+    match load_failed(&cli.path)? {
+        Some(failed) if !failed.is_empty() => {
+            retain_failed(&mut test_groups, &failed);
+            let n_tests = test_groups.tests.len();
+            Ok((test_groups, n_tests, setup))
+        }
+        _ => {
+            println!(
+                "No failed tests recorded for {}, running every test",
+                cli.path
+            );
+            Ok((test_groups, n_tests, setup))
+        }
+    }
+}
 
-    Ok((cli, test_groups, n_tests, setup))
+/// The resolved config the outputter writes to `--report-dir`, if it was
+/// given. Like `--config-check`, credential headers, the app's env values and
+/// the proxy's credentials are redacted unless `--show-secrets` is set.
+fn report_config(cli: &Cli, test_groups: &IR, setup: &EnvSetup) -> Option<String> {
+    cli.report_dir
+        .as_ref()
+        .map(|_| OutPutter::resolved_config(test_groups, setup, cli.show_secrets))
 }
 
 /// Spawns the concurrent test pipeline tasks: runner, asserter, and outputter.
 ///
-/// This function sets up communication channels between the three pipeline
-/// stages:
+/// The runner and asserter run through [`run_pipeline`], the same way
+/// `tq::run_suite` runs them:
 /// - **Runner:** Executes each HTTP test and sends the results to the asserter.
 /// - **Asserter:** Validates test results and forwards assertion outcomes to
 ///   the outputter.
 /// - **Outputter:** Collects and prints or writes test results to disk.
 ///
 /// Each stage runs in its own Tokio task with unbounded flume channels for
-/// communication. The function returns the join handles for all three tasks so
-/// they can be awaited later.
+/// communication. The function returns the join handles for the pipeline and
+/// the outputter so they can be awaited later.
+///
+/// `--repeat`, `--repeat-until-failure`, `--shuffle` and `--parallel-groups`
+/// are passed on as [`RunOptions`]. `config` is passed on to the outputter for
+/// `--report-dir`.
 ///
 /// # Concurrency
 /// All three tasks run concurrently and communicate via flume channels.
 async fn run_pipeline_tasks(
     test_groups: IR,
     n_tests: usize,
-    pool: Arc<AnyDbPool>,
-    path: &str,
-) -> (
-    JoinHandle<Result<(), RunnerError>>,
-    JoinHandle<Result<(), ()>>,
-    JoinHandle<()>,
-) {
-    let (runner_tx, asserter_rx) = flume::unbounded::<RunnerResult>();
-    let (asserter_tx, outputter_rx) =
-        flume::unbounded::<(String, String, String, Arc<[AssertResult]>)>();
+    clients: &Clients,
+    app_handle: &AppHandle,
+    cli: &Cli,
+    config: Option<String>,
+) -> PipelineHandles {
+    let (asserter_tx, outputter_rx) = flume::unbounded::<TestOutcome>();
 
     // Outputter Task
-    let outputter_rx_printter = outputter_rx.clone();
-    let outputter_path = path.to_owned();
+    let outputter_cli = cli.clone();
+    let app_output = app_handle.child.output.clone();
 
     let outputter_handle = tokio::spawn(async move {
-        OutPutter::start(outputter_rx_printter, &outputter_path, n_tests).await;
+        OutPutter::start(outputter_rx, &outputter_cli, n_tests, app_output, config).await;
     });
 
-    // TestRunner Task
+    // Runner and Asserter Task
+    let options = RunOptions {
+        repeat: cli.repeat,
+        repeat_until_failure: cli.repeat_until_failure,
+        parallel_groups: cli.parallel_groups as usize,
+        seed: cli.seed,
+    };
+    let pool = app_handle.pool.clone();
+    let clients = clients.clone();
+    let pipeline_jh = tokio::spawn(async move {
+        run_pipeline(test_groups, &clients, pool, options, asserter_tx).await
+    });
 
-    let runner_jh = tokio::spawn(async move { run_tests(test_groups, runner_tx, pool).await });
+    (pipeline_jh, outputter_handle)
+}
 
-    // Asserter Task
-    let asserter_outputter_tx = asserter_tx;
+/// Waits for all pipeline tasks to finish. Returns the iteration a test
+/// failed in with `--repeat-until-failure`.
+async fn wait_for_pipeline((pipeline_jh, outputter_handle): PipelineHandles) -> Option<u32> {
+    let (pipeline, _) = futures::join!(pipeline_jh, outputter_handle);
+    pipeline.ok()?.ok()?
+}
 
-    let asserter_jh =
-        tokio::spawn(async move { Asserter::run(asserter_rx, asserter_outputter_tx).await });
+/// Tells how a `--repeat-until-failure` run ended, after the outputter has
+/// printed the results of the last iteration.
+fn print_repeat_outcome(failed_in: Option<u32>, max: Option<u32>) {
+    let message = match (failed_in, max) {
+        (Some(iteration), _) => format!("[REPEAT] a test failed in iteration {iteration}"),
+        (None, Some(max)) => format!("[REPEAT] no test failed in {max} iterations"),
+        (None, None) => return,
+    };
+    println!("\n{}", console::style(message).bold().yellow());
+}
 
-    (runner_jh, asserter_jh, outputter_handle)
+/// Reruns the suite every time the config file, its `init_sql` or its
+/// migrations change, reusing the database container and app that are already
+/// running. Returns once the user presses Ctrl-C.
+///
+/// Because the app, container and HTTP clients are kept alive, changes to
+/// `[setup]` and `[db]` only take effect the next time test_quest is started.
+/// That includes the port the app was given through `port_env`, which every
+/// reload reuses.
+async fn watch_and_rerun(
+    cli: &Cli,
+    port: u16,
+    clients: &Clients,
+    app_handle: &AppHandle,
+    init_sql: Option<PathBuf>,
+    migration_dirs: Vec<String>,
+) -> Result<(), TestQuestError> {
+    let mut paths = vec![PathBuf::from(&cli.path)];
+    paths.extend(init_sql.clone());
+    paths.extend(migration_dirs.iter().map(PathBuf::from));
+
+    let watcher = FileWatcher::new(&paths).map_err(TestQuestError::WatchError)?;
+
+    loop {
+        println!(
+            "\n{}",
+            console::style("[WATCH] waiting for changes, press Ctrl-C to stop")
+                .bold()
+                .yellow()
+        );
+
+        let changed = tokio::select! {
+            changed = watcher.changed() => changed,
+            _ = tokio::signal::ctrl_c() => return Ok(()),
+        };
+
+        println!(
+            "\n{}",
+            console::style(format!("{:=^60}", " change detected, rerunning ")).bold()
+        );
+
+        if let Err(error) = rerun(
+            cli,
+            port,
+            clients,
+            app_handle,
+            &changed,
+            init_sql.as_ref(),
+            &migration_dirs,
+        )
+        .await
+        {
+            eprintln!("{:?}", miette::Report::new(error));
+        }
+    }
 }
 
-/// Waits for all pipeline tasks to finish and then terminates the running app
-/// process.
-async fn cleanup_and_teardown(
-    process: &AppProcess,
-    runner_jh: JoinHandle<Result<(), RunnerError>>,
-    asserter_jh: JoinHandle<Result<(), ()>>,
-    outputter_handle: JoinHandle<()>,
-) {
-    let _ = futures::join!(runner_jh, asserter_jh, outputter_handle);
-
-    let mut lock = process.process.lock().await;
-    let _ = lock.kill().await;
+/// Reloads the config and runs the pipeline once more. New migrations are
+/// applied, and `init_sql` is only loaded again if it was one of the changed
+/// files.
+async fn rerun(
+    cli: &Cli,
+    port: u16,
+    clients: &Clients,
+    app_handle: &AppHandle,
+    changed: &[PathBuf],
+    init_sql: Option<&PathBuf>,
+    migration_dirs: &[String],
+) -> Result<(), TestQuestError> {
+    let (test_groups, n_tests, setup) = load_and_validate_config(cli, port).await?;
+    let config = report_config(cli, &test_groups, &setup);
+
+    run_migrations(&app_handle.pool, migration_dirs)
+        .await
+        .map_err(|e| TestQuestError::StartUpError(StartUpError::DatabaseError(e)))?;
+
+    if let Some(path) = init_sql
+        && changed.contains(&path.canonicalize()?)
+    {
+        load_init_sql(&app_handle.pool, path.clone())
+            .await
+            .map_err(|e| TestQuestError::StartUpError(StartUpError::DatabaseError(e)))?;
+    }
+
+    let handles = run_pipeline_tasks(test_groups, n_tests, clients, app_handle, cli, config).await;
+    wait_for_pipeline(handles).await;
+
+    Ok(())
 }
 
 /// Prints the captured stdout and stderr from the application process.
@@ -179,30 +273,153 @@ async fn print_app_output(output_lines: &Arc<tokio::sync::Mutex<Vec<OutputLine>>
     println!("------------------------------------");
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    // Load the CLI arguments and read the test configuration file.
-    // The configuration is parsed, validated, and returned together with
-    // the total number of tests and environment setup details.
-    let (cli, test_groups, n_tests, setup) = load_and_validate_config().await?;
+fn main() -> Result<()> {
+    // testcontainers only finds Podman through DOCKER_HOST, which can only be
+    // set safely while this is the only thread, so before the runtime starts.
+    // SAFETY: no other thread has been started yet.
+    let podman = unsafe { use_podman_socket() };
+
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .into_diagnostic()?
+        .block_on(run(podman))
+}
+
+/// Everything `main` does once the tokio runtime is up. `podman` is the
+/// Podman socket `DOCKER_HOST` was pointed at, if any.
+async fn run(podman: Option<String>) -> Result<()> {
+    let mut cli = Cli::parse();
+
+    // Subcommands don't run any tests, so handle them before loading a config.
+    match &cli.command {
+        Some(Command::Init(args)) => {
+            match &args.openapi {
+                Some(openapi) => init_from_openapi(openapi, &args.output, args.force).await?,
+                None => init_starter(&args.output, args.force)?,
+            }
+            println!("Wrote {}", args.output.display());
+            return Ok(());
+        }
+        Some(Command::Import(args)) => {
+            import_postman(&args.postman, &args.output)?;
+            println!("Wrote {}", args.output.display());
+            return Ok(());
+        }
+        None => {}
+    }
+
+    // Styling is turned off with --no-color or when NO_COLOR is set to a
+    // non-empty value, see https://no-color.org.
+    if cli.no_color || std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty()) {
+        console::set_colors_enabled(false);
+        console::set_colors_enabled_stderr(false);
+    }
+
+    tracing_subscriber::fmt()
+        .with_max_level(LevelFilter::from(cli.log_level()))
+        .with_writer(std::io::stderr)
+        .with_ansi(console::colors_enabled_stderr())
+        .with_target(false)
+        .without_time()
+        .init();
+
+    if let Some(host) = podman {
+        tracing::debug!(host, "using the Podman socket");
+    }
+
+    // The port handed to the app through `port_env` is picked once, so the
+    // configs reloaded by --watch keep pointing at the running app.
+    let port = free_port().map_err(TestQuestError::PortError)?;
+
+    // Read the test configuration file. The configuration is parsed,
+    // validated, and returned together with the total number of tests and
+    // environment setup details.
+    let (test_groups, n_tests, setup) = load_and_validate_config(&cli, port).await?;
+
+    // With --list we only enumerate the tests, so there is no need to start
+    // any containers or the app.
+    if cli.list {
+        OutPutter::list(&test_groups);
+        return Ok(());
+    }
+
+    if cli.config_check {
+        OutPutter::config_check(&test_groups, &setup, cli.show_secrets);
+        return Ok(());
+    }
+
+    // Pick a seed up front so a failing order can be replayed with --seed.
+    if cli.shuffle {
+        let seed = *cli.seed.get_or_insert_with(rand::random);
+        println!("Shuffling tests with seed {seed}");
+    }
+
+    if test_groups.client.accept_invalid_certs {
+        eprintln!(
+            "{}",
+            console::style(
+                "Warning: accept_invalid_certs is enabled, TLS certificates are not verified"
+            )
+            .yellow()
+            .bold()
+        );
+    }
+
+    // The HTTP clients are built once and shared by every run, so a bad
+    // client option like an unreadable CA certificate fails before any
+    // container is started.
+    let clients = Clients::new(&test_groups.client)
+        .map_err(|e| TestQuestError::ClientError(Box::new(e)))?
+        .with_max_concurrency(cli.max_concurrency.map(|max| max as usize));
+
+    // The setup is handed over to start the app, so the config is resolved
+    // for --report-dir before that.
+    let config = report_config(&cli, &test_groups, &setup);
+
+    // Keep track of the files referenced by the config so --watch can rerun
+    // the suite when they change.
+    let init_sql = setup.init_sql.clone();
+    let migration_dirs = setup.migration_dirs.clone();
 
     // Start the database container (e.g. Postgres, MySQL, etc.) and launch
     // the application under test. Returns a handle containing the process,
     // database connection pool, and captured output buffers.
-    let app_handle = start_db_and_app(setup, cli.stream_app)
-        .await
-        .map_err(TestQuestError::StartUpError)?;
+    let mut app_handle = start_db_and_app(
+        setup,
+        cli.stream_app,
+        cli.stream_db,
+        cli.verbose,
+        cli.show_secrets,
+    )
+    .await
+    .map_err(TestQuestError::StartUpError)?;
 
     // Spawn the main test pipeline consisting of three concurrent tasks:
     // - The test runner, which executes the HTTP requests.
     // - The asserter, which verifies the results.
     // - The outputter, which collects and displays final output.
-    let (runner_jh, asserter_jh, outputter_handle) =
-        run_pipeline_tasks(test_groups, n_tests, app_handle.pool, &cli.path).await;
+    let handles =
+        run_pipeline_tasks(test_groups, n_tests, &clients, &app_handle, &cli, config).await;
 
-    // Wait for all background tasks to complete and gracefully shut down
-    // the database container and application process.
-    cleanup_and_teardown(&app_handle.child, runner_jh, asserter_jh, outputter_handle).await;
+    // Wait for all background tasks to complete.
+    let failed_in = wait_for_pipeline(handles).await;
+    if let Some(max) = cli.repeat_until_failure {
+        print_repeat_outcome(failed_in, max);
+    }
+
+    // With --watch, keep the app and database alive and rerun the suite
+    // whenever the config or the files it references change.
+    if cli.watch {
+        watch_and_rerun(&cli, port, &clients, &app_handle, init_sql, migration_dirs).await?;
+    }
+
+    // Gracefully shut down the application process and run any teardown
+    // commands. The database container is stopped when `app_handle` is
+    // dropped, unless --keep-container detached it.
+    cleanup_and_teardown(&mut app_handle, cli.stream_app, cli.keep_container)
+        .await
+        .map_err(TestQuestError::StartUpError)?;
 
     // If the -o flag was provided, print the full captured stdout and stderr
     // output from the application after all tests have finished running.
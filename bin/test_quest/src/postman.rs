@@ -0,0 +1,328 @@
+use std::path::Path;
+
+use serde::Deserialize;
+use url::Url;
+
+use crate::TestQuestError;
+use crate::init::quote;
+use crate::init::setup_section;
+
+/// The parts of a Postman v2.1 collection that map onto a test_quest config.
+#[derive(Debug, Deserialize)]
+struct Collection {
+    info: Info,
+    #[serde(default)]
+    item: Vec<Item>,
+    #[serde(default)]
+    variable: Vec<Variable>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Info {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Variable {
+    key: String,
+    #[serde(default)]
+    value: serde_json::Value,
+}
+
+/// Either a folder with nested items or a single request.
+#[derive(Debug, Deserialize)]
+struct Item {
+    name: String,
+    #[serde(default)]
+    item: Option<Vec<Item>>,
+    #[serde(default)]
+    request: Option<Request>,
+    #[serde(default)]
+    response: Vec<ExampleResponse>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Request {
+    Url(String),
+    Full {
+        #[serde(default = "default_method")]
+        method: String,
+        #[serde(default)]
+        header: Vec<Header>,
+        url: RequestUrl,
+        #[serde(default)]
+        body: Option<Body>,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum RequestUrl {
+    Raw(String),
+    Parts { raw: String },
+}
+
+#[derive(Debug, Deserialize)]
+struct Header {
+    key: String,
+    value: String,
+    #[serde(default)]
+    disabled: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct Body {
+    mode: String,
+    #[serde(default)]
+    raw: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExampleResponse {
+    code: Option<u16>,
+}
+
+fn default_method() -> String {
+    "GET".to_string()
+}
+
+/// Converts the Postman v2.1 collection at `collection` into a config file at
+/// `output`. An existing `output` is never overwritten.
+pub fn import_postman(collection: &Path, output: &Path) -> Result<(), TestQuestError> {
+    if output.exists() {
+        return Err(TestQuestError::InitError(format!(
+            "{} already exists",
+            output.display()
+        )));
+    }
+
+    let contents = std::fs::read_to_string(collection)?;
+    let collection: Collection = serde_json::from_str(&contents).map_err(|e| {
+        TestQuestError::InitError(format!(
+            "{} is not a Postman collection: {e}",
+            collection.display()
+        ))
+    })?;
+
+    std::fs::write(output, generate_config(&collection))?;
+
+    Ok(())
+}
+
+/// Renders `collection` as a config. Folders become test groups, named after
+/// their path in the collection, and requests outside of any folder end up in
+/// a group named after the collection.
+fn generate_config(collection: &Collection) -> String {
+    let mut groups: Vec<(String, Vec<&Item>)> = vec![];
+    collect_groups(&collection.info.name, "", &collection.item, &mut groups);
+
+    let mut base_url = None;
+    let mut rendered = String::new();
+    for (group, items) in groups {
+        rendered.push_str(&format!("\n[[test_groups]]\nname = {}\n", quote(&group)));
+
+        for item in items {
+            rendered.push_str(&render_test(collection, item, &mut base_url));
+        }
+    }
+
+    let base_url = base_url.unwrap_or_else(|| "http://localhost:8080".to_string());
+    format!("{}{rendered}", setup_section(&base_url))
+}
+
+/// Groups the requests in `items` under `name` and recurses into folders,
+/// which are named after their path below `prefix`.
+fn collect_groups<'a>(
+    name: &str,
+    prefix: &str,
+    items: &'a [Item],
+    groups: &mut Vec<(String, Vec<&'a Item>)>,
+) {
+    let requests: Vec<&Item> = items.iter().filter(|i| i.request.is_some()).collect();
+    if !requests.is_empty() {
+        groups.push((name.to_string(), requests));
+    }
+
+    for folder in items {
+        if let Some(children) = &folder.item {
+            let path = if prefix.is_empty() {
+                folder.name.clone()
+            } else {
+                format!("{prefix}/{}", folder.name)
+            };
+            collect_groups(&path, &path, children, groups);
+        }
+    }
+}
+
+/// Renders a single request as a `[[test_groups.tests]]` entry. The first
+/// origin seen, either from a `{{variable}}` prefix or an absolute URL, is
+/// stored in `base_url`. Requests to any other origin keep their absolute
+/// URL.
+fn render_test(collection: &Collection, item: &Item, base_url: &mut Option<String>) -> String {
+    let (method, headers, raw_url, body) = match item.request.as_ref() {
+        Some(Request::Url(url)) => ("GET", &[][..], url.as_str(), None),
+        Some(Request::Full {
+            method,
+            header,
+            url,
+            body,
+        }) => {
+            let raw = match url {
+                RequestUrl::Raw(raw) | RequestUrl::Parts { raw } => raw.as_str(),
+            };
+            (method.as_str(), &header[..], raw, body.as_ref())
+        }
+        None => return String::new(),
+    };
+
+    let (origin, path) = split_url(collection, raw_url);
+    if base_url.is_none() {
+        base_url.clone_from(&origin);
+    }
+    let (path, query) = match path.split_once('?') {
+        Some((path, query)) => (path.to_string(), Some(format!("?{query}"))),
+        None => (path, None),
+    };
+    let path = if path.is_empty() { "/" } else { &path };
+    let url = match origin {
+        Some(origin) if base_url.as_ref() != Some(&origin) => format!("{origin}{path}"),
+        _ => path.to_string(),
+    };
+
+    let mut test = format!(
+        "\n[[test_groups.tests]]\nname = {}\nmethod = {}\nurl = {}\n",
+        quote(&item.name),
+        quote(&method.to_uppercase()),
+        quote(&url),
+    );
+
+    if let Some(query) = query {
+        test.push_str(&format!("query = {}\n", quote(&query)));
+    }
+
+    let headers: toml::Table = headers
+        .iter()
+        .filter(|h| !h.disabled)
+        .map(|h| (h.key.clone(), toml::Value::String(h.value.clone())))
+        .collect();
+    if !headers.is_empty() {
+        test.push_str(&format!("headers = {}\n", toml::Value::Table(headers)));
+    }
+
+    if let Some(body) = body
+        .filter(|b| b.mode == "raw")
+        .and_then(|b| b.raw.as_ref())
+    {
+        match serde_json::from_str::<serde_json::Value>(body)
+            .ok()
+            .and_then(|json| toml::Value::try_from(json).ok())
+        {
+            Some(body) => test.push_str(&format!("body = {body}\n")),
+            None => test.push_str("# The request body is not JSON and was not imported\n"),
+        }
+    }
+
+    if let Some(code) = item.response.iter().find_map(|r| r.code) {
+        test.push_str(&format!("assert_status = {code}\n"));
+    }
+
+    test
+}
+
+/// Splits a Postman URL into its origin and the path that goes into `url`.
+///
+/// A leading `{{variable}}` is resolved against the collection variables, so
+/// `{{baseUrl}}/users` becomes the origin of `baseUrl` and `/users`.
+fn split_url(collection: &Collection, raw: &str) -> (Option<String>, String) {
+    if let Some(rest) = raw.strip_prefix("{{")
+        && let Some((name, path)) = rest.split_once("}}")
+    {
+        let origin = collection
+            .variable
+            .iter()
+            .find(|v| v.key == name)
+            .and_then(|v| v.value.as_str())
+            .map(|v| v.trim_end_matches('/').to_string());
+        return (origin, path.to_string());
+    }
+
+    match Url::parse(raw) {
+        Ok(url) if url.has_host() => {
+            let mut path = url.path().to_string();
+            if let Some(query) = url.query() {
+                path.push_str(&format!("?{query}"));
+            }
+            (Some(url.origin().ascii_serialization()), path)
+        }
+        _ => (None, raw.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::parser::TestQuest;
+    use crate::postman::Collection;
+    use crate::postman::generate_config;
+
+    const COLLECTION: &str = r#"{
+        "info": { "name": "Users API" },
+        "variable": [{ "key": "baseUrl", "value": "http://localhost:6969" }],
+        "item": [
+            {
+                "name": "Health",
+                "request": "{{baseUrl}}/health"
+            },
+            {
+                "name": "Token",
+                "request": "https://auth.example.com/token?grant=client"
+            },
+            {
+                "name": "users",
+                "item": [
+                    {
+                        "name": "CreateUser",
+                        "request": {
+                            "method": "POST",
+                            "header": [
+                                { "key": "Content-Type", "value": "application/json" },
+                                { "key": "X-Debug", "value": "1", "disabled": true }
+                            ],
+                            "url": { "raw": "{{baseUrl}}/users?notify=true" },
+                            "body": { "mode": "raw", "raw": "{\"name\": \"Alice\"}" }
+                        },
+                        "response": [{ "code": 201 }]
+                    }
+                ]
+            }
+        ]
+    }"#;
+
+    #[test]
+    fn folders_become_groups_and_other_origins_keep_their_url() {
+        let collection: Collection = serde_json::from_str(COLLECTION).unwrap();
+
+        let config = generate_config(&collection);
+        let test_quest: TestQuest = toml::from_str(&config).unwrap();
+
+        assert_eq!(test_quest.setup.base_url, "http://localhost:6969");
+        let groups: Vec<_> = test_quest.test_groups.iter().map(|g| &g.name).collect();
+        assert_eq!(groups, ["Users API", "users"]);
+
+        let token = &test_quest.test_groups[0].tests[1];
+        assert_eq!(token.url, "https://auth.example.com/token");
+        assert_eq!(token.query.as_deref(), Some("?grant=client"));
+
+        let create = &test_quest.test_groups[1].tests[0];
+        assert_eq!(create.method, "POST");
+        assert_eq!(create.url, "/users");
+        assert_eq!(create.query.as_deref(), Some("?notify=true"));
+        assert_eq!(create.body, Some(serde_json::json!({ "name": "Alice" })));
+        assert_eq!(create.assert_status, Some(201));
+
+        let headers = create.headers.as_ref().unwrap().as_table().unwrap();
+        assert_eq!(headers.len(), 1);
+        assert_eq!(headers["Content-Type"].as_str(), Some("application/json"));
+    }
+}
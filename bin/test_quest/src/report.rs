@@ -0,0 +1,163 @@
+use serde::Serialize;
+
+use crate::asserter::TestOutcome;
+use crate::asserter::TestResult;
+
+pub mod artifacts;
+pub mod junit;
+
+/// The results of a run returned by [`crate::run_suite`], with the tests in
+/// the order they finished, grouped by their test group.
+#[derive(Debug, Clone, Serialize)]
+pub struct RunReport {
+    pub groups: Vec<GroupReport>,
+    /// Number of tests where every assertion passed.
+    pub passed: usize,
+    /// Number of tests with at least one failed assertion.
+    pub failed: usize,
+    /// Number of tests that didn't run because a test they depend on failed.
+    pub skipped: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GroupReport {
+    pub name: String,
+    pub tests: Vec<TestReport>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TestReport {
+    pub name: String,
+    pub method: String,
+    pub url: String,
+    pub duration_ms: u128,
+    pub passed: bool,
+    /// Why the test wasn't run, if it was skipped.
+    pub skipped: Option<String>,
+    pub assertions: Vec<AssertionReport>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AssertionReport {
+    /// The kind of assertion, e.g. `Status test`.
+    pub assertion: String,
+    pub passed: bool,
+    /// What the response held instead, e.g. `Got status 404`.
+    pub actual: String,
+}
+
+impl RunReport {
+    /// Whether every test passed.
+    pub fn success(&self) -> bool {
+        self.failed == 0
+    }
+
+    pub fn from_outcomes(outcomes: impl IntoIterator<Item = TestOutcome>) -> Self {
+        let mut groups: Vec<GroupReport> = vec![];
+        let (mut passed, mut failed, mut skipped) = (0, 0, 0);
+
+        for outcome in outcomes {
+            let assertions: Vec<AssertionReport> = outcome
+                .results
+                .iter()
+                .map(|result| AssertionReport {
+                    assertion: result.expected.to_string(),
+                    passed: result.status == TestResult::Pass,
+                    actual: console::strip_ansi_codes(&result.actual.to_string()).into_owned(),
+                })
+                .collect();
+            let test_passed = outcome.skipped.is_none() && assertions.iter().all(|a| a.passed);
+            if outcome.skipped.is_some() {
+                skipped += 1;
+            } else if test_passed {
+                passed += 1;
+            } else {
+                failed += 1;
+            }
+
+            let test = TestReport {
+                name: outcome.name,
+                method: outcome.method,
+                url: outcome.url.to_string(),
+                duration_ms: outcome.duration.as_millis(),
+                passed: test_passed,
+                skipped: outcome.skipped,
+                assertions,
+            };
+
+            match groups.iter_mut().find(|g| g.name == outcome.group) {
+                Some(group) => group.tests.push(test),
+                None => groups.push(GroupReport {
+                    name: outcome.group,
+                    tests: vec![test],
+                }),
+            }
+        }
+
+        Self {
+            groups,
+            passed,
+            failed,
+            skipped,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use std::time::Duration;
+    use std::time::Instant;
+
+    use reqwest::StatusCode;
+    use url::Url;
+
+    use crate::asserter::Actual;
+    use crate::asserter::AssertResult;
+    use crate::asserter::TestOutcome;
+    use crate::asserter::TestResult;
+    use crate::report::RunReport;
+    use crate::runner::RequestDetails;
+    use crate::validator::Assertion;
+
+    fn outcome(group: &str, name: &str, status: TestResult) -> TestOutcome {
+        let now = Instant::now();
+        TestOutcome {
+            name: name.into(),
+            group: group.into(),
+            path: "/users".into(),
+            method: "GET".into(),
+            results: Arc::from([AssertResult {
+                status,
+                expected: Assertion::Status(200),
+                actual: Actual::Status(StatusCode::NOT_FOUND),
+                location: None,
+            }]),
+            duration: Duration::from_millis(12),
+            location: None,
+            url: Url::parse("http://localhost:6969/users").unwrap(),
+            request: RequestDetails::default(),
+            ran_during: now..now,
+            skipped: None,
+        }
+    }
+
+    #[test]
+    fn outcomes_are_grouped_and_counted() {
+        let report = RunReport::from_outcomes([
+            outcome("users", "ListUsers", TestResult::Pass),
+            outcome("health", "Health", TestResult::Pass),
+            outcome("users", "GetUser", TestResult::Fail),
+        ]);
+
+        let groups: Vec<_> = report.groups.iter().map(|g| g.name.as_str()).collect();
+        assert_eq!(groups, ["users", "health"]);
+        assert_eq!(report.groups[0].tests.len(), 2);
+        assert_eq!((report.passed, report.failed), (2, 1));
+        assert!(!report.success());
+
+        let failed = &report.groups[0].tests[1].assertions[0];
+        assert_eq!(failed.assertion, "Status test");
+        assert_eq!(failed.actual, "Got status 404 Not Found");
+    }
+}
@@ -0,0 +1,109 @@
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+
+use chrono::DateTime;
+use chrono::Local;
+
+use crate::report::RunReport;
+use crate::setup::app::OutputLine;
+use crate::setup::app::OutputSource;
+
+/// Everything `--report-dir` keeps of a run.
+pub struct RunArtifacts<'a> {
+    pub report: &'a RunReport,
+    pub junit: &'a str,
+    /// The app's stdout and stderr up to the end of the run.
+    pub app_output: &'a [OutputLine],
+    /// The config the run used, as `--config-check` prints it, so with its
+    /// secrets already redacted unless `--show-secrets` was set.
+    pub config: &'a str,
+}
+
+/// Writes `artifacts` into a folder of `dir` named after when the run
+/// `started`, e.g. `2025-03-01T12-30-05`, and returns that folder. Missing
+/// folders are created, and the files of an existing one are overwritten.
+pub fn write_run(
+    dir: &Path,
+    started: DateTime<Local>,
+    artifacts: &RunArtifacts,
+) -> io::Result<PathBuf> {
+    let folder = dir.join(started.format("%Y-%m-%dT%H-%M-%S").to_string());
+    std::fs::create_dir_all(&folder)?;
+
+    let report = serde_json::to_string_pretty(artifacts.report)?;
+    std::fs::write(folder.join("report.json"), report)?;
+    std::fs::write(folder.join("junit.xml"), artifacts.junit)?;
+    std::fs::write(folder.join("app_output.log"), app_log(artifacts.app_output))?;
+    std::fs::write(folder.join("config.txt"), artifacts.config)?;
+
+    Ok(folder)
+}
+
+/// The app output with each line labeled by the stream it came from, like
+/// `--app-output` prints it.
+fn app_log(output: &[OutputLine]) -> String {
+    output
+        .iter()
+        .map(|item| match item.source {
+            OutputSource::StdOut => format!("[STDOUT] {}\n", item.line),
+            OutputSource::StdErr => format!("[STDERR] {}\n", item.line),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Instant;
+
+    use chrono::Local;
+    use chrono::TimeZone;
+
+    use crate::report::RunReport;
+    use crate::report::artifacts::RunArtifacts;
+    use crate::report::artifacts::write_run;
+    use crate::setup::app::OutputLine;
+    use crate::setup::app::OutputSource;
+
+    #[test]
+    fn run_artifacts_go_to_a_folder_per_run() {
+        let dir = std::env::temp_dir().join(format!("tq-report-{}", uuid::Uuid::new_v4()));
+        let started = Local.with_ymd_and_hms(2025, 3, 1, 12, 30, 5).unwrap();
+        let report = RunReport::from_outcomes([]);
+        let app_output = [
+            OutputLine {
+                source: OutputSource::StdOut,
+                line: "listening on 8080".into(),
+                at: Instant::now(),
+            },
+            OutputLine {
+                source: OutputSource::StdErr,
+                line: "GET /users 500".into(),
+                at: Instant::now(),
+            },
+        ];
+        let artifacts = RunArtifacts {
+            report: &report,
+            junit: "<testsuites/>",
+            app_output: &app_output,
+            config: "concurrency: 1\n",
+        };
+
+        let folder = write_run(&dir, started, &artifacts).unwrap();
+        assert_eq!(folder, dir.join("2025-03-01T12-30-05"));
+        // A second run in the same second writes to the same folder.
+        assert_eq!(write_run(&dir, started, &artifacts).unwrap(), folder);
+
+        let read = |name: &str| std::fs::read_to_string(folder.join(name)).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&read("report.json")).unwrap();
+        assert_eq!(json["passed"], 0);
+        assert_eq!(read("junit.xml"), "<testsuites/>");
+        assert_eq!(
+            read("app_output.log"),
+            "[STDOUT] listening on 8080\n[STDERR] GET /users 500\n"
+        );
+        assert_eq!(read("config.txt"), "concurrency: 1\n");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,268 @@
+use chrono::DateTime;
+use chrono::Local;
+
+use crate::report::GroupReport;
+use crate::report::RunReport;
+use crate::report::TestReport;
+
+/// What a JUnit report says about the run as a whole, next to its tests.
+pub struct JunitRun<'a> {
+    /// The `name` of `<testsuites>`.
+    pub suite_name: &'a str,
+    /// When the run started.
+    pub timestamp: DateTime<Local>,
+    pub hostname: &'a str,
+}
+
+/// `report` as JUnit XML, with one `<testsuite>` per test group and one
+/// `<testcase>` per test. Every failed assertion of a test is listed in its
+/// `<failure>`.
+pub fn to_junit(report: &RunReport, run: &JunitRun) -> String {
+    let timestamp = run.timestamp.format("%Y-%m-%dT%H:%M:%S").to_string();
+    let time: u128 = report
+        .groups
+        .iter()
+        .flat_map(|g| &g.tests)
+        .map(|t| t.duration_ms)
+        .sum();
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str(&format!(
+        "<testsuites name=\"{}\" tests=\"{}\" failures=\"{}\" skipped=\"{}\" time=\"{}\" timestamp=\"{timestamp}\">\n",
+        escape(run.suite_name),
+        report.passed + report.failed + report.skipped,
+        report.failed,
+        report.skipped,
+        seconds(time),
+    ));
+    for group in &report.groups {
+        push_suite(&mut xml, group, &timestamp, run.hostname);
+    }
+    xml.push_str("</testsuites>\n");
+    xml
+}
+
+fn push_suite(xml: &mut String, group: &GroupReport, timestamp: &str, hostname: &str) {
+    let failures = group
+        .tests
+        .iter()
+        .filter(|t| t.skipped.is_none() && !t.passed)
+        .count();
+    let skipped = group.tests.iter().filter(|t| t.skipped.is_some()).count();
+    let time: u128 = group.tests.iter().map(|t| t.duration_ms).sum();
+
+    xml.push_str(&format!(
+        "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{failures}\" errors=\"0\" skipped=\"{skipped}\" time=\"{}\" timestamp=\"{timestamp}\" hostname=\"{}\">\n",
+        escape(&group.name),
+        group.tests.len(),
+        seconds(time),
+        escape(hostname),
+    ));
+    for test in &group.tests {
+        push_case(xml, &group.name, test);
+    }
+    xml.push_str("  </testsuite>\n");
+}
+
+fn push_case(xml: &mut String, group: &str, test: &TestReport) {
+    xml.push_str(&format!(
+        "    <testcase name=\"{}\" classname=\"{}\" time=\"{}\"",
+        escape(&test.name),
+        escape(group),
+        seconds(test.duration_ms),
+    ));
+
+    if let Some(reason) = &test.skipped {
+        xml.push_str(&format!(
+            ">\n      <skipped message=\"{}\"/>\n    </testcase>\n",
+            escape(reason)
+        ));
+        return;
+    }
+
+    let failed: Vec<_> = test.assertions.iter().filter(|a| !a.passed).collect();
+    let Some(first) = failed.first() else {
+        xml.push_str("/>\n");
+        return;
+    };
+
+    let details: Vec<String> = failed
+        .iter()
+        .map(|a| format!("{}: {}", a.assertion, a.actual))
+        .collect();
+    xml.push_str(&format!(
+        ">\n      <failure message=\"{}\" type=\"{}\">{} {}\n{}</failure>\n    </testcase>\n",
+        escape(&first.actual),
+        escape(&first.assertion),
+        escape(&test.method),
+        escape(&test.url),
+        escape(&details.join("\n")),
+    ));
+}
+
+/// Milliseconds as the seconds JUnit expects, e.g. `0.012`.
+fn seconds(ms: u128) -> String {
+    format!("{}.{:03}", ms / 1000, ms % 1000)
+}
+
+/// `s` with the characters XML gives a meaning replaced by entities, and
+/// the control characters it doesn't allow at all left out.
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\n' | '\t' | '\r' => escaped.push(c),
+            c if c.is_control() => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use std::time::Duration;
+    use std::time::Instant;
+
+    use chrono::Local;
+    use chrono::TimeZone;
+    use reqwest::StatusCode;
+    use url::Url;
+
+    use crate::asserter::Actual;
+    use crate::asserter::AssertResult;
+    use crate::asserter::TestOutcome;
+    use crate::asserter::TestResult;
+    use crate::report::RunReport;
+    use crate::report::junit::JunitRun;
+    use crate::report::junit::to_junit;
+    use crate::runner::RequestDetails;
+    use crate::validator::Assertion;
+
+    fn outcome(name: &str, status: TestResult, skipped: Option<&str>) -> TestOutcome {
+        let now = Instant::now();
+        TestOutcome {
+            name: name.into(),
+            group: "users & roles".into(),
+            path: "/users".into(),
+            method: "GET".into(),
+            results: Arc::from([AssertResult {
+                status,
+                expected: Assertion::Status(200),
+                actual: Actual::Status(StatusCode::NOT_FOUND),
+                location: None,
+            }]),
+            duration: Duration::from_millis(1250),
+            location: None,
+            url: Url::parse("http://localhost:6969/users?page=1&size=2").unwrap(),
+            request: RequestDetails::default(),
+            ran_during: now..now,
+            skipped: skipped.map(String::from),
+        }
+    }
+
+    #[test]
+    fn junit_report_has_run_and_test_metadata() {
+        let report = RunReport::from_outcomes([
+            outcome("ListUsers", TestResult::Pass, None),
+            outcome("GetUser <1>", TestResult::Fail, None),
+            outcome("DeleteUser", TestResult::Pass, Some("GetUser failed")),
+        ]);
+        let run = JunitRun {
+            suite_name: "api",
+            timestamp: Local.with_ymd_and_hms(2025, 3, 1, 12, 30, 5).unwrap(),
+            hostname: "ci-runner",
+        };
+
+        let xml = to_junit(&report, &run);
+        assert!(xml.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n"));
+
+        let doc = roxmltree::Document::parse(&xml).unwrap();
+        let attrs = |node: roxmltree::Node| -> Vec<(String, String)> {
+            node.attributes()
+                .map(|a| (a.name().to_string(), a.value().to_string()))
+                .collect()
+        };
+        let expected = |pairs: &[(&str, &str)]| -> Vec<(String, String)> {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+
+        let suites = doc.root_element();
+        assert_eq!(suites.tag_name().name(), "testsuites");
+        assert_eq!(
+            attrs(suites),
+            expected(&[
+                ("name", "api"),
+                ("tests", "3"),
+                ("failures", "1"),
+                ("skipped", "1"),
+                ("time", "3.750"),
+                ("timestamp", "2025-03-01T12:30:05"),
+            ])
+        );
+
+        let suite: Vec<_> = suites.children().filter(|n| n.is_element()).collect();
+        let [suite] = suite.as_slice() else {
+            panic!("expected a single testsuite");
+        };
+        assert_eq!(suite.tag_name().name(), "testsuite");
+        assert_eq!(
+            attrs(*suite),
+            expected(&[
+                ("name", "users & roles"),
+                ("tests", "3"),
+                ("failures", "1"),
+                ("errors", "0"),
+                ("skipped", "1"),
+                ("time", "3.750"),
+                ("timestamp", "2025-03-01T12:30:05"),
+                ("hostname", "ci-runner"),
+            ])
+        );
+
+        let cases: Vec<_> = suite.children().filter(|n| n.is_element()).collect();
+        let [list, get, delete] = cases.as_slice() else {
+            panic!("expected three testcases");
+        };
+        assert_eq!(
+            attrs(*list),
+            expected(&[
+                ("name", "ListUsers"),
+                ("classname", "users & roles"),
+                ("time", "1.250"),
+            ])
+        );
+        assert!(!list.has_children());
+
+        assert_eq!(get.attribute("name"), Some("GetUser <1>"));
+        let failure = get.first_element_child().unwrap();
+        assert_eq!(failure.tag_name().name(), "failure");
+        assert_eq!(
+            attrs(failure),
+            expected(&[
+                ("message", "Got status 404 Not Found"),
+                ("type", "Status test"),
+            ])
+        );
+        assert!(
+            failure
+                .text()
+                .unwrap()
+                .starts_with("GET http://localhost:6969/users?page=1&size=2")
+        );
+
+        assert_eq!(delete.attribute("name"), Some("DeleteUser"));
+        let skipped = delete.first_element_child().unwrap();
+        assert_eq!(skipped.tag_name().name(), "skipped");
+        assert_eq!(attrs(skipped), expected(&[("message", "GetUser failed")]));
+    }
+}
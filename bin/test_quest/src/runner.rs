@@ -1,19 +1,67 @@
 #![allow(clippy::enum_variant_names)]
 
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::io;
+use std::ops::Range;
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::sync::OnceLock;
+use std::time::Duration;
+use std::time::Instant;
 
 use flume::SendError;
 use flume::Sender;
+use futures::SinkExt;
+use futures::StreamExt;
+use futures::TryStreamExt;
+use http_body_util::BodyExt;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use reqwest::Certificate;
 use reqwest::Client;
+use reqwest::NoProxy;
+use reqwest::Proxy;
+use reqwest::RequestBuilder;
 use reqwest::Response;
 use reqwest::StatusCode;
+use reqwest::Version;
+use reqwest::header::AUTHORIZATION;
+use reqwest::header::COOKIE;
 use reqwest::header::HeaderMap;
+use reqwest::header::LOCATION;
+use reqwest::header::PROXY_AUTHORIZATION;
+use reqwest::header::WWW_AUTHENTICATE;
+use reqwest::redirect::Policy;
 use thiserror::Error;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Semaphore;
+use tokio::sync::SemaphorePermit;
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
 use url::Url;
 
+use crate::asserter::Assert;
+use crate::asserter::AssertResult;
+use crate::asserter::TestResult;
+use crate::asserter::assert_sql;
+use crate::parser::StringOrStrings;
 use crate::setup::database::any_db::AnyDbPool;
+use crate::setup::database::any_db::AnyRow;
+use crate::setup::database::any_db::DbValue;
+use crate::setup::database::any_db::SqlRows;
 use crate::validator::Assertion;
+use crate::validator::BeforeEach;
+use crate::validator::ClientConfig;
+use crate::validator::DbReset;
+use crate::validator::HookStatement;
 use crate::validator::IR;
+use crate::validator::SourceLocation;
+use crate::validator::TestGroups;
+use crate::validator::ValidatedTests;
+use crate::validator::WsRequest;
 
 #[derive(Error, Debug)]
 // TODO: Fix large enum
@@ -24,142 +72,1080 @@ pub enum RunnerError {
 
     #[error("database error")]
     DatabaseError(#[from] sqlx::Error),
+
+    #[error("failed to build the HTTP client: {0}")]
+    ClientError(#[from] reqwest::Error),
+
+    #[error("failed to read CA certificate {0}: {1}")]
+    CaCertError(PathBuf, std::io::Error),
+
+    #[error("expected `{query}` to affect {expected} rows, but it affected {actual}")]
+    UnexpectedRowsAffected {
+        query: String,
+        expected: u64,
+        actual: u64,
+    },
 }
 
 #[derive(Debug)]
 pub struct RunnerResult {
     pub name: String,
+    pub group: String,
     pub method: String,
     pub url: Url,
     pub response: Option<CapturedResponse>,
     pub error: Option<String>,
-    pub assertions: Vec<Assertion>,
+    pub assertions: Arc<Vec<Assertion>>,
+    pub duration: Duration,
+    pub location: Option<SourceLocation>,
+    /// Where each of `assertions` was defined, in the same order.
+    pub assertion_locations: Vec<Option<SourceLocation>>,
+    pub request: RequestDetails,
+    /// From the start of the test's hooks until its SQL assertions finished.
+    pub ran_during: Range<Instant>,
+    /// Why the test wasn't run, if it was skipped.
+    pub skipped: Option<String>,
+    /// The timeout of the group, if it ran out before the test finished.
+    pub timed_out: Option<Duration>,
+    /// The assertion results, evaluated once on first use so snapshot and
+    /// other backends with side effects don't run twice.
+    pub verdict: OnceLock<Arc<[AssertResult]>>,
+}
+
+impl RunnerResult {
+    /// The result of a test whose hook failed, so its request was never sent.
+    fn hook_failed(test: ValidatedTests, error: String, ran_during: Range<Instant>) -> Self {
+        Self {
+            name: test.name,
+            group: test.group,
+            method: test.method.to_string(),
+            url: test.url,
+            response: None,
+            error: Some(error),
+            assertions: test.assertions,
+            duration: Duration::ZERO,
+            location: test.location,
+            assertion_locations: test.assertion_locations,
+            request: RequestDetails {
+                headers: test.headers,
+                body: test.body,
+            },
+            ran_during,
+            skipped: None,
+            timed_out: None,
+            verdict: OnceLock::new(),
+        }
+    }
+
+    /// The result of a test that was skipped because `dependency` failed or
+    /// was skipped itself.
+    fn dependency_failed(test: ValidatedTests, dependency: &str) -> Self {
+        let now = Instant::now();
+        Self {
+            skipped: Some(format!("dependency failed: {dependency}")),
+            error: None,
+            ..Self::hook_failed(test, String::new(), now..now)
+        }
+    }
+
+    /// The result of a test that hadn't finished when its group ran past
+    /// `timeout`.
+    fn timed_out(test: ValidatedTests, timeout: Duration, ran_during: Range<Instant>) -> Self {
+        Self {
+            error: None,
+            timed_out: Some(timeout),
+            ..Self::hook_failed(test, String::new(), ran_during)
+        }
+    }
+
+    fn passed(&self) -> bool {
+        self.assert()
+            .iter()
+            .all(|result| result.status == TestResult::Pass)
+    }
 }
 
+/// The headers and body a test sent, kept so a failed request can be
+/// reproduced by hand.
+#[derive(Debug, Clone, Default)]
+pub struct RequestDetails {
+    pub headers: Arc<HeaderMap>,
+    pub body: Option<Arc<serde_json::Value>>,
+}
+
+/// Runs every test group in order, sending a `RunnerResult` per test to the
+/// asserter.
+///
+/// With `parallel_groups` greater than 1, up to that many groups run at once.
+/// Groups share the database, so a group that resets it (in `before_group` or
+/// a test's `before_run`) would wipe the data of the groups running alongside
+/// it. Such a group waits for the groups before it to finish and runs on its
+/// own.
 pub async fn run_tests(
     ir: IR,
+    clients: &Clients,
     tx: Sender<RunnerResult>,
     pool: Arc<AnyDbPool>,
+    parallel_groups: usize,
 ) -> Result<(), RunnerError> {
-    let client = Client::new();
+    let concurrency = ir.concurrency;
+    let mut groups = ir.tests.into_iter().peekable();
+
+    while let Some(group) = groups.next() {
+        if parallel_groups <= 1 || resets_database(&group) {
+            run_group(group, concurrency, clients, tx.clone(), &pool).await?;
+            continue;
+        }
 
-    for test_group in ir.tests {
-        let tx = tx.clone();
-        let client = client.clone();
+        let mut batch = vec![group];
+        while let Some(group) = groups.next_if(|group| !resets_database(group)) {
+            batch.push(group);
+        }
 
-        // If the test group has put database reset to true, we reset the database
-        // before the tests run
-        if let Some(before) = test_group.before_group {
-            if before.reset_db.is_some_and(|b| b) {
-                reset_database(&pool)
-                    .await
-                    .map_err(RunnerError::DatabaseError)?;
+        let mut running = futures::stream::iter(batch)
+            .map(|group| run_group(group, concurrency, clients, tx.clone(), &pool))
+            .buffer_unordered(parallel_groups);
+        while let Some(result) = running.next().await {
+            result?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Runs the whole suite over and over until a test fails, or until `max`
+/// iterations passed if it is set. Results are forwarded to `tx` as they come
+/// in, and the iteration is abandoned as soon as one of them failed.
+///
+/// Returns the iteration, counting from 1, in which a test failed, or `None`
+/// if every iteration passed.
+pub async fn run_until_failure(
+    ir: IR,
+    clients: &Clients,
+    tx: Sender<RunnerResult>,
+    pool: Arc<AnyDbPool>,
+    parallel_groups: usize,
+    max: Option<u32>,
+) -> Result<Option<u32>, RunnerError> {
+    for iteration in (1..).take_while(|n| max.is_none_or(|max| *n <= max)) {
+        let (iteration_tx, iteration_rx) = flume::unbounded();
+        let mut run = std::pin::pin!(run_tests(
+            ir.clone(),
+            clients,
+            iteration_tx,
+            pool.clone(),
+            parallel_groups
+        ));
+        let mut forward = std::pin::pin!(async {
+            while let Ok(result) = iteration_rx.recv_async().await {
+                let passed = result.passed();
+                tx.send_async(result).await?;
+                if !passed {
+                    return Ok::<_, RunnerError>(true);
+                }
             }
+            Ok(false)
+        });
 
-            if let Some(sql_statements) = &before.sql {
-                run_sql(&pool, sql_statements).await?
+        // The results channel only closes once the run is over, so when
+        // nothing failed the run is awaited to surface its errors.
+        let failed = tokio::select! {
+            result = &mut run => {
+                result?;
+                forward.await?
+            }
+            failed = &mut forward => {
+                let failed = failed?;
+                if !failed {
+                    run.await?;
+                }
+                failed
             }
+        };
+
+        if failed {
+            return Ok(Some(iteration));
         }
+    }
 
-        for mut test in test_group.tests {
-            let client = client.clone();
-            let tx = tx.clone();
-            let url = test.url.clone();
-            let method = test.method.to_string().clone();
-
-            // TODO: Duplicated logic with the one above
-            if let Some(before) = test.before_run {
-                if before.reset_db.is_some_and(|b| b) {
-                    reset_database(&pool)
-                        .await
-                        .map_err(RunnerError::DatabaseError)?;
-                }
+    Ok(None)
+}
+
+/// Whether any hook of `group` resets the database.
+fn resets_database(group: &TestGroups) -> bool {
+    std::iter::once(&group.before_group)
+        .chain(group.tests.iter().map(|test| &test.before_run))
+        .flatten()
+        .any(|hook| hook.reset_db.is_some())
+}
+
+/// Runs the tests of a single group.
+///
+/// The `before_group` hook always runs once before any test in the group. If
+/// one of its statements doesn't affect the expected number of rows, every test
+/// in the group fails without sending its request.
+/// After that, the tests in a group run one at a time, unless `concurrency`
+/// is greater than 1 and none of them has a `before_run` hook, in which case
+/// up to `concurrency` tests are in flight at once and results are sent in the
+/// order they complete.
+///
+/// A test whose `depends_on` names a test that failed or was skipped is
+/// skipped without sending its request, and groups with dependencies always
+/// run sequentially. So do groups that store bodies with `capture_json`,
+/// which are handed to the `assert_json_equals_var` assertions after them.
+///
+/// A group with a `timeout` that runs past it is cut short, failing every
+/// test that hadn't finished by then, including those still in flight.
+async fn run_group(
+    test_group: TestGroups,
+    concurrency: usize,
+    clients: &Clients,
+    tx: Sender<RunnerResult>,
+    pool: &AnyDbPool,
+) -> Result<(), RunnerError> {
+    let mut finished = HashSet::new();
+    let Some(timeout) = test_group.timeout else {
+        return run_group_tests(test_group, concurrency, clients, &tx, pool, &mut finished).await;
+    };
 
-                if let Some(sql_statements) = &before.sql {
-                    run_sql(&pool, sql_statements).await?
+    let started = Instant::now();
+    let tests = test_group.tests.clone();
+    let run = run_group_tests(test_group, concurrency, clients, &tx, pool, &mut finished);
+    let Ok(result) = tokio::time::timeout(timeout, run).await else {
+        let ran_during = started..Instant::now();
+        for test in tests {
+            if !finished.contains(&test.name) {
+                let result = RunnerResult::timed_out(test, timeout, ran_during.clone());
+                tx.send_async(result).await?;
+            }
+        }
+        return Ok(());
+    };
+    result
+}
+
+/// Runs the hooks and tests of a group as `run_group` describes, noting the
+/// name of every test a result was sent for in `finished`.
+async fn run_group_tests(
+    test_group: TestGroups,
+    concurrency: usize,
+    clients: &Clients,
+    tx: &Sender<RunnerResult>,
+    pool: &AnyDbPool,
+    finished: &mut HashSet<String>,
+) -> Result<(), RunnerError> {
+    // If the test group has put database reset to true, we reset the database
+    // before the tests run
+    if let Some(before) = test_group.before_group {
+        let started = Instant::now();
+        let permit = clients.permit().await;
+        let hook = run_hook(pool, &before).await;
+        drop(permit);
+        match hook {
+            Err(err @ RunnerError::UnexpectedRowsAffected { .. }) => {
+                let error = format!("before_group hook failed: {err}");
+                let ran_during = started..Instant::now();
+                for test in test_group.tests {
+                    let result = RunnerResult::hook_failed(test, error.clone(), ran_during.clone());
+                    finished.insert(result.name.clone());
+                    tx.send_async(result).await?;
                 }
+                return Ok(());
             }
+            result => result?,
+        }
+    }
 
-            let result = if let Some(body) = test.body {
-                client
-                    .request(test.method, url)
-                    .headers(test.headers)
-                    .json(&body)
-            } else {
-                client.request(test.method, url).headers(test.headers)
+    // Tests with their own hooks may depend on the state left behind by the
+    // tests before them, so those groups always run sequentially.
+    let concurrent = concurrency > 1
+        && test_group.tests.iter().all(|test| {
+            test.before_run.is_none() && test.depends_on.is_empty() && !test.uses_captures()
+        });
+
+    if concurrent {
+        let mut results = futures::stream::iter(test_group.tests)
+            .map(|test| run_test(clients, test, pool))
+            .buffer_unordered(concurrency);
+
+        while let Some(runner_result) = results.next().await {
+            let runner_result = runner_result?;
+            finished.insert(runner_result.name.clone());
+            tx.send_async(runner_result).await?;
+        }
+    } else {
+        // Only the tests something depends on have to be checked here, the
+        // rest are left to the asserter.
+        let depended_on: HashSet<String> = test_group
+            .tests
+            .iter()
+            .flat_map(|test| test.depends_on.iter().cloned())
+            .collect();
+        let mut failed: HashSet<String> = HashSet::new();
+        let mut captures: HashMap<String, serde_json::Value> = HashMap::new();
+
+        for mut test in test_group.tests {
+            if let Some(dependency) = test.depends_on.iter().find(|d| failed.contains(*d)) {
+                let dependency = dependency.clone();
+                failed.insert(test.name.clone());
+                finished.insert(test.name.clone());
+                tx.send_async(RunnerResult::dependency_failed(test, &dependency))
+                    .await?;
+                continue;
             }
-            .send()
-            .await;
 
-            run_sql_assertions(&mut test.assertions, &pool).await;
+            fill_captures(&mut test, &captures);
+            let capture_json = test.capture_json.clone();
+            let runner_result = run_test(clients, test, pool).await?;
+            if let Some(var) = capture_json
+                && let Some(body) = runner_result
+                    .response
+                    .as_ref()
+                    .and_then(|r| r.body_json.clone())
+            {
+                captures.insert(var, body);
+            }
+            if depended_on.contains(&runner_result.name) && !runner_result.passed() {
+                failed.insert(runner_result.name.clone());
+            }
+            finished.insert(runner_result.name.clone());
+            tx.send_async(runner_result).await?;
+        }
+    }
 
-            let runner_result = match result {
-                Ok(resp) => RunnerResult {
-                    name: test.name,
-                    method,
-                    url: test.url.clone(),
-                    response: Some(CapturedResponse::from_response(resp).await),
-                    error: None,
-                    assertions: test.assertions,
-                },
-                Err(err) => RunnerResult {
-                    name: test.name,
-                    method,
-                    url: test.url,
-                    response: None,
-                    error: Some(err.to_string()),
-                    assertions: test.assertions,
-                },
-            };
+    Ok(())
+}
+
+/// Hands the bodies stored by earlier tests to the `assert_json_equals_var`
+/// assertions of `test`.
+fn fill_captures(test: &mut ValidatedTests, captures: &HashMap<String, serde_json::Value>) {
+    if !test.uses_captures() {
+        return;
+    }
+    for assertion in Arc::make_mut(&mut test.assertions).iter_mut() {
+        if let Assertion::JsonEqualsVar { var, captured } = assertion {
+            *captured = captures.get(var).cloned();
+        }
+    }
+}
+
+/// Shuffles the tests of every group using `seed`, so the same seed always
+/// gives the same order.
+///
+/// Tests with a `before_run` hook may set up state for the tests after them,
+/// and tests with a `depends_on` have to run after their dependencies, so
+/// both keep their position along with the tests they depend on. Comparing a
+/// body stored with `capture_json` counts as depending on the test storing
+/// it. Only the other tests trade places.
+pub fn shuffle_tests(ir: &mut IR, seed: u64) {
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    for group in &mut ir.tests {
+        let depended_on: HashSet<&str> = group
+            .tests
+            .iter()
+            .flat_map(|test| test.depends_on.iter().map(String::as_str))
+            .collect();
+        let compared: HashSet<&str> = group
+            .tests
+            .iter()
+            .flat_map(ValidatedTests::compared_captures)
+            .collect();
+        let slots: Vec<usize> = (0..group.tests.len())
+            .filter(|&i| {
+                let test = &group.tests[i];
+                test.before_run.is_none()
+                    && test.depends_on.is_empty()
+                    && test.compared_captures().next().is_none()
+                    && !depended_on.contains(test.name.as_str())
+                    && !test
+                        .capture_json
+                        .as_deref()
+                        .is_some_and(|var| compared.contains(var))
+            })
+            .collect();
+
+        let mut order = slots.clone();
+        order.shuffle(&mut rng);
+
+        let mut tests: Vec<Option<ValidatedTests>> = group.tests.drain(..).map(Some).collect();
+        let shuffled: Vec<ValidatedTests> = order.iter().filter_map(|&i| tests[i].take()).collect();
+
+        for (slot, test) in slots.into_iter().zip(shuffled) {
+            tests[slot] = Some(test);
+        }
+        group.tests = tests.into_iter().flatten().collect();
+    }
+}
+
+/// How many redirects are followed before giving up, the same limit reqwest
+/// uses by default.
+const MAX_REDIRECTS: usize = 10;
+
+/// How long a WebSocket test without `timeout_ms` waits to connect and for
+/// a message.
+const DEFAULT_WS_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The HTTP clients requests are sent with. Tests with
+/// `follow_redirects = false` use `no_follow`, so they see the redirect
+/// response itself rather than where it leads.
+///
+/// They are built once from the `[setup]` client options and shared by every
+/// run, so connections are reused across tests and runs. They also hold the
+/// `--max-concurrency` limit, which every request, hook and SQL assertion
+/// query waits for, whichever group or test it belongs to.
+#[derive(Clone)]
+pub struct Clients {
+    follow: Client,
+    no_follow: Client,
+    limit: Option<Arc<Semaphore>>,
+}
+
+impl Clients {
+    pub fn new(config: &ClientConfig) -> Result<Self, RunnerError> {
+        Ok(Self {
+            follow: build_client(config, Policy::default())?,
+            no_follow: build_client(config, Policy::none())?,
+            limit: None,
+        })
+    }
+
+    /// Allows at most `max` requests and queries in flight at once across the
+    /// whole run. Without it there is no limit beyond the group and test
+    /// concurrency.
+    pub fn with_max_concurrency(mut self, max: Option<usize>) -> Self {
+        self.limit = max.map(|max| Arc::new(Semaphore::new(max)));
+        self
+    }
+
+    /// Waits until there is room under the concurrency limit. The slot is
+    /// held until the permit is dropped.
+    async fn permit(&self) -> Option<SemaphorePermit<'_>> {
+        self.limit.as_ref()?.acquire().await.ok()
+    }
+
+    fn for_test(&self, test: &ValidatedTests) -> &Client {
+        if test.follow_redirects {
+            &self.follow
+        } else {
+            &self.no_follow
+        }
+    }
+}
+
+/// Builds an HTTP client from the `[setup]` client options.
+fn build_client(config: &ClientConfig, redirect: Policy) -> Result<Client, RunnerError> {
+    let mut builder = Client::builder()
+        .redirect(redirect)
+        .danger_accept_invalid_certs(config.accept_invalid_certs)
+        .default_headers(config.default_headers.clone());
+
+    // After the default headers, so it replaces a `User-Agent` among them.
+    if let Some(user_agent) = &config.user_agent {
+        builder = builder.user_agent(user_agent.clone());
+    }
+
+    if let Some(path) = &config.ca_cert {
+        let pem = std::fs::read(path).map_err(|e| RunnerError::CaCertError(path.clone(), e))?;
+        builder = builder.add_root_certificate(Certificate::from_pem(&pem)?);
+    }
+
+    if config.http2 {
+        builder = builder.http2_prior_knowledge();
+    }
+
+    if let Some(timeout) = config.pool_idle_timeout {
+        builder = builder.pool_idle_timeout(timeout);
+    }
 
-            if let Err(error) = tx.send_async(runner_result).await {
-                todo!("{error}")
+    if let Some(max_idle) = config.pool_max_idle_per_host {
+        builder = builder.pool_max_idle_per_host(max_idle);
+    }
+
+    if let Some(proxy) = &config.proxy {
+        let no_proxy = NoProxy::from_string(&config.no_proxy.join(","));
+        builder = builder.proxy(Proxy::all(proxy)?.no_proxy(no_proxy));
+    }
+
+    Ok(builder.build()?)
+}
+
+/// Runs a single test: its `before_run` hook, the request itself and then the
+/// SQL assertions. The test fails without sending its request if a hook
+/// statement doesn't affect the expected number of rows.
+async fn run_test(
+    clients: &Clients,
+    mut test: ValidatedTests,
+    pool: &AnyDbPool,
+) -> Result<RunnerResult, RunnerError> {
+    let method = test.method.to_string();
+    let capture =
+        if !test.assertions.iter().any(Assertion::needs_body) && test.capture_json.is_none() {
+            BodyCapture::Skip
+        } else if test.capture_to_file {
+            BodyCapture::File
+        } else if test
+            .assertions
+            .iter()
+            .any(|a| matches!(a, Assertion::Trailers(_)))
+        {
+            BodyCapture::Trailers
+        } else if test.assertions.iter().any(Assertion::needs_raw_body) {
+            BodyCapture::Raw
+        } else {
+            BodyCapture::Memory
+        };
+    let started = Instant::now();
+
+    if let Some(before) = &test.before_run {
+        let permit = clients.permit().await;
+        let hook = run_hook(pool, before).await;
+        drop(permit);
+        match hook {
+            Err(err @ RunnerError::UnexpectedRowsAffected { .. }) => {
+                let error = format!("before_run hook failed: {err}");
+                let ran_during = started..Instant::now();
+                return Ok(RunnerResult::hook_failed(test, error, ran_during));
             }
+            result => result?,
         }
     }
+
+    let permit = clients.permit().await;
+    let start = Instant::now();
+    let result = if let Some(ws) = &test.ws {
+        exchange_ws(&test, ws).await.map(Received::WebSocket)
+    } else if test
+        .assertions
+        .iter()
+        .any(|a| matches!(a, Assertion::Redirect { .. }))
+    {
+        send_recording_redirects(&clients.no_follow, &test)
+            .await
+            .map(|(response, chain)| {
+                record_redirects(Arc::make_mut(&mut test.assertions).as_mut_slice(), chain);
+                Received::Http(response)
+            })
+    } else {
+        send_request(clients.for_test(&test), &test)
+            .await
+            .map(Received::Http)
+            .map_err(|err| error_chain(&err))
+    };
+    let duration = start.elapsed();
+    drop(permit);
+
+    if test
+        .assertions
+        .iter()
+        .any(|a| matches!(a, Assertion::Sql { .. } | Assertion::SqlCompare { .. }))
+    {
+        let assertions = Arc::make_mut(&mut test.assertions).as_mut_slice();
+        run_sql_assertions(assertions, pool, clients).await;
+    }
+    let ran_during = started..Instant::now();
+
+    let request = RequestDetails {
+        headers: test.headers,
+        body: test.body,
+    };
+
+    let result = match result {
+        Ok(Received::Http(resp)) => CapturedResponse::from_response(resp, capture).await,
+        Ok(Received::WebSocket(captured)) => Ok(captured),
+        Err(err) => Err(err),
+    };
+
+    let runner_result = match result {
+        Ok(response) => RunnerResult {
+            name: test.name,
+            group: test.group.clone(),
+            method,
+            url: test.url.clone(),
+            response: Some(response),
+            error: None,
+            assertions: test.assertions,
+            duration,
+            location: test.location,
+            assertion_locations: test.assertion_locations,
+            request,
+            ran_during,
+            skipped: None,
+            timed_out: None,
+            verdict: OnceLock::new(),
+        },
+        Err(err) => RunnerResult {
+            name: test.name,
+            group: test.group.clone(),
+            method,
+            url: test.url,
+            response: None,
+            error: Some(err),
+            assertions: test.assertions,
+            duration,
+            location: test.location,
+            assertion_locations: test.assertion_locations,
+            request,
+            ran_during,
+            skipped: None,
+            timed_out: None,
+            verdict: OnceLock::new(),
+        },
+    };
+
+    Ok(runner_result)
+}
+
+/// What a test got back before its body is read.
+enum Received {
+    Http(Response),
+    /// A WebSocket exchange is over by the time it is received, so it is
+    /// captured right away.
+    WebSocket(CapturedResponse),
+}
+
+/// Connects to the WebSocket at the test's URL, sends the configured message
+/// and waits for the first message back, for at most the test's timeout.
+///
+/// The message becomes the body of the returned response, next to the status
+/// and headers of the handshake, so the HTTP assertions check the upgrade and
+/// the body assertions check the message. A message that doesn't arrive in
+/// time leaves the body empty, while failing to connect is an error.
+async fn exchange_ws(test: &ValidatedTests, ws: &WsRequest) -> Result<CapturedResponse, String> {
+    let timeout = test.timeout.unwrap_or(DEFAULT_WS_TIMEOUT);
+    let deadline = tokio::time::Instant::now() + timeout;
+
+    let mut request = test
+        .url
+        .as_str()
+        .into_client_request()
+        .map_err(|err| error_chain(&err))?;
+    request
+        .headers_mut()
+        .extend(HeaderMap::clone(&test.headers));
+
+    let (mut stream, response) = tokio::time::timeout_at(deadline, connect_async(request))
+        .await
+        .map_err(|_| {
+            format!(
+                "WebSocket handshake timed out after {}ms",
+                timeout.as_millis()
+            )
+        })?
+        .map_err(|err| error_chain(&err))?;
+
+    if let Some(message) = &ws.send {
+        stream
+            .send(Message::text(message.as_str()))
+            .await
+            .map_err(|err| error_chain(&err))?;
+    }
+
+    let receive = async {
+        while let Some(message) = stream.next().await {
+            match message.map_err(|err| error_chain(&err))? {
+                Message::Text(text) => return Ok(Some(text.to_string())),
+                Message::Binary(bytes) => return Ok(Some(String::from_utf8_lossy(&bytes).into())),
+                Message::Close(_) => break,
+                Message::Ping(_) | Message::Pong(_) | Message::Frame(_) => {}
+            }
+        }
+        Ok::<_, String>(None)
+    };
+    let message = tokio::time::timeout_at(deadline, receive)
+        .await
+        .unwrap_or(Ok(None))?;
+    let _ = stream.close(None).await;
+
+    Ok(CapturedResponse {
+        status: response.status(),
+        version: response.version(),
+        headers: response.headers().clone(),
+        body_json: message
+            .as_deref()
+            .and_then(|m| serde_json::from_str(m).ok()),
+        body_text: message,
+        body_file: None,
+        body_bytes: None,
+        trailers: None,
+    })
+}
+
+/// `error` followed by each of its sources, as reqwest's own message leaves
+/// out the underlying cause, e.g. that the connection was refused.
+fn error_chain(error: &dyn std::error::Error) -> String {
+    let mut chain = error.to_string();
+    let mut source = error.source();
+    while let Some(cause) = source {
+        chain.push_str(&format!(": {cause}"));
+        source = cause.source();
+    }
+    chain
+}
+
+/// Resets the database if the hook asks for it and then runs its SQL.
+pub async fn run_hook(pool: &AnyDbPool, hook: &BeforeEach) -> Result<(), RunnerError> {
+    if let Some(reset) = &hook.reset_db {
+        reset_database(pool, reset)
+            .await
+            .map_err(RunnerError::DatabaseError)?;
+    }
+
+    if let Some(sql_statements) = &hook.sql {
+        run_sql(pool, sql_statements).await?
+    }
+
     Ok(())
 }
 
+/// Sends the request described by `test`, applying its timeout and retrying
+/// transport failures (connection refused, timeouts, ...) up to `test.retries`
+/// times. A response with any status code counts as a successful send.
+async fn send_request(client: &Client, test: &ValidatedTests) -> Result<Response, reqwest::Error> {
+    send_with_retries(test, || {
+        let request = client
+            .request(test.method.clone(), test.url.clone())
+            .headers(HeaderMap::clone(&test.headers));
+        match &test.body {
+            Some(body) => request.json(body.as_ref()),
+            None => request,
+        }
+    })
+    .await
+}
+
+/// Sends the request `build` makes with the timeout of `test`, building it
+/// anew for each of the `retries` of `test` when sending fails.
+async fn send_with_retries(
+    test: &ValidatedTests,
+    build: impl Fn() -> RequestBuilder,
+) -> Result<Response, reqwest::Error> {
+    let mut attempt = 0;
+
+    loop {
+        let mut request = build();
+        if let Some(timeout) = test.timeout {
+            request = request.timeout(timeout);
+        }
+
+        match request.send().await {
+            Err(_) if attempt < test.retries => attempt += 1,
+            result => return result,
+        }
+    }
+}
+
+/// Sends the request described by `test` and follows its redirects by hand,
+/// so every URL it went through is known. Returns the last response and the
+/// URLs, starting with the request's own.
+///
+/// Like browsers, a 307 or 308 repeats the request as is, while the other
+/// redirects are followed with a GET without a body. Like reqwest, the
+/// credentials among the headers aren't sent on once a redirect leaves the
+/// origin of the URL before it. Every hop is retried like the request itself,
+/// and a request that is still redirected after `MAX_REDIRECTS` hops fails.
+async fn send_recording_redirects(
+    client: &Client,
+    test: &ValidatedTests,
+) -> Result<(Response, Vec<Url>), String> {
+    let mut response = send_request(client, test)
+        .await
+        .map_err(|err| error_chain(&err))?;
+    let mut chain = vec![test.url.clone()];
+    let mut headers = HeaderMap::clone(&test.headers);
+
+    while response.status().is_redirection() {
+        let Some(next) = response
+            .headers()
+            .get(LOCATION)
+            .and_then(|location| location.to_str().ok())
+            .and_then(|location| response.url().join(location).ok())
+        else {
+            break;
+        };
+        if chain.len() > MAX_REDIRECTS {
+            return Err(format!(
+                "too many redirects, gave up after {MAX_REDIRECTS} at {}",
+                response.url()
+            ));
+        }
+
+        if next.origin() != response.url().origin() {
+            remove_credentials(&mut headers);
+        }
+        let repeat = matches!(
+            response.status(),
+            StatusCode::TEMPORARY_REDIRECT | StatusCode::PERMANENT_REDIRECT
+        );
+
+        response = send_with_retries(test, || {
+            let request = if repeat {
+                let request = client.request(test.method.clone(), next.clone());
+                match &test.body {
+                    Some(body) => request.json(body.as_ref()),
+                    None => request,
+                }
+            } else {
+                client.get(next.clone())
+            };
+            request.headers(headers.clone())
+        })
+        .await
+        .map_err(|err| error_chain(&err))?;
+        chain.push(next);
+    }
+
+    Ok((response, chain))
+}
+
+/// Leaves out the headers that carry credentials, which reqwest also drops
+/// when a redirect goes to another host.
+fn remove_credentials(headers: &mut HeaderMap) {
+    for name in [AUTHORIZATION, COOKIE, PROXY_AUTHORIZATION, WWW_AUTHENTICATE] {
+        headers.remove(name);
+    }
+}
+
+/// Fills the `got` field of each `Assertion::Redirect` with `chain`.
+fn record_redirects(assertions: &mut [Assertion], chain: Vec<Url>) {
+    for assertion in assertions {
+        if let Assertion::Redirect { got, .. } = assertion {
+            *got = Some(chain.clone());
+        }
+    }
+}
+
 /// Executes all SQL assertions in-place, handling multiple rows and types.
-/// Fills the `got` field for each `Assertion::Sql`.
-pub async fn run_sql_assertions(assertions: &mut [Assertion], pool: &AnyDbPool) {
+/// Fills the `got` field for each `Assertion::Sql` and `Assertion::SqlCompare`,
+/// with the error instead if the query failed.
+/// Polled queries are re-run
+/// until their rows match or they time out, leaving the last rows or error in
+/// `got`.
+/// Every query waits for room under the concurrency limit of `clients`.
+///
+/// Rows are read until there is one more than expected, which already fails
+/// the assertion, so large tables aren't read in full. `got` marks whether
+/// any rows after those weren't read.
+pub async fn run_sql_assertions(assertions: &mut [Assertion], pool: &AnyDbPool, clients: &Clients) {
     for ass in assertions.iter_mut() {
-        if let Assertion::Sql { query, got, .. } = ass {
-            let rows = pool.raw_sql(query).await.unwrap();
+        if let Assertion::SqlCompare { query, got, .. } = ass {
+            let permit = clients.permit().await;
+            *got = Some(sql_value(pool, query).await);
+            drop(permit);
+        } else if let Assertion::Sql {
+            query,
+            expect,
+            got,
+            epsilon,
+            poll,
+        } = ass
+        {
+            let poll = *poll;
+            let deadline = poll.map(|poll| Instant::now() + poll.timeout);
+            loop {
+                let limit = sql_row_limit(expect);
+                let permit = clients.permit().await;
+                let rows: Result<Vec<_>, _> =
+                    pool.raw_sql_stream(query).take(limit).try_collect().await;
+                drop(permit);
+
+                // A query that errors while polling may only be waiting for
+                // the state to appear, so it is retried like rows that don't
+                // match, and the last error is kept if it never succeeds.
+                let rows = rows
+                    .map(|rows| SqlRows {
+                        truncated: rows.len() == limit,
+                        rows,
+                    })
+                    .map_err(|error| error.to_string());
+                let matched = rows
+                    .as_ref()
+                    .is_ok_and(|rows| assert_sql(expect, Some(rows), *epsilon) == TestResult::Pass);
+                *got = Some(rows);
 
-            let vec_of_colums: Vec<String> = rows.iter().map(|row| row.to_csv_line()).collect();
-            *got = Some(vec_of_colums);
+                match (poll, deadline) {
+                    (Some(poll), Some(deadline)) if !matched && Instant::now() < deadline => {
+                        tokio::time::sleep(poll.interval.min(deadline - Instant::now())).await;
+                    }
+                    _ => break,
+                }
+            }
         }
     }
 }
 
-async fn run_sql(pool: &AnyDbPool, sql_statements: &Vec<String>) -> Result<(), RunnerError> {
+/// The single value `query` returns, or why it didn't return exactly one.
+/// NULL isn't a value either, as it can't be compared to anything.
+async fn sql_value(pool: &AnyDbPool, query: &str) -> Result<String, String> {
+    let rows: Vec<AnyRow> = pool
+        .raw_sql_stream(query)
+        .take(2)
+        .try_collect()
+        .await
+        .map_err(|err| err.to_string())?;
+
+    let [row] = rows.as_slice() else {
+        return Err(match rows.len() {
+            0 => "The query returned no rows".into(),
+            _ => "The query returned more than one row".into(),
+        });
+    };
+    match row.values.as_slice() {
+        [DbValue::Null] => Err("The query returned NULL".into()),
+        [value] => Ok(value.to_string()),
+        values => Err(format!(
+            "The query returned {} columns, expected one",
+            values.len()
+        )),
+    }
+}
+
+/// How many rows an SQL assertion expecting `expect` reads: one more than it
+/// expects, enough to tell that it got too many.
+fn sql_row_limit(expect: &StringOrStrings) -> usize {
+    match expect {
+        // An empty line expects no rows at all.
+        StringOrStrings::Single(line) if line.is_empty() => 1,
+        StringOrStrings::Single(_) => 2,
+        StringOrStrings::Multiple(lines) => lines.len() + 1,
+    }
+}
+
+/// Runs the hook statements in order. Statements with `expect_affected` are
+/// checked against the number of rows they changed.
+async fn run_sql(pool: &AnyDbPool, sql_statements: &[HookStatement]) -> Result<(), RunnerError> {
     for sql in sql_statements {
-        pool.raw_sql(sql)
+        let Some(expected) = sql.expect_affected else {
+            pool.raw_sql(&sql.query)
+                .await
+                .map_err(RunnerError::DatabaseError)?;
+            continue;
+        };
+
+        let actual = pool
+            .execute(&sql.query)
             .await
             .map_err(RunnerError::DatabaseError)?;
+        if actual != expected {
+            return Err(RunnerError::UnexpectedRowsAffected {
+                query: sql.query.clone(),
+                expected,
+                actual,
+            });
+        }
     }
 
     Ok(())
 }
 
-pub async fn reset_database(_pool: &AnyDbPool) -> Result<(), sqlx::Error> {
-    Ok(())
+/// Empties the tables `reset` names, or every table when it resets the whole
+/// database.
+pub async fn reset_database(pool: &AnyDbPool, reset: &DbReset) -> Result<(), sqlx::Error> {
+    match reset {
+        DbReset::All => pool.truncate_all_tables().await,
+        DbReset::Tables(tables) => pool.truncate_tables(tables).await,
+    }
 }
 
 #[derive(Debug)]
 pub struct CapturedResponse {
     pub status: StatusCode,
+    pub version: Version,
     pub headers: HeaderMap,
     pub body_text: Option<String>,
     pub body_json: Option<serde_json::Value>,
+    /// Where the body was streamed to with `capture_to_file`. `body_text` and
+    /// `body_json` are empty then.
+    pub body_file: Option<BodyFile>,
+    /// The body as it was sent, only kept for assertions that need the raw
+    /// bytes.
+    pub body_bytes: Option<Vec<u8>>,
+    /// The trailers sent after the body, only read for `assert_trailers`.
+    /// Empty when the server sent none.
+    pub trailers: Option<HeaderMap>,
+}
+
+/// A response body streamed to a temp file. The file is removed once the
+/// test passes and kept when it fails so it can be inspected.
+#[derive(Debug, Clone)]
+pub struct BodyFile {
+    pub path: PathBuf,
+    pub size: u64,
+}
+
+/// How much of a response body the assertions of a test need.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BodyCapture {
+    /// No assertion looks at the body, so it isn't read at all.
+    Skip,
+    Memory,
+    /// Like `Memory`, but also keeps the bytes of the body, which may not be
+    /// text, e.g. when it is compressed.
+    Raw,
+    /// Stream the body to a temp file instead of buffering it.
+    File,
+    /// Like `Raw`, but reads the body frame by frame to keep the trailers
+    /// that follow it.
+    Trailers,
 }
 
 impl CapturedResponse {
-    pub async fn from_response(resp: Response) -> Self {
+    /// Captures the status and headers of `resp`, and its body as `capture`
+    /// says. Skipped bodies leave `body_text` and `body_json` empty so large
+    /// responses aren't buffered for status/header-only tests. Fails if a body
+    /// captured to file can't be streamed, since there is nothing to assert on.
+    pub async fn from_response(resp: Response, capture: BodyCapture) -> Result<Self, String> {
         let status = resp.status();
+        let version = resp.version();
         let headers = resp.headers().clone();
 
+        let mut captured = Self {
+            status,
+            version,
+            headers,
+            body_text: None,
+            body_json: None,
+            body_file: None,
+            body_bytes: None,
+            trailers: None,
+        };
+
+        match capture {
+            BodyCapture::Skip => return Ok(captured),
+            BodyCapture::File => {
+                let file = stream_to_file(resp)
+                    .await
+                    .map_err(|err| format!("Failed to stream body to file: {err}"))?;
+                captured.body_file = Some(file);
+                return Ok(captured);
+            }
+            BodyCapture::Raw => match resp.bytes().await {
+                Ok(bytes) => {
+                    captured.body_text = Some(String::from_utf8_lossy(&bytes).into_owned());
+                    captured.body_json = serde_json::from_slice(&bytes).ok();
+                    captured.body_bytes = Some(bytes.to_vec());
+                    return Ok(captured);
+                }
+                Err(err) => {
+                    captured.body_text = Some(format!("Failed to read body: {err}"));
+                    return Ok(captured);
+                }
+            },
+            BodyCapture::Trailers => {
+                match read_with_trailers(resp).await {
+                    Ok((bytes, trailers)) => {
+                        captured.body_text = Some(String::from_utf8_lossy(&bytes).into_owned());
+                        captured.body_json = serde_json::from_slice(&bytes).ok();
+                        captured.body_bytes = Some(bytes);
+                        captured.trailers = Some(trailers);
+                    }
+                    Err(err) => captured.body_text = Some(format!("Failed to read body: {err}")),
+                }
+                return Ok(captured);
+            }
+            BodyCapture::Memory => {}
+        }
+
         // Consume the body exactly once
         let body_text = match resp.text().await {
             Ok(t) => t,
@@ -169,11 +1155,859 @@ impl CapturedResponse {
         // Attempt to parse JSON, but don't panic
         let body_json = serde_json::from_str::<serde_json::Value>(&body_text).ok();
 
-        Self {
-            status,
-            headers,
+        Ok(Self {
             body_text: Some(body_text),
             body_json,
+            ..captured
+        })
+    }
+
+    /// The length of the body in bytes, wherever it was captured to.
+    pub fn body_size(&self) -> usize {
+        match &self.body_file {
+            Some(file) => usize::try_from(file.size).unwrap_or(usize::MAX),
+            None => self.body_text.as_deref().map_or(0, str::len),
         }
     }
 }
+
+/// Streams the body of `resp` chunk by chunk into a new file in the temp dir.
+async fn stream_to_file(mut resp: Response) -> io::Result<BodyFile> {
+    let path = std::env::temp_dir().join(format!("tq-body-{}", uuid::Uuid::new_v4()));
+    let mut file = tokio::fs::File::create(&path).await?;
+    let mut size = 0;
+
+    while let Some(chunk) = resp.chunk().await.map_err(io::Error::other)? {
+        file.write_all(&chunk).await?;
+        size += chunk.len() as u64;
+    }
+    file.flush().await?;
+
+    Ok(BodyFile { path, size })
+}
+
+/// Reads the body of `resp` together with the trailers sent after it. A
+/// response without trailers gives an empty map.
+async fn read_with_trailers(resp: Response) -> Result<(Vec<u8>, HeaderMap), reqwest::Error> {
+    let mut body = http::Response::<reqwest::Body>::from(resp).into_body();
+    let mut bytes = Vec::new();
+    let mut trailers = HeaderMap::new();
+
+    while let Some(frame) = body.frame().await {
+        match frame?.into_data() {
+            Ok(data) => bytes.extend_from_slice(&data),
+            Err(frame) => {
+                if let Ok(sent) = frame.into_trailers() {
+                    trailers.extend(sent);
+                }
+            }
+        }
+    }
+
+    Ok((bytes, trailers))
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering;
+    use std::time::Duration;
+
+    use futures::SinkExt;
+    use futures::StreamExt;
+    use reqwest::Method;
+    use reqwest::StatusCode;
+    use reqwest::header::HeaderMap;
+    use reqwest::header::HeaderValue;
+    use reqwest::header::LOCATION;
+    use reqwest::header::USER_AGENT;
+    use tokio::io::AsyncReadExt;
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::tungstenite::Message;
+    use url::Url;
+
+    use crate::parser::StringOrStrings;
+    use crate::runner::BodyCapture;
+    use crate::runner::CapturedResponse;
+    use crate::runner::Clients;
+    use crate::runner::error_chain;
+    use crate::runner::exchange_ws;
+    use crate::runner::resets_database;
+    use crate::runner::run_sql_assertions;
+    use crate::runner::run_tests;
+    use crate::runner::run_until_failure;
+    use crate::runner::send_recording_redirects;
+    use crate::runner::send_request;
+    use crate::runner::shuffle_tests;
+    use crate::runner::sql_row_limit;
+    use crate::setup::database::any_db::AnyDbPool;
+    use crate::validator::Assertion;
+    use crate::validator::BeforeEach;
+    use crate::validator::ClientConfig;
+    use crate::validator::DbReset;
+    use crate::validator::IR;
+    use crate::validator::SqlPoll;
+    use crate::validator::TestGroups;
+    use crate::validator::ValidatedTests;
+    use crate::validator::WsRequest;
+
+    /// Serves a 302 from `/old` to `/new`, which answers with a 200.
+    async fn redirect_server() -> Url {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut buf = [0; 1024];
+                let n = stream.read(&mut buf).await.unwrap();
+                let response = if buf[..n].starts_with(b"GET /old ") {
+                    "HTTP/1.1 302 Found\r\nlocation: /new\r\ncontent-length: 0\r\nconnection: close\r\n\r\n"
+                } else {
+                    "HTTP/1.1 200 OK\r\ncontent-length: 0\r\nconnection: close\r\n\r\n"
+                };
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        Url::parse(&format!("http://{addr}/old")).unwrap()
+    }
+
+    /// A GET of `url` without assertions, for tests to fill in what they need.
+    fn get_test(name: &str, url: &Url) -> ValidatedTests {
+        ValidatedTests {
+            before_run: None,
+            name: name.into(),
+            group: "group".into(),
+            method: Method::GET,
+            url: url.clone(),
+            headers: Arc::default(),
+            body: None,
+            assertions: Arc::default(),
+            timeout: None,
+            retries: 0,
+            follow_redirects: true,
+            location: None,
+            assertion_locations: vec![],
+            depends_on: vec![],
+            capture_to_file: false,
+            capture_json: None,
+            ws: None,
+        }
+    }
+
+    fn redirect_test(url: Url, follow_redirects: bool) -> ValidatedTests {
+        ValidatedTests {
+            group: "redirects".into(),
+            follow_redirects,
+            ..get_test("Redirect", &url)
+        }
+    }
+
+    /// A group of `tests` without hooks or a timeout.
+    fn test_group(name: &str, tests: Vec<ValidatedTests>) -> TestGroups {
+        TestGroups {
+            name: name.into(),
+            before_group: None,
+            before_each_test: None,
+            timeout: None,
+            tests,
+        }
+    }
+
+    fn test_ir(tests: Vec<TestGroups>, concurrency: usize) -> IR {
+        IR {
+            before_each_group: None,
+            tests,
+            concurrency,
+            client: ClientConfig::default(),
+        }
+    }
+
+    /// A pool for tests that don't touch the database, it is never connected
+    /// to.
+    fn unused_pool() -> Arc<AnyDbPool> {
+        Arc::new(AnyDbPool::Postgres(
+            sqlx::PgPool::connect_lazy("postgres://localhost/unused").unwrap(),
+        ))
+    }
+
+    #[tokio::test]
+    async fn follow_redirects_false_captures_the_redirect() {
+        let url = redirect_server().await;
+        let clients = Clients::new(&ClientConfig::default()).unwrap();
+
+        let test = redirect_test(url.clone(), false);
+        let response = send_request(clients.for_test(&test), &test).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FOUND);
+        assert_eq!(response.headers()[LOCATION], "/new");
+
+        let test = redirect_test(url, true);
+        let response = send_request(clients.for_test(&test), &test).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.url().path(), "/new");
+    }
+
+    #[test]
+    fn sql_assertions_read_one_row_more_than_expected() {
+        let single = |line: &str| StringOrStrings::Single(line.into());
+        assert_eq!(sql_row_limit(&single("")), 1);
+        assert_eq!(sql_row_limit(&single("Alice")), 2);
+        let lines = StringOrStrings::Multiple(vec!["1".into(), "2".into(), "3".into()]);
+        assert_eq!(sql_row_limit(&lines), 4);
+    }
+
+    /// A pool whose queries fail right away, as nothing listens on its port.
+    fn failing_pool() -> AnyDbPool {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let pool = sqlx::postgres::PgPoolOptions::new()
+            .acquire_timeout(Duration::from_millis(50))
+            .connect_lazy(&format!("postgres://postgres@127.0.0.1:{port}/postgres"))
+            .unwrap();
+        AnyDbPool::Postgres(pool)
+    }
+
+    #[tokio::test]
+    async fn failed_sql_queries_fail_the_assertion() {
+        let clients = Clients::new(&ClientConfig::default()).unwrap();
+        let mut assertions = vec![Assertion::Sql {
+            query: "SELECT 1".into(),
+            expect: StringOrStrings::Single("1".into()),
+            got: None,
+            epsilon: None,
+            poll: None,
+        }];
+
+        run_sql_assertions(&mut assertions, &failing_pool(), &clients).await;
+
+        let Assertion::Sql {
+            got: Some(Err(error)),
+            ..
+        } = &assertions[0]
+        else {
+            panic!("expected the query error in got");
+        };
+        assert!(!error.is_empty());
+    }
+
+    #[tokio::test]
+    async fn polled_queries_are_retried_after_errors_until_the_timeout() {
+        let clients = Clients::new(&ClientConfig::default()).unwrap();
+        let poll = SqlPoll {
+            interval: Duration::from_millis(20),
+            timeout: Duration::from_millis(300),
+        };
+        let mut assertions = vec![Assertion::Sql {
+            query: "SELECT 1".into(),
+            expect: StringOrStrings::Single("1".into()),
+            got: None,
+            epsilon: None,
+            poll: Some(poll),
+        }];
+
+        let started = std::time::Instant::now();
+        run_sql_assertions(&mut assertions, &failing_pool(), &clients).await;
+
+        assert!(started.elapsed() >= poll.timeout);
+        assert!(matches!(
+            &assertions[0],
+            Assertion::Sql {
+                got: Some(Err(_)),
+                ..
+            }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_headers_replace_the_client_defaults() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        // Answers with the request it got.
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = [0; 2048];
+            let read = stream.read(&mut buf).await.unwrap();
+            let head =
+                format!("HTTP/1.1 200 OK\r\ncontent-length: {read}\r\nconnection: close\r\n\r\n");
+            stream.write_all(head.as_bytes()).await.unwrap();
+            stream.write_all(&buf[..read]).await.unwrap();
+        });
+
+        let mut default_headers = HeaderMap::new();
+        default_headers.insert("x-api-version", HeaderValue::from_static("2"));
+        default_headers.insert("x-team", HeaderValue::from_static("qa"));
+        default_headers.insert(USER_AGENT, HeaderValue::from_static("from-defaults"));
+        let clients = Clients::new(&ClientConfig {
+            user_agent: Some(HeaderValue::from_static("tq/1.0")),
+            default_headers,
+            ..ClientConfig::default()
+        })
+        .unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert("x-api-version", HeaderValue::from_static("3"));
+        let test = ValidatedTests {
+            headers: Arc::new(headers),
+            ..get_test("Headers", &url)
+        };
+
+        let request = send_request(clients.for_test(&test), &test)
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap()
+            .to_lowercase();
+
+        assert!(request.contains("user-agent: tq/1.0\r\n"), "{request}");
+        assert!(request.contains("x-team: qa\r\n"), "{request}");
+        assert!(request.contains("x-api-version: 3\r\n"), "{request}");
+        assert!(!request.contains("x-api-version: 2"), "{request}");
+    }
+
+    #[tokio::test]
+    async fn capture_to_file_streams_the_body_to_disk() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let body = "x".repeat(100_000);
+        let served = body.clone();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = [0; 1024];
+            let _ = stream.read(&mut buf).await.unwrap();
+            let head = format!(
+                "HTTP/1.1 200 OK\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+                served.len()
+            );
+            stream.write_all(head.as_bytes()).await.unwrap();
+            stream.write_all(served.as_bytes()).await.unwrap();
+        });
+
+        let resp = reqwest::get(format!("http://{addr}/export")).await.unwrap();
+        let captured = CapturedResponse::from_response(resp, BodyCapture::File)
+            .await
+            .unwrap();
+
+        let file = captured.body_file.as_ref().unwrap();
+        assert!(captured.body_text.is_none());
+        assert_eq!(file.size, 100_000);
+        assert_eq!(captured.body_size(), 100_000);
+        assert_eq!(std::fs::read_to_string(&file.path).unwrap(), body);
+
+        std::fs::remove_file(&file.path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn trailers_are_read_after_a_chunked_body() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            for _ in 0..2 {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = [0; 1024];
+                let _ = stream.read(&mut buf).await.unwrap();
+                stream
+                    .write_all(
+                        b"HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\ntrailer: x-checksum\r\nconnection: close\r\n\r\n\
+                          4\r\n{\"a\"\r\n3\r\n:1}\r\n0\r\nx-checksum: abc\r\n\r\n",
+                    )
+                    .await
+                    .unwrap();
+            }
+        });
+
+        let url = format!("http://{addr}/data");
+        let resp = reqwest::get(&url).await.unwrap();
+        let captured = CapturedResponse::from_response(resp, BodyCapture::Trailers)
+            .await
+            .unwrap();
+        assert_eq!(captured.body_text.as_deref(), Some("{\"a\":1}"));
+        assert_eq!(captured.body_json, Some(serde_json::json!({ "a": 1 })));
+        let trailers = captured.trailers.unwrap();
+        assert_eq!(trailers.get("x-checksum").unwrap(), "abc");
+
+        // Other captures don't look for trailers.
+        let resp = reqwest::get(&url).await.unwrap();
+        let captured = CapturedResponse::from_response(resp, BodyCapture::Memory)
+            .await
+            .unwrap();
+        assert!(captured.trailers.is_none());
+    }
+
+    #[tokio::test]
+    async fn request_errors_include_their_cause() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        drop(listener);
+        let clients = Clients::new(&ClientConfig::default()).unwrap();
+
+        let test = get_test("Refused", &url);
+        let error = send_request(clients.for_test(&test), &test)
+            .await
+            .unwrap_err();
+        let chain = error_chain(&error);
+        assert!(chain.starts_with(&error.to_string()), "{chain}");
+        assert!(
+            chain.to_lowercase().contains("connection refused"),
+            "{chain}"
+        );
+    }
+
+    #[tokio::test]
+    async fn websocket_tests_capture_the_first_message() {
+        // Echoes every text message back in upper case.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+                while let Some(Ok(Message::Text(text))) = ws.next().await {
+                    let reply = Message::text(text.to_uppercase());
+                    if ws.send(reply).await.is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+
+        let url = Url::parse(&format!("ws://{addr}/echo")).unwrap();
+        let test = ValidatedTests {
+            timeout: Some(Duration::from_millis(500)),
+            ..get_test("Echo", &url)
+        };
+
+        let send = WsRequest {
+            send: Some(r#"{"type": "ping"}"#.into()),
+        };
+        let response = exchange_ws(&test, &send).await.unwrap();
+        assert_eq!(response.status, StatusCode::SWITCHING_PROTOCOLS);
+        assert_eq!(response.body_text.as_deref(), Some(r#"{"TYPE": "PING"}"#));
+        assert_eq!(
+            response.body_json,
+            Some(serde_json::json!({ "TYPE": "PING" }))
+        );
+
+        // Nothing is sent, so nothing comes back before the timeout.
+        let response = exchange_ws(&test, &WsRequest::default()).await.unwrap();
+        assert_eq!(response.body_text, None);
+    }
+
+    #[tokio::test]
+    async fn redirect_chains_are_recorded() {
+        let url = redirect_server().await;
+        let clients = Clients::new(&ClientConfig::default()).unwrap();
+
+        let test = redirect_test(url.clone(), true);
+        let (response, chain) = send_recording_redirects(&clients.no_follow, &test)
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(chain, [url.clone(), url.join("/new").unwrap()]);
+    }
+
+    #[tokio::test]
+    async fn group_timeout_fails_the_tests_that_had_not_finished() {
+        let ok = redirect_server().await.join("/new").unwrap();
+        // Accepts connections but never answers them.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let hang = Url::parse(&format!("http://{}/hang", listener.local_addr().unwrap())).unwrap();
+        tokio::spawn(async move {
+            let mut open = vec![];
+            while let Ok((stream, _)) = listener.accept().await {
+                open.push(stream);
+            }
+        });
+
+        let test = |name: &str, url: &Url| ValidatedTests {
+            assertions: vec![Assertion::Status(200)].into(),
+            ..get_test(name, url)
+        };
+        let stuck = TestGroups {
+            timeout: Some(Duration::from_millis(300)),
+            ..test_group(
+                "stuck",
+                vec![test("Health", &ok), test("Hang", &hang), test("After", &ok)],
+            )
+        };
+        let ir = test_ir(vec![stuck, test_group("next", vec![test("Next", &ok)])], 1);
+        let pool = unused_pool();
+        let clients = Clients::new(&ir.client).unwrap();
+
+        let (tx, rx) = flume::unbounded();
+        run_tests(ir, &clients, tx, pool, 1).await.unwrap();
+        let results: Vec<_> = rx
+            .drain()
+            .map(|result| (result.name, result.timed_out))
+            .collect();
+
+        let timed_out = Some(Duration::from_millis(300));
+        assert_eq!(
+            results,
+            [
+                ("Health".to_string(), None),
+                ("Hang".to_string(), timed_out),
+                ("After".to_string(), timed_out),
+                ("Next".to_string(), None),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn redirects_drop_credentials_across_origins_and_stop_at_the_limit() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut buf = [0; 1024];
+                let n = stream.read(&mut buf).await.unwrap();
+                let request = String::from_utf8_lossy(&buf[..n]).to_lowercase();
+                let response = if request.starts_with("get /same ") {
+                    "HTTP/1.1 302 Found\r\nlocation: /echo\r\ncontent-length: 0\r\nconnection: close\r\n\r\n".to_string()
+                } else if request.starts_with("get /other ") {
+                    format!(
+                        "HTTP/1.1 302 Found\r\nlocation: http://localhost:{port}/echo\r\ncontent-length: 0\r\nconnection: close\r\n\r\n"
+                    )
+                } else if request.starts_with("get /loop ") {
+                    "HTTP/1.1 302 Found\r\nlocation: /loop\r\ncontent-length: 0\r\nconnection: close\r\n\r\n".to_string()
+                } else {
+                    let body = if request.contains("\r\nauthorization:") {
+                        "with credentials"
+                    } else {
+                        "without credentials"
+                    };
+                    format!(
+                        "HTTP/1.1 200 OK\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+                        body.len()
+                    )
+                };
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        let clients = Clients::new(&ClientConfig::default()).unwrap();
+        let test = |path: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert("authorization", HeaderValue::from_static("Bearer secret"));
+            ValidatedTests {
+                headers: Arc::new(headers),
+                ..redirect_test(
+                    Url::parse(&format!("http://127.0.0.1:{port}{path}")).unwrap(),
+                    true,
+                )
+            }
+        };
+        let body = |path: &str| {
+            let test = test(path);
+            let clients = clients.clone();
+            async move {
+                let (response, _) = send_recording_redirects(&clients.no_follow, &test)
+                    .await
+                    .unwrap();
+                response.text().await.unwrap()
+            }
+        };
+
+        assert_eq!(body("/same").await, "with credentials");
+        assert_eq!(body("/other").await, "without credentials");
+
+        let Err(error) = send_recording_redirects(&clients.no_follow, &test("/loop")).await else {
+            panic!("expected a redirect loop to fail");
+        };
+        assert!(error.starts_with("too many redirects"), "{error}");
+    }
+
+    #[test]
+    fn shuffle_tests_keeps_captures_after_the_tests_storing_them() {
+        let url = Url::parse("http://localhost/").unwrap();
+        let tests = (0..8)
+            .map(|i| ValidatedTests {
+                capture_json: match i {
+                    2 => Some("user".into()),
+                    4 => Some("unused".into()),
+                    _ => None,
+                },
+                assertions: if i == 6 {
+                    vec![Assertion::JsonEqualsVar {
+                        var: "user".into(),
+                        captured: None,
+                    }]
+                    .into()
+                } else {
+                    Arc::new(vec![])
+                },
+                ..get_test(&i.to_string(), &url)
+            })
+            .collect();
+        let mut ir = test_ir(vec![test_group("group", tests)], 1);
+
+        shuffle_tests(&mut ir, 42);
+        let names: Vec<&str> = ir.tests[0].tests.iter().map(|t| t.name.as_str()).collect();
+
+        assert_eq!(names[2], "2");
+        assert_eq!(names[6], "6");
+        // Nothing compares the body stored by 4, so it is shuffled.
+        assert_ne!(names[4], "4");
+    }
+
+    #[test]
+    fn shuffle_tests_keeps_hooked_tests_in_place() {
+        let url = Url::parse("http://localhost/").unwrap();
+        let tests = (0..8)
+            .map(|i| ValidatedTests {
+                before_run: (i == 3).then_some(BeforeEach {
+                    reset_db: Some(DbReset::All),
+                    sql: None,
+                }),
+                ..get_test(&i.to_string(), &url)
+            })
+            .collect();
+        let ir = test_ir(vec![test_group("group", tests)], 1);
+        let names =
+            |ir: &IR| -> Vec<String> { ir.tests[0].tests.iter().map(|t| t.name.clone()).collect() };
+
+        let mut first = ir.clone();
+        shuffle_tests(&mut first, 42);
+        let mut second = ir.clone();
+        shuffle_tests(&mut second, 42);
+
+        assert_eq!(names(&first), names(&second));
+        assert_ne!(names(&first), names(&ir));
+        assert_eq!(names(&first)[3], "3");
+
+        let mut sorted = names(&first);
+        sorted.sort();
+        assert_eq!(sorted, names(&ir));
+    }
+
+    #[tokio::test]
+    async fn tests_are_skipped_when_a_dependency_fails() {
+        let url = redirect_server().await.join("/new").unwrap();
+        let test = |name: &str, status, depends_on: &[&str]| ValidatedTests {
+            assertions: vec![Assertion::Status(status)].into(),
+            depends_on: depends_on.iter().map(|d| d.to_string()).collect(),
+            ..get_test(name, &url)
+        };
+        let tests = vec![
+            test("Login", 401, &[]),
+            test("Profile", 200, &["Login"]),
+            test("Settings", 200, &["Profile"]),
+            test("Health", 200, &[]),
+        ];
+        let ir = test_ir(vec![test_group("group", tests)], 4);
+        let pool = unused_pool();
+        let clients = Clients::new(&ir.client).unwrap();
+
+        let (tx, rx) = flume::unbounded();
+        run_tests(ir, &clients, tx, pool, 1).await.unwrap();
+        let results: Vec<_> = rx
+            .drain()
+            .map(|result| (result.name, result.skipped))
+            .collect();
+
+        assert_eq!(
+            results,
+            [
+                ("Login".to_string(), None),
+                (
+                    "Profile".to_string(),
+                    Some("dependency failed: Login".to_string())
+                ),
+                (
+                    "Settings".to_string(),
+                    Some("dependency failed: Profile".to_string())
+                ),
+                ("Health".to_string(), None),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn captured_bodies_reach_later_tests_in_the_group() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!(
+            "http://{}/users/1",
+            listener.local_addr().unwrap()
+        ))
+        .unwrap();
+        tokio::spawn(async move {
+            for body in [
+                r#"{"id":1,"name":"Ada"}"#,
+                r#"{"id":1,"name":"Ada"}"#,
+                r#"{"id":1}"#,
+            ] {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = [0; 1024];
+                let _ = stream.read(&mut buf).await.unwrap();
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        let compare = vec![Assertion::JsonEqualsVar {
+            var: "user".into(),
+            captured: None,
+        }];
+        let tests = vec![
+            ValidatedTests {
+                capture_json: Some("user".into()),
+                ..get_test("Create", &url)
+            },
+            ValidatedTests {
+                assertions: compare.clone().into(),
+                ..get_test("Get", &url)
+            },
+            ValidatedTests {
+                assertions: compare.into(),
+                ..get_test("GetAgain", &url)
+            },
+        ];
+        // Captures make the group run in order anyway.
+        let ir = test_ir(vec![test_group("users", tests)], 4);
+        let pool = unused_pool();
+        let clients = Clients::new(&ir.client).unwrap();
+
+        let (tx, rx) = flume::unbounded();
+        run_tests(ir, &clients, tx, pool, 1).await.unwrap();
+        let results: Vec<_> = rx
+            .drain()
+            .map(|result| (result.name.clone(), result.passed()))
+            .collect();
+
+        assert_eq!(
+            results,
+            [
+                ("Create".to_string(), true),
+                ("Get".to_string(), true),
+                ("GetAgain".to_string(), false),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn run_until_failure_stops_at_the_failing_iteration() {
+        // Answers 200 twice and 500 from then on.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut served = 0;
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut buf = [0; 1024];
+                let _ = stream.read(&mut buf).await.unwrap();
+                let status = if served < 2 { "200 OK" } else { "500 Oops" };
+                served += 1;
+                let response =
+                    format!("HTTP/1.1 {status}\r\ncontent-length: 0\r\nconnection: close\r\n\r\n");
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        let ir = |url: Url| {
+            let test = ValidatedTests {
+                assertions: vec![Assertion::Status(200)].into(),
+                ..get_test("Health", &url)
+            };
+            test_ir(vec![test_group("group", vec![test])], 1)
+        };
+        let pool = unused_pool();
+        let clients = Clients::new(&ClientConfig::default()).unwrap();
+
+        let url = Url::parse(&format!("http://{addr}/")).unwrap();
+        let (tx, rx) = flume::unbounded();
+        let failed_in = run_until_failure(ir(url), &clients, tx, pool.clone(), 1, None)
+            .await
+            .unwrap();
+        assert_eq!(failed_in, Some(3));
+        assert_eq!(rx.drain().count(), 3);
+
+        let url = redirect_server().await.join("/new").unwrap();
+        let (tx, rx) = flume::unbounded();
+        let failed_in = run_until_failure(ir(url), &clients, tx, pool, 1, Some(2))
+            .await
+            .unwrap();
+        assert_eq!(failed_in, None);
+        assert_eq!(rx.drain().count(), 2);
+    }
+
+    #[tokio::test]
+    async fn max_concurrency_limits_requests_in_flight() {
+        // Answers every request after a short delay, recording how many were
+        // being handled at once.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let most_in_flight = Arc::new(AtomicUsize::new(0));
+        let (current, most) = (in_flight.clone(), most_in_flight.clone());
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let (current, most) = (current.clone(), most.clone());
+                tokio::spawn(async move {
+                    let mut buf = [0; 1024];
+                    let _ = stream.read(&mut buf).await.unwrap();
+                    let now = current.fetch_add(1, Ordering::SeqCst) + 1;
+                    most.fetch_max(now, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                    current.fetch_sub(1, Ordering::SeqCst);
+                    let response =
+                        "HTTP/1.1 200 OK\r\ncontent-length: 0\r\nconnection: close\r\n\r\n";
+                    stream.write_all(response.as_bytes()).await.unwrap();
+                });
+            }
+        });
+
+        let url = Url::parse(&format!("http://{addr}/")).unwrap();
+        let tests = (0..6).map(|i| get_test(&i.to_string(), &url)).collect();
+        let ir = test_ir(vec![test_group("group", tests)], 6);
+        let pool = unused_pool();
+        let clients = Clients::new(&ir.client)
+            .unwrap()
+            .with_max_concurrency(Some(2));
+
+        let (tx, rx) = flume::unbounded();
+        run_tests(ir, &clients, tx, pool, 1).await.unwrap();
+
+        assert_eq!(rx.drain().count(), 6);
+        let most = most_in_flight.load(Ordering::SeqCst);
+        assert!(most <= 2, "{most} requests were in flight at once");
+    }
+
+    #[tokio::test]
+    async fn parallel_groups_run_every_group() {
+        let url = redirect_server().await.join("/new").unwrap();
+        let group = |name: &str, reset: bool| TestGroups {
+            before_group: reset.then_some(BeforeEach {
+                reset_db: Some(DbReset::Tables(vec![])),
+                sql: None,
+            }),
+            ..test_group(name, vec![get_test(name, &url)])
+        };
+        let groups = vec![
+            group("a", false),
+            group("b", false),
+            group("c", true),
+            group("d", false),
+        ];
+        let resets: Vec<bool> = groups.iter().map(resets_database).collect();
+        assert_eq!(resets, [false, false, true, false]);
+
+        let ir = test_ir(groups, 1);
+        let pool = unused_pool();
+        let clients = Clients::new(&ir.client).unwrap();
+
+        let (tx, rx) = flume::unbounded();
+        run_tests(ir, &clients, tx, pool, 4).await.unwrap();
+        let mut names: Vec<String> = rx.drain().map(|result| result.name).collect();
+
+        // The resetting group runs on its own, after the groups before it.
+        assert_eq!(names[2], "c");
+        names.sort();
+        assert_eq!(names, ["a", "b", "c", "d"]);
+    }
+}
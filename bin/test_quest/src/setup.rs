@@ -1,22 +1,39 @@
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 
 use database::Database;
 use thiserror::Error;
+use tokio::task::JoinHandle;
+use tracing::info;
+use tracing::warn;
 
+use crate::runner::RunnerError;
+use crate::runner::run_hook;
 use crate::setup::app::AppError;
 use crate::setup::app::AppProcess;
+use crate::setup::command::CommandError;
 use crate::setup::database::DatabaseContainer;
 use crate::setup::database::DbError;
 use crate::setup::database::any_db::AnyDbPool;
+use crate::setup::docker::DockerError;
+use crate::validator::BeforeEach;
 use crate::validator::EnvSetup;
 
 pub mod app;
+pub mod command;
 pub mod database;
+pub mod docker;
 
 pub struct AppHandle {
     pub child: AppProcess,
-    pub database_container: DatabaseContainer,
+    pub database_container: Option<DatabaseContainer>,
+    pub database_url: String,
+    pub db_log_tasks: Vec<JoinHandle<()>>,
     pub pool: Arc<AnyDbPool>,
+    pub shutdown_grace: Duration,
+    pub post_commands: Vec<String>,
+    pub after_all: Option<BeforeEach>,
 }
 
 #[derive(Debug, Error)]
@@ -24,16 +41,29 @@ pub enum StartUpError {
     #[error("Start up process failed with database errror: {0}")]
     DatabaseError(DbError),
 
+    #[error("{0}")]
+    DockerUnavailable(DockerError),
+
     #[error("Start up process failed with App Error: {0}")]
     AppError(AppError),
 
     #[error("Failed to connect with app: {0}")]
     AppTimeout(AppError),
+
+    #[error("Setup command failed: {0}")]
+    CommandError(CommandError),
+
+    #[error("{0} hook failed: {1}")]
+    HookError(&'static str, Box<RunnerError>),
 }
 
+#[tracing::instrument(name = "setup", skip_all)]
 pub async fn start_db_and_app(
     env_setup: EnvSetup,
     stream_app: bool,
+    stream_db: bool,
+    verbose: bool,
+    show_secrets: bool,
 ) -> Result<AppHandle, StartUpError> {
     // Get all values needed from the
     // `env_setup`
@@ -41,73 +71,254 @@ pub async fn start_db_and_app(
         base_url,
         command,
         args,
+        cwd,
+        env,
         ready_when,
         db_type,
-        migration_dir,
+        migration_dirs,
         db_port,
         database_url_env,
         init_sql,
         image_ref,
+        db_ready_timeout_secs,
+        db_warmup,
+        db_reuse,
+        db_url,
+        shutdown_grace_ms,
+        pre_commands,
+        post_commands,
+        before_all,
+        after_all,
     } = env_setup;
 
-    print_with_color("[SETUP] setting up database container! ⚙️");
+    let shutdown_grace = Duration::from_millis(shutdown_grace_ms);
+
+    database::check_migration_dirs(&migration_dirs).map_err(StartUpError::DatabaseError)?;
 
     let Database {
         database_container,
         database_url,
-    } = database::from_type(db_type, db_port, image_ref)
-        .await
-        .map_err(StartUpError::DatabaseError)?;
-
-    print_with_color("[SETUP] connecting to database! ⚙️");
+    } = match db_url {
+        Some(database_url) => {
+            info!(db_type, "using the database at [db] url");
+            Database {
+                database_container: None,
+                database_url,
+            }
+        }
+        None => {
+            if database::needs_container(&db_type) {
+                match docker::check_daemon().await {
+                    Ok(host) => info!(host, "docker daemon is reachable"),
+                    Err(error) if docker::host_overridden() => {
+                        warn!(%error, "couldn't check the docker daemon, starting the database anyway");
+                    }
+                    Err(error) => return Err(StartUpError::DockerUnavailable(error)),
+                }
+            }
 
-    let pool = database::connection_pool(&database_url)
-        .await
-        .map_err(StartUpError::DatabaseError)?;
+            info!(db_type, "setting up database container");
+            database::from_type(db_type, db_port, image_ref, db_reuse)
+                .await
+                .map_err(StartUpError::DatabaseError)?
+        }
+    };
 
-    print_with_color("[SETUP] waiting for database to be ready..! ⚙️");
+    if db_reuse && let Some(container) = &database_container {
+        info!(
+            container = container.id(),
+            "database container is reused and kept running after the run"
+        );
+    }
 
-    if let Err(e) = database::wait_for_db(&pool).await {
-        return Err(StartUpError::DatabaseError(e));
+    let db_log_tasks = match &database_container {
+        Some(container) if stream_db => container.stream_logs(),
+        _ => vec![],
     };
 
-    if let Some(migration_dir) = migration_dir {
-        database::run_migrations(&pool, &migration_dir)
+    let db_ready_timeout = Duration::from_secs(db_ready_timeout_secs);
+    let pool =
+        match prepare_database(&database_url, &migration_dirs, init_sql, db_ready_timeout).await {
+            Ok(pool) => pool,
+            Err(error) => {
+                // With --stream-db the logs have already been printed as they came.
+                if let Some(container) = &database_container
+                    && !stream_db
+                {
+                    eprintln!("{}", console::style("[SETUP] database logs:").bold().red());
+                    container.print_logs().await;
+                }
+
+                return Err(StartUpError::DatabaseError(error));
+            }
+        };
+
+    if db_warmup {
+        let connections = pool
+            .warm_up()
             .await
-            .map_err(StartUpError::DatabaseError)?;
-    };
+            .map_err(|e| StartUpError::DatabaseError(DbError::DatabaseError(e)))?;
+        info!(connections, "warmed up database connections");
+    }
 
-    if let Some(path) = init_sql {
-        print_with_color("[SETUP] loading init sql..! ⚙️");
-        database::load_init_sql(&pool, path)
+    if verbose {
+        print_database_url(&database_url, show_secrets);
+    }
+
+    if let Some(hook) = &before_all {
+        info!("running before_all hook");
+        run_hook(&pool, hook)
             .await
-            .map_err(StartUpError::DatabaseError)?;
-    };
+            .map_err(|e| StartUpError::HookError("before_all", Box::new(e)))?;
+    }
 
-    print_with_color("[SETUP] setting up app..! ⚙️");
+    if !pre_commands.is_empty() {
+        info!("running pre commands");
+        command::run_commands(&pre_commands, stream_app)
+            .await
+            .map_err(StartUpError::CommandError)?;
+    }
 
-    let child = app::from_command(command, args, database_url_env, database_url, stream_app)
-        .await
-        .map_err(StartUpError::AppError)?;
+    info!(command, "setting up app");
+
+    let child = app::from_command(
+        command,
+        args,
+        cwd,
+        env,
+        database_url_env,
+        database_url.clone(),
+        stream_app,
+    )
+    .await
+    .map_err(StartUpError::AppError)?;
 
-    print_with_color("[SETUP] waiting for app to be ready..! ⚙️");
+    info!(ready_when, "waiting for app to be ready");
 
-    if let Err(error) = app::wait_for_app_ready(base_url.as_str(), ready_when.as_str()).await {
-        let mut lock = child.process.lock().await;
-        let _ = lock.kill().await;
+    if let Err(error) =
+        app::wait_for_app_ready(base_url.as_str(), ready_when.as_str(), &child).await
+    {
+        app::shutdown(&child, shutdown_grace).await;
 
-        return Err(StartUpError::AppTimeout(error));
+        return Err(match error {
+            AppError::Timeout(_) => StartUpError::AppTimeout(error),
+            error => StartUpError::AppError(error),
+        });
     }
 
-    print_with_color("[SETUP] App is ready to rock and roll..! ⚙️");
+    info!("app is ready");
 
     Ok(AppHandle {
         child,
         database_container,
+        database_url,
+        db_log_tasks,
         pool,
+        shutdown_grace,
+        post_commands,
+        after_all,
     })
 }
 
-fn print_with_color(s: &str) {
-    println!("{}", console::style(s).bold().yellow());
+/// Runs the `after_all` hook, terminates the running app process, stops
+/// streaming the database logs and then runs the configured `post_commands`.
+/// With `keep_container` the database container is detached so it keeps
+/// running once test_quest exits.
+///
+/// The app is shut down and `post_commands` run even if `after_all` fails, in
+/// which case its error is returned afterwards.
+#[tracing::instrument(name = "teardown", skip_all)]
+pub async fn cleanup_and_teardown(
+    app_handle: &mut AppHandle,
+    stream_app: bool,
+    keep_container: bool,
+) -> Result<(), StartUpError> {
+    let after_all = match &app_handle.after_all {
+        Some(hook) => {
+            info!("running after_all hook");
+            run_hook(&app_handle.pool, hook)
+                .await
+                .map_err(|e| StartUpError::HookError("after_all", Box::new(e)))
+        }
+        None => Ok(()),
+    };
+
+    info!("shutting down app");
+    app::shutdown(&app_handle.child, app_handle.shutdown_grace).await;
+
+    for task in &app_handle.db_log_tasks {
+        task.abort();
+    }
+
+    if keep_container && let Some(container) = app_handle.database_container.take() {
+        println!(
+            "\n{}\n  {}\n{}",
+            console::style("[TEARDOWN] keeping the database container running, connect with:")
+                .bold()
+                .yellow(),
+            app_handle.database_url,
+            console::style(format!(
+                "Remember to stop it when you're done: docker rm -f {}",
+                container.id()
+            ))
+            .bold()
+            .yellow()
+        );
+        container.detach();
+    }
+
+    if !app_handle.post_commands.is_empty() {
+        info!("running post commands");
+    }
+    command::run_commands(&app_handle.post_commands, stream_app)
+        .await
+        .map_err(StartUpError::CommandError)?;
+
+    after_all
+}
+
+/// Connects to the database, waits for it to accept queries and loads the
+/// schema and initial data into it.
+async fn prepare_database(
+    database_url: &str,
+    migration_dirs: &[String],
+    init_sql: Option<PathBuf>,
+    ready_timeout: Duration,
+) -> Result<Arc<AnyDbPool>, DbError> {
+    info!("connecting to database");
+
+    let pool = database::connection_pool(database_url).await?;
+
+    info!("waiting for database to be ready");
+
+    database::wait_for_db(&pool, ready_timeout).await?;
+
+    database::run_migrations(&pool, migration_dirs).await?;
+
+    if let Some(path) = init_sql {
+        info!(path = %path.display(), "loading init sql");
+        database::load_init_sql(&pool, path).await?;
+    };
+
+    Ok(pool)
+}
+
+/// Prints where the database can be reached and how to connect to it, with the
+/// password redacted unless `show_secrets` is set.
+fn print_database_url(database_url: &str, show_secrets: bool) {
+    let database_url = if show_secrets {
+        database_url.to_string()
+    } else {
+        database::redact_password(database_url)
+    };
+
+    println!(
+        "{}",
+        console::style(format!("[SETUP] database is ready at {database_url}"))
+            .bold()
+            .yellow()
+    );
+    if let Some(command) = database::connect_command(&database_url) {
+        println!("  connect with: {command}");
+    }
 }
@@ -1,7 +1,14 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::process::ExitStatus;
 use std::process::Stdio;
 use std::sync::Arc;
 use std::time::Duration;
+use std::time::Instant;
 
+use nix::sys::signal::Signal;
+use nix::sys::signal::kill;
+use nix::unistd::Pid;
 use reqwest::Client;
 use thiserror::Error;
 use tokio::io::AsyncBufReadExt;
@@ -10,6 +17,7 @@ use tokio::process::Child;
 use tokio::process::Command;
 use tokio::sync::Mutex;
 use tokio::time::sleep;
+use tokio::time::timeout;
 
 #[derive(Debug)]
 pub enum OutputSource {
@@ -21,6 +29,9 @@ pub enum OutputSource {
 pub struct OutputLine {
     pub source: OutputSource,
     pub line: String,
+    /// When the line was read, used to match lines to the test that was
+    /// running at the time.
+    pub at: Instant,
 }
 
 #[derive(Error, Debug)]
@@ -33,6 +44,23 @@ pub enum AppError {
 
     #[error("Error while reading messages from stdin: {0}")]
     PipeAccessError(String),
+
+    #[error("app exited with {status} before it was ready{}", stderr_tail(.stderr))]
+    ExitedEarly {
+        status: ExitStatus,
+        stderr: Vec<String>,
+    },
+}
+
+/// Number of stderr lines included in `AppError::ExitedEarly`.
+const STDERR_TAIL: usize = 20;
+
+fn stderr_tail(stderr: &[String]) -> String {
+    if stderr.is_empty() {
+        return String::new();
+    }
+
+    format!(", its last stderr lines were:\n{}", stderr.join("\n"))
 }
 
 pub struct AppProcess {
@@ -43,6 +71,8 @@ pub struct AppProcess {
 pub async fn from_command(
     command: String,
     args: Option<Vec<String>>,
+    cwd: Option<PathBuf>,
+    env: HashMap<String, String>,
     database_env: String,
     database_url: String,
     stream_app: bool,
@@ -53,8 +83,16 @@ pub async fn from_command(
     let stdout_task_buffer = output_buffer.clone();
     let stderr_task_buffer = output_buffer.clone();
 
-    let mut app_process = Command::new(command)
+    let mut command = Command::new(command);
+    if let Some(cwd) = cwd {
+        command.current_dir(cwd);
+    }
+
+    // The database URL is applied after the user supplied env, so it always
+    // points the app at the test container even if `env` sets the same key.
+    let mut app_process = command
         .args(args.unwrap_or_default())
+        .envs(env)
         .env(database_env, &database_url)
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
@@ -81,6 +119,7 @@ pub async fn from_command(
             buffer.push(OutputLine {
                 source: OutputSource::StdOut,
                 line,
+                at: Instant::now(),
             });
         }
     });
@@ -95,6 +134,7 @@ pub async fn from_command(
             buffer.push(OutputLine {
                 source: OutputSource::StdErr,
                 line,
+                at: Instant::now(),
             });
         }
     });
@@ -105,7 +145,14 @@ pub async fn from_command(
     })
 }
 
-pub async fn wait_for_app_ready(base_url: &str, ready_when_url: &str) -> Result<(), AppError> {
+/// Polls `ready_when_url` until the app answers with a success status. If the
+/// app exits in the meantime, its exit status and last stderr lines are
+/// returned right away instead of waiting for the timeout.
+pub async fn wait_for_app_ready(
+    base_url: &str,
+    ready_when_url: &str,
+    app: &AppProcess,
+) -> Result<(), AppError> {
     let client = Client::new();
     let mut elapsed = 0;
     let timeout_secs = 15;
@@ -118,9 +165,92 @@ pub async fn wait_for_app_ready(base_url: &str, ready_when_url: &str) -> Result<
             return Ok(());
         }
 
+        let exited = app.process.lock().await.try_wait();
+        if let Ok(Some(status)) = exited {
+            // Give the reader tasks a moment to drain what is left in the
+            // pipes.
+            sleep(Duration::from_millis(100)).await;
+            return Err(AppError::ExitedEarly {
+                status,
+                stderr: last_stderr_lines(&app.output.lock().await),
+            });
+        }
+
         sleep(Duration::from_secs(1)).await;
         elapsed += 1;
     }
 
     Err(AppError::Timeout(url))
 }
+
+fn last_stderr_lines(output: &[OutputLine]) -> Vec<String> {
+    let mut lines: Vec<String> = output
+        .iter()
+        .rev()
+        .filter(|line| matches!(line.source, OutputSource::StdErr))
+        .take(STDERR_TAIL)
+        .map(|line| line.line.clone())
+        .collect();
+    lines.reverse();
+    lines
+}
+
+/// Shuts the app down by sending SIGTERM, giving it `grace_period` to exit on
+/// its own before falling back to SIGKILL.
+///
+/// This lets the app flush its logs and close its database connections
+/// instead of being killed mid-write.
+pub async fn shutdown(process: &AppProcess, grace_period: Duration) {
+    let mut child = process.process.lock().await;
+
+    // `id` is `None` once the child has already been reaped, in which case
+    // there is nothing left to signal.
+    let Some(pid) = child.id() else {
+        return;
+    };
+
+    if kill(Pid::from_raw(pid as i32), Signal::SIGTERM).is_ok()
+        && let Ok(Ok(_)) = timeout(grace_period, child.wait()).await
+    {
+        return;
+    }
+
+    let _ = child.kill().await;
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use crate::setup::app::AppError;
+    use crate::setup::app::from_command;
+    use crate::setup::app::wait_for_app_ready;
+
+    #[tokio::test]
+    async fn app_that_exits_before_it_is_ready_is_reported() {
+        let app = from_command(
+            "sh".into(),
+            Some(vec![
+                "-c".into(),
+                "echo 'missing binary' >&2; exit 3".into(),
+            ]),
+            None,
+            HashMap::new(),
+            "DATABASE_URL".into(),
+            "postgres://localhost/test".into(),
+            false,
+        )
+        .await
+        .unwrap();
+
+        let error = wait_for_app_ready("http://127.0.0.1:1", "/health", &app)
+            .await
+            .unwrap_err();
+
+        let AppError::ExitedEarly { status, stderr } = error else {
+            panic!("expected the app to have exited early, got {error}");
+        };
+        assert_eq!(status.code(), Some(3));
+        assert_eq!(stderr, ["missing binary"]);
+    }
+}
@@ -0,0 +1,83 @@
+use std::process::ExitStatus;
+use std::process::Stdio;
+
+use thiserror::Error;
+use tokio::io::AsyncBufReadExt;
+use tokio::io::BufReader;
+use tokio::process::Command;
+
+#[derive(Error, Debug)]
+pub enum CommandError {
+    #[error("failed to spawn command `{0}`: {1}")]
+    SpawningCommandFailed(String, std::io::Error),
+
+    #[error("command `{0}` exited with {1}")]
+    NonZeroExit(String, ExitStatus),
+
+    #[error("Error while reading output from command `{0}`")]
+    PipeAccessError(String),
+}
+
+/// Runs each shell command in order, stopping at the first one that fails.
+///
+/// Commands are executed through `sh -c` so users can write them the same way
+/// they would in a terminal. When `stream_output` is set, their stdout and
+/// stderr are printed as they arrive, the same way `--stream-app` does for the
+/// app.
+pub async fn run_commands(commands: &[String], stream_output: bool) -> Result<(), CommandError> {
+    for command in commands {
+        run_command(command, stream_output).await?;
+    }
+
+    Ok(())
+}
+
+async fn run_command(command: &str, stream_output: bool) -> Result<(), CommandError> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| CommandError::SpawningCommandFailed(command.to_string(), e))?;
+
+    let stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| CommandError::PipeAccessError(command.to_string()))?;
+    let stderr = child
+        .stderr
+        .take()
+        .ok_or_else(|| CommandError::PipeAccessError(command.to_string()))?;
+
+    let stdout_task = tokio::spawn(async move {
+        let mut reader = BufReader::new(stdout).lines();
+        while let Ok(Some(line)) = reader.next_line().await {
+            if stream_output {
+                println!("[ STDOUT ] {line}")
+            }
+        }
+    });
+
+    let stderr_task = tokio::spawn(async move {
+        let mut reader = BufReader::new(stderr).lines();
+        while let Ok(Some(line)) = reader.next_line().await {
+            if stream_output {
+                println!("[ stderr ] {line}")
+            }
+        }
+    });
+
+    let status = child
+        .wait()
+        .await
+        .map_err(|e| CommandError::SpawningCommandFailed(command.to_string(), e))?;
+
+    let _ = futures::join!(stdout_task, stderr_task);
+
+    if !status.success() {
+        return Err(CommandError::NonZeroExit(command.to_string(), status));
+    }
+
+    Ok(())
+}
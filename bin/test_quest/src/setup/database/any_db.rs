@@ -5,15 +5,27 @@ use chrono::DateTime;
 use chrono::NaiveDate;
 use chrono::NaiveDateTime;
 use chrono::Utc;
+use futures::StreamExt;
+use futures::TryStreamExt;
+use futures::stream::BoxStream;
 use rust_decimal::Decimal;
+use rust_decimal::prelude::ToPrimitive;
 use sqlx::Executor;
+use sqlx::migrate::MigrateError;
 use sqlx::migrate::Migrator;
 use uuid::Uuid;
 
+#[cfg(feature = "duckdb")]
+pub mod duckdb;
+pub mod mssql;
 pub mod mysql;
 pub mod postgres;
 
-#[derive(Debug, PartialEq)]
+/// Where `run_migration_files` records the migration files it has run, for the
+/// databases sqlx can't migrate.
+const MIGRATIONS_TABLE: &str = "_test_quest_migrations";
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum DbValue {
     I64(i64),
     F64(f64),
@@ -26,6 +38,12 @@ pub enum DbValue {
     Date(NaiveDate),
     DateTime(NaiveDateTime),
     Timestamp(DateTime<Utc>),
+    Array(Vec<DbValue>),
+    Interval {
+        months: i32,
+        days: i32,
+        microseconds: i64,
+    },
     Null,
     Unsupported,
 }
@@ -44,13 +62,103 @@ impl Display for DbValue {
             DbValue::Date(v) => write!(f, "{}", v),
             DbValue::DateTime(v) => write!(f, "{}", v),
             DbValue::Timestamp(v) => write!(f, "{}", v),
+            // Same format as Postgres uses for array literals, e.g. {1,2,NULL}
+            DbValue::Array(values) => {
+                write!(f, "{{")?;
+                for (idx, value) in values.iter().enumerate() {
+                    if idx > 0 {
+                        write!(f, ",")?;
+                    }
+                    match value {
+                        DbValue::String(s) if needs_quoting(s) => {
+                            write!(f, "\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))?
+                        }
+                        value => write!(f, "{value}")?,
+                    }
+                }
+                write!(f, "}}")
+            }
+            DbValue::Interval {
+                months,
+                days,
+                microseconds,
+            } => write_interval(f, *months, *days, *microseconds),
             DbValue::Null => write!(f, "NULL"),
             DbValue::Unsupported => write!(f, "<unsupported>"),
         }
     }
 }
 
-#[derive(Debug)]
+/// Writes an interval the way Postgres prints it by default, e.g.
+/// `1 year 2 mons 3 days 04:05:06.5`.
+fn write_interval(
+    f: &mut std::fmt::Formatter<'_>,
+    months: i32,
+    days: i32,
+    microseconds: i64,
+) -> std::fmt::Result {
+    let plural = |n: i32, unit: &str, units: &str| {
+        if n.abs() == 1 {
+            format!("{n} {unit}")
+        } else {
+            format!("{n} {units}")
+        }
+    };
+
+    let mut parts = vec![];
+    let (years, months) = (months / 12, months % 12);
+    if years != 0 {
+        parts.push(plural(years, "year", "years"));
+    }
+    if months != 0 {
+        parts.push(plural(months, "mon", "mons"));
+    }
+    if days != 0 {
+        parts.push(plural(days, "day", "days"));
+    }
+
+    if microseconds != 0 || parts.is_empty() {
+        let sign = if microseconds < 0 { "-" } else { "" };
+        let micros = microseconds.unsigned_abs();
+        let seconds = micros / 1_000_000;
+        let mut time = format!(
+            "{sign}{:02}:{:02}:{:02}",
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        );
+        let fraction = micros % 1_000_000;
+        if fraction != 0 {
+            time.push_str(format!(".{fraction:06}").trim_end_matches('0'));
+        }
+        parts.push(time);
+    }
+
+    write!(f, "{}", parts.join(" "))
+}
+
+/// Whether a string inside an array has to be quoted to be read back, the same
+/// rules Postgres follows when printing arrays.
+fn needs_quoting(s: &str) -> bool {
+    s.is_empty()
+        || s.eq_ignore_ascii_case("NULL")
+        || s.chars()
+            .any(|c| c.is_whitespace() || matches!(c, '{' | '}' | ',' | '"' | '\\'))
+}
+
+impl DbValue {
+    /// The value as a float, if it is a number.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            DbValue::I64(v) => Some(*v as f64),
+            DbValue::F64(v) => Some(*v),
+            DbValue::Decimal(v) => v.to_f64(),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct AnyRow {
     pub values: Vec<DbValue>,
 }
@@ -64,26 +172,244 @@ impl AnyRow {
     }
 }
 
+/// The rows an SQL assertion's query returned, as far as they were read.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SqlRows {
+    pub rows: Vec<AnyRow>,
+    /// Whether the query returned more rows than were read.
+    pub truncated: bool,
+}
+
+impl SqlRows {
+    /// The rows as CSV lines, with a last `…` if there were more.
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines: Vec<String> = self.rows.iter().map(AnyRow::to_csv_line).collect();
+        if self.truncated {
+            lines.push("…".into());
+        }
+        lines
+    }
+}
+
 pub enum AnyDbPool {
     Postgres(sqlx::Pool<sqlx::Postgres>),
     MySql(sqlx::Pool<sqlx::MySql>),
+    MsSql(Box<mssql::MsSqlPool>),
+    #[cfg(feature = "duckdb")]
+    DuckDb(duckdb::DuckDbPool),
 }
 
 impl AnyDbPool {
     pub async fn raw_sql(&self, query: &str) -> Result<Vec<AnyRow>, sqlx::Error> {
         match self {
             AnyDbPool::Postgres(pool) => {
-                let rows = pool.fetch_all(query).await.unwrap();
+                let rows = pool.fetch_all(query).await?;
                 Ok(rows.into_iter().map(Into::into).collect())
             }
             AnyDbPool::MySql(pool) => {
-                let rows = pool.fetch_all(query).await.unwrap();
+                let rows = pool.fetch_all(query).await?;
                 Ok(rows.into_iter().map(Into::into).collect())
             }
+            AnyDbPool::MsSql(pool) => pool.raw_sql(query).await,
+            #[cfg(feature = "duckdb")]
+            AnyDbPool::DuckDb(pool) => pool.raw_sql(query).await,
+        }
+    }
+
+    /// Like `raw_sql`, but hands out the rows as they arrive, so a caller that
+    /// only needs the first few can stop without reading the rest. MSSQL and
+    /// DuckDB still read every row before the first one is handed out.
+    pub fn raw_sql_stream<'a>(
+        &'a self,
+        query: &'a str,
+    ) -> BoxStream<'a, Result<AnyRow, sqlx::Error>> {
+        match self {
+            AnyDbPool::Postgres(pool) => pool.fetch(query).map_ok(Into::into).boxed(),
+            AnyDbPool::MySql(pool) => pool.fetch(query).map_ok(Into::into).boxed(),
+            AnyDbPool::MsSql(_) => self.all_rows_stream(query),
+            #[cfg(feature = "duckdb")]
+            AnyDbPool::DuckDb(_) => self.all_rows_stream(query),
+        }
+    }
+
+    /// The rows of `raw_sql` as a stream, for pools that can't stream them.
+    fn all_rows_stream<'a>(&'a self, query: &'a str) -> BoxStream<'a, Result<AnyRow, sqlx::Error>> {
+        futures::stream::once(self.raw_sql(query))
+            .map_ok(|rows| futures::stream::iter(rows).map(Ok))
+            .try_flatten()
+            .boxed()
+    }
+
+    /// Opens every connection the pool may hold and runs `SELECT 1` on each,
+    /// so they are ready before the first test needs them. Returns the number
+    /// of connections that were warmed up.
+    pub async fn warm_up(&self) -> Result<u32, sqlx::Error> {
+        match self {
+            AnyDbPool::Postgres(pool) => warm_up_pool(pool).await,
+            AnyDbPool::MySql(pool) => warm_up_pool(pool).await,
+            AnyDbPool::MsSql(pool) => pool.raw_sql("SELECT 1").await.map(|_| 1),
+            // DuckDB connections are opened per query, there is nothing to
+            // keep warm.
+            #[cfg(feature = "duckdb")]
+            AnyDbPool::DuckDb(_) => Ok(0),
         }
     }
-    pub async fn migrate(&self, migration_path: &Path) -> Result<(), sqlx::migrate::MigrateError> {
-        let m = Migrator::new(Path::new(migration_path)).await?;
+
+    /// Runs `query` without reading any rows back and returns how many rows it
+    /// changed.
+    pub async fn execute(&self, query: &str) -> Result<u64, sqlx::Error> {
+        match self {
+            AnyDbPool::Postgres(pool) => Ok(pool.execute(query).await?.rows_affected()),
+            AnyDbPool::MySql(pool) => Ok(pool.execute(query).await?.rows_affected()),
+            AnyDbPool::MsSql(pool) => pool.execute(query).await,
+            #[cfg(feature = "duckdb")]
+            AnyDbPool::DuckDb(pool) => pool.execute(query).await,
+        }
+    }
+
+    /// Removes every row from `tables`, which may be qualified with a schema,
+    /// e.g. `billing.invoices`. The names are quoted, so they have to match
+    /// the case the tables were created with. Postgres cascades to tables
+    /// referencing the listed ones. DuckDB can't turn off foreign key checks,
+    /// so there the tables are cleared last to first and parent tables have to
+    /// be listed before the tables referencing them.
+    pub async fn truncate_tables(&self, tables: &[String]) -> Result<(), sqlx::Error> {
+        let tables: Vec<String> = tables
+            .iter()
+            .map(|table| self.quote_table(table.split('.')))
+            .collect();
+        self.clear_tables(&tables).await
+    }
+
+    /// Removes every row from every table in the database, except the ones
+    /// sqlx and `run_migration_files` keep the applied migrations in.
+    pub async fn truncate_all_tables(&self) -> Result<(), sqlx::Error> {
+        let query = match self {
+            AnyDbPool::Postgres(_) => {
+                "SELECT table_schema, table_name FROM information_schema.tables \
+                 WHERE table_type = 'BASE TABLE' \
+                 AND table_schema NOT IN ('pg_catalog', 'information_schema') \
+                 AND table_name <> '_sqlx_migrations'"
+            }
+            AnyDbPool::MySql(_) => {
+                "SELECT CAST(table_schema AS CHAR), CAST(table_name AS CHAR) \
+                 FROM information_schema.tables \
+                 WHERE table_type = 'BASE TABLE' AND table_schema = DATABASE() \
+                 AND table_name <> '_sqlx_migrations'"
+            }
+            AnyDbPool::MsSql(_) => {
+                "SELECT TABLE_SCHEMA, TABLE_NAME FROM INFORMATION_SCHEMA.TABLES \
+                 WHERE TABLE_TYPE = 'BASE TABLE' AND TABLE_NAME <> '_test_quest_migrations'"
+            }
+            // In creation order, so clearing them last to first empties the
+            // tables referencing another table before that table.
+            #[cfg(feature = "duckdb")]
+            AnyDbPool::DuckDb(_) => {
+                "SELECT schema_name, table_name FROM duckdb_tables() \
+                 WHERE NOT internal AND NOT temporary \
+                 AND table_name <> '_test_quest_migrations' ORDER BY table_oid"
+            }
+        };
+
+        let tables: Vec<String> = self
+            .raw_sql(query)
+            .await?
+            .iter()
+            .map(|row| {
+                let names: Vec<String> = row.values.iter().map(ToString::to_string).collect();
+                self.quote_table(names.iter().map(String::as_str))
+            })
+            .collect();
+        self.clear_tables(&tables).await
+    }
+
+    /// Quotes each part of a possibly schema qualified table name the way the
+    /// database quotes identifiers.
+    fn quote_table<'a>(&self, parts: impl Iterator<Item = &'a str>) -> String {
+        parts
+            .map(|part| match self {
+                AnyDbPool::MySql(_) => format!("`{}`", part.replace('`', "``")),
+                AnyDbPool::MsSql(_) => format!("[{}]", part.replace(']', "]]")),
+                _ => format!("\"{}\"", part.replace('"', "\"\"")),
+            })
+            .collect::<Vec<_>>()
+            .join(".")
+    }
+
+    /// Removes every row from the already quoted `tables`, without foreign
+    /// keys between them getting in the way.
+    async fn clear_tables(&self, tables: &[String]) -> Result<(), sqlx::Error> {
+        if tables.is_empty() {
+            return Ok(());
+        }
+
+        match self {
+            // Truncating the tables in one statement lets them reference each
+            // other, and CASCADE also empties the tables referencing them.
+            AnyDbPool::Postgres(pool) => {
+                pool.execute(
+                    format!(
+                        "TRUNCATE TABLE {} RESTART IDENTITY CASCADE;",
+                        tables.join(", ")
+                    )
+                    .as_str(),
+                )
+                .await?;
+            }
+            // One call, so the checks are turned off on the connection that
+            // truncates.
+            AnyDbPool::MySql(pool) => {
+                let truncates: String = tables
+                    .iter()
+                    .map(|table| format!("TRUNCATE TABLE {table};"))
+                    .collect();
+                pool.execute(
+                    format!("SET FOREIGN_KEY_CHECKS = 0; {truncates} SET FOREIGN_KEY_CHECKS = 1;")
+                        .as_str(),
+                )
+                .await?;
+            }
+            // SQL Server won't truncate a referenced table even with its
+            // constraints off, so the rows are deleted instead.
+            AnyDbPool::MsSql(pool) => {
+                let mut batch = String::new();
+                for table in tables {
+                    batch.push_str(&format!("ALTER TABLE {table} NOCHECK CONSTRAINT ALL; "));
+                }
+                for table in tables {
+                    batch.push_str(&format!("DELETE FROM {table}; "));
+                }
+                for table in tables {
+                    batch.push_str(&format!(
+                        "ALTER TABLE {table} WITH CHECK CHECK CONSTRAINT ALL; "
+                    ));
+                }
+                pool.execute(&batch).await?;
+            }
+            #[cfg(feature = "duckdb")]
+            AnyDbPool::DuckDb(pool) => {
+                let deletes: String = tables
+                    .iter()
+                    .rev()
+                    .map(|table| format!("DELETE FROM {table};"))
+                    .collect();
+                pool.raw_sql(&deletes).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Runs the migrations in `migration_path`. With `ignore_missing` set,
+    /// applied migrations that aren't in the directory are not an error, as
+    /// when they came from another of several migration directories.
+    pub async fn migrate(
+        &self,
+        migration_path: &Path,
+        ignore_missing: bool,
+    ) -> Result<(), MigrateError> {
+        let mut m = Migrator::new(Path::new(migration_path)).await?;
+        m.set_ignore_missing(ignore_missing);
 
         match self {
             AnyDbPool::Postgres(pool) => {
@@ -92,8 +418,142 @@ impl AnyDbPool {
             AnyDbPool::MySql(pool) => {
                 m.run(pool).await?;
             }
+            // sqlx can't run migrations against these, so run the files as
+            // plain SQL in order instead.
+            AnyDbPool::MsSql(_) => self.run_migration_files(migration_path).await?,
+            #[cfg(feature = "duckdb")]
+            AnyDbPool::DuckDb(_) => self.run_migration_files(migration_path).await?,
+        }
+
+        Ok(())
+    }
+
+    /// Runs the `.sql` files in `migration_path` in file name order, skipping
+    /// `.down.sql` files, the same layout sqlx migrations use. Like sqlx, the
+    /// files that ran are recorded, in `MIGRATIONS_TABLE`, and skipped when
+    /// the migrations are run again, e.g. on a `--watch` rerun or a reused
+    /// database.
+    async fn run_migration_files(&self, migration_path: &Path) -> Result<(), MigrateError> {
+        let create_table = match self {
+            AnyDbPool::MsSql(_) => format!(
+                "IF OBJECT_ID('{MIGRATIONS_TABLE}') IS NULL \
+                 CREATE TABLE {MIGRATIONS_TABLE} (name NVARCHAR(255) PRIMARY KEY)"
+            ),
+            _ => format!(
+                "CREATE TABLE IF NOT EXISTS {MIGRATIONS_TABLE} (name VARCHAR(255) PRIMARY KEY)"
+            ),
+        };
+        self.execute(&create_table)
+            .await
+            .map_err(MigrateError::Execute)?;
+        let applied: Vec<String> = self
+            .raw_sql(&format!("SELECT name FROM {MIGRATIONS_TABLE}"))
+            .await
+            .map_err(MigrateError::Execute)?
+            .iter()
+            .filter_map(|row| row.values.first().map(ToString::to_string))
+            .collect();
+
+        let mut files = std::fs::read_dir(migration_path)
+            .and_then(|entries| {
+                entries
+                    .map(|entry| entry.map(|e| e.path()))
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .map_err(|e| MigrateError::Source(Box::new(e)))?;
+        files.retain(|p| {
+            p.extension().is_some_and(|ext| ext == "sql")
+                && !p.to_string_lossy().ends_with(".down.sql")
+        });
+        files.sort();
+
+        for file in files {
+            let name = file
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            if applied.contains(&name) {
+                continue;
+            }
+
+            let sql =
+                std::fs::read_to_string(&file).map_err(|e| MigrateError::Source(Box::new(e)))?;
+            self.raw_sql(&sql).await.map_err(MigrateError::Execute)?;
+            self.execute(&format!(
+                "INSERT INTO {MIGRATIONS_TABLE} (name) VALUES ('{}')",
+                name.replace('\'', "''")
+            ))
+            .await
+            .map_err(MigrateError::Execute)?;
         }
 
         Ok(())
     }
 }
+
+/// Acquires all of the pool's connections at once, so each one is opened
+/// rather than the same one being reused, and checks each with `SELECT 1`.
+/// They go back to the pool as idle connections when dropped.
+async fn warm_up_pool<DB: sqlx::Database>(pool: &sqlx::Pool<DB>) -> Result<u32, sqlx::Error>
+where
+    for<'c> &'c mut DB::Connection: Executor<'c, Database = DB>,
+{
+    let size = pool.options().get_max_connections();
+    let mut connections = Vec::with_capacity(size as usize);
+    for _ in 0..size {
+        let mut connection = pool.acquire().await?;
+        sqlx::raw_sql("SELECT 1").execute(&mut *connection).await?;
+        connections.push(connection);
+    }
+
+    Ok(size)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::setup::database::any_db::AnyDbPool;
+
+    #[tokio::test]
+    async fn table_names_are_quoted_per_database() {
+        let postgres =
+            AnyDbPool::Postgres(sqlx::PgPool::connect_lazy("postgres://localhost/unused").unwrap());
+        let mysql =
+            AnyDbPool::MySql(sqlx::MySqlPool::connect_lazy("mysql://localhost/unused").unwrap());
+
+        assert_eq!(
+            postgres.quote_table("billing.Invoices".split('.')),
+            r#""billing"."Invoices""#
+        );
+        assert_eq!(postgres.quote_table(["a\"b"].into_iter()), r#""a""b""#);
+        assert_eq!(mysql.quote_table(["order`s"].into_iter()), "`order``s`");
+    }
+
+    #[cfg(feature = "duckdb")]
+    #[tokio::test]
+    async fn migration_files_run_once() {
+        let dir = std::env::temp_dir().join(format!("tq-migrations-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("migrations")).unwrap();
+        std::fs::write(
+            dir.join("migrations/0001_users.sql"),
+            "CREATE TABLE users (id INTEGER);",
+        )
+        .unwrap();
+        let pool = AnyDbPool::DuckDb(super::duckdb::DuckDbPool {
+            path: dir.join("test.duckdb"),
+        });
+
+        pool.migrate(&dir.join("migrations"), false).await.unwrap();
+        pool.execute("INSERT INTO users VALUES (1)").await.unwrap();
+        pool.truncate_all_tables().await.unwrap();
+        pool.migrate(&dir.join("migrations"), false).await.unwrap();
+
+        let applied = pool
+            .raw_sql("SELECT name FROM _test_quest_migrations")
+            .await
+            .unwrap();
+        assert_eq!(applied.len(), 1);
+        assert_eq!(applied[0].values[0].to_string(), "0001_users.sql");
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,124 @@
+//! DuckDB support, enabled with the `duckdb` feature.
+//!
+//! DuckDB runs in-process, so there is no container. The database lives in a
+//! file that both test_quest and the app open, and test_quest only holds a
+//! connection while it runs a query, since DuckDB lets one process at a time
+//! write to a file.
+//!
+//! Column types map onto `DbValue` as follows, anything else is
+//! `DbValue::Unsupported`:
+//!
+//! | DuckDB                                      | `DbValue`  |
+//! |---------------------------------------------|------------|
+//! | `TINYINT`..`BIGINT`, `UTINYINT`..`UINTEGER` | `I64`      |
+//! | `FLOAT`, `DOUBLE`                           | `F64`      |
+//! | `DECIMAL`                                   | `Decimal`  |
+//! | `BOOLEAN`                                   | `Bool`     |
+//! | `VARCHAR`                                   | `String`   |
+//! | `BLOB`                                      | `Bytes`    |
+//! | `DATE`                                      | `Date`     |
+//! | `TIMESTAMP`                                 | `DateTime` |
+
+use std::path::Path;
+use std::path::PathBuf;
+
+use ::duckdb::Connection;
+use ::duckdb::types::ValueRef;
+use chrono::DateTime;
+use chrono::NaiveDate;
+
+use crate::setup::database::any_db::AnyRow;
+use crate::setup::database::any_db::DbValue;
+
+/// Days between 0001-01-01 and 1970-01-01, DuckDB counts dates from the latter.
+const UNIX_EPOCH_DAYS_FROM_CE: i32 = 719_163;
+
+/// Statements that return rows. Everything else is run as a batch, which
+/// allows several statements in one string like the other databases do.
+const QUERY_KEYWORDS: [&str; 7] = [
+    "SELECT", "WITH", "VALUES", "FROM", "SHOW", "DESCRIBE", "PRAGMA",
+];
+
+pub struct DuckDbPool {
+    pub path: PathBuf,
+}
+
+impl DuckDbPool {
+    pub async fn raw_sql(&self, query: &str) -> Result<Vec<AnyRow>, sqlx::Error> {
+        let path = self.path.clone();
+        let query = query.to_string();
+
+        tokio::task::spawn_blocking(move || run_query(&path, &query))
+            .await
+            .map_err(|e| sqlx::Error::Protocol(e.to_string()))?
+            .map_err(|e| sqlx::Error::Protocol(e.to_string()))
+    }
+
+    /// Runs a single statement and returns the number of rows it changed.
+    pub async fn execute(&self, query: &str) -> Result<u64, sqlx::Error> {
+        let path = self.path.clone();
+        let query = query.to_string();
+
+        tokio::task::spawn_blocking(move || Connection::open(&path)?.execute(&query, []))
+            .await
+            .map_err(|e| sqlx::Error::Protocol(e.to_string()))?
+            .map(|rows| rows as u64)
+            .map_err(|e| sqlx::Error::Protocol(e.to_string()))
+    }
+}
+
+fn run_query(path: &Path, query: &str) -> Result<Vec<AnyRow>, ::duckdb::Error> {
+    let conn = Connection::open(path)?;
+
+    let first_word = query
+        .split_whitespace()
+        .next()
+        .unwrap_or_default()
+        .to_uppercase();
+    if !QUERY_KEYWORDS.contains(&first_word.as_str()) {
+        conn.execute_batch(query)?;
+        return Ok(vec![]);
+    }
+
+    let mut stmt = conn.prepare(query)?;
+    let mut rows = stmt.query([])?;
+
+    let mut result = vec![];
+    while let Some(row) = rows.next()? {
+        let values = (0..row.as_ref().column_count())
+            .map(|idx| row.get_ref(idx).map_or(DbValue::Unsupported, to_db_value))
+            .collect();
+        result.push(AnyRow { values });
+    }
+
+    Ok(result)
+}
+
+fn to_db_value(value: ValueRef<'_>) -> DbValue {
+    match value {
+        ValueRef::Null => DbValue::Null,
+        ValueRef::Boolean(v) => DbValue::Bool(v),
+        ValueRef::TinyInt(v) => DbValue::I64(v.into()),
+        ValueRef::SmallInt(v) => DbValue::I64(v.into()),
+        ValueRef::Int(v) => DbValue::I64(v.into()),
+        ValueRef::BigInt(v) => DbValue::I64(v),
+        ValueRef::UTinyInt(v) => DbValue::I64(v.into()),
+        ValueRef::USmallInt(v) => DbValue::I64(v.into()),
+        ValueRef::UInt(v) => DbValue::I64(v.into()),
+        ValueRef::Float(v) => DbValue::F64(v.into()),
+        ValueRef::Double(v) => DbValue::F64(v),
+        ValueRef::Decimal(v) => {
+            rust_decimal::Decimal::try_from_i128_with_scale(v.value(), v.scale().into())
+                .map_or(DbValue::Unsupported, DbValue::Decimal)
+        }
+        ValueRef::Text(v) => DbValue::String(String::from_utf8_lossy(v).into_owned()),
+        ValueRef::Blob(v) => DbValue::Bytes(v.to_vec()),
+        ValueRef::Date32(days) => {
+            NaiveDate::from_num_days_from_ce_opt(UNIX_EPOCH_DAYS_FROM_CE + days)
+                .map_or(DbValue::Unsupported, DbValue::Date)
+        }
+        ValueRef::Timestamp(unit, v) => DateTime::from_timestamp_micros(unit.to_micros(v))
+            .map_or(DbValue::Unsupported, |v| DbValue::DateTime(v.naive_utc())),
+        _ => DbValue::Unsupported,
+    }
+}
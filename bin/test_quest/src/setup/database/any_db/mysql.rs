@@ -79,7 +79,11 @@ impl From<sqlx::mysql::MySqlRow> for AnyRow {
             };
 
             if value == DbValue::Null {
-                dbg!(typ);
+                tracing::debug!(
+                    column = name,
+                    column_type = typ,
+                    "column is NULL or failed to decode"
+                );
             }
 
             values.push(value);
@@ -103,7 +107,7 @@ mod test {
 
     #[tokio::test]
     async fn mysql_type_test() {
-        let database = database::from_type("mysql".into(), None, None)
+        let database = database::from_type("mysql".into(), None, None, false)
             .await
             .unwrap();
 
@@ -4,6 +4,14 @@ use chrono::NaiveDateTime;
 use chrono::Utc;
 use rust_decimal::Decimal;
 use sqlx::Column;
+use sqlx::Decode;
+use sqlx::Postgres;
+use sqlx::Row;
+use sqlx::Type;
+use sqlx::postgres::PgHasArrayType;
+use sqlx::postgres::PgRow;
+use sqlx::postgres::PgTypeKind;
+use sqlx::postgres::types::PgInterval;
 use uuid::Uuid;
 
 use crate::setup::database::any_db::AnyRow;
@@ -11,72 +19,30 @@ use crate::setup::database::any_db::DbValue;
 
 impl From<sqlx::postgres::PgRow> for AnyRow {
     fn from(row: sqlx::postgres::PgRow) -> Self {
-        use sqlx::Row;
-
         let mut values = Vec::with_capacity(row.len());
 
         for col in row.columns() {
             let name = col.name();
             let typ = col.type_info().to_string();
 
-            let value = match typ.as_str() {
-                "INT2" => row
-                    .try_get::<i16, _>(name)
-                    .map(|v| DbValue::I64(v as i64))
-                    .unwrap_or(DbValue::Null),
-                "INT4" => row
-                    .try_get::<i32, _>(name)
-                    .map(|v| DbValue::I64(v as i64))
-                    .unwrap_or(DbValue::Null),
-                "INT8" => row
-                    .try_get::<i64, _>(name)
-                    .map(DbValue::I64)
-                    .unwrap_or(DbValue::Null),
-                "FLOAT4" => row
-                    .try_get::<f32, _>(name)
-                    .map(|v| DbValue::F64(v as f64))
-                    .unwrap_or(DbValue::Null),
-                "FLOAT8" => row
-                    .try_get::<f64, _>(name)
-                    .map(DbValue::F64)
-                    .unwrap_or(DbValue::Null),
-                "BOOL" => row
-                    .try_get::<bool, _>(name)
-                    .map(DbValue::Bool)
-                    .unwrap_or(DbValue::Null),
-                "TEXT" | "VARCHAR" | "CHAR" => row
-                    .try_get::<String, _>(name)
+            // Enums are user defined, so they can't be matched on by name. Their
+            // values are sent as the label, which reads fine as a string.
+            if let PgTypeKind::Enum(_) = col.type_info().kind() {
+                let value = row
+                    .try_get_unchecked::<String, _>(name)
                     .map(DbValue::String)
-                    .unwrap_or(DbValue::Null),
-                "UUID" => row
-                    .try_get::<Uuid, _>(name)
-                    .map(DbValue::Uuid)
-                    .unwrap_or(DbValue::Null),
-                "NUMERIC" => row
-                    .try_get::<Decimal, _>(name)
-                    .map(DbValue::Decimal)
-                    .unwrap_or(DbValue::Null),
-                "JSON" | "JSONB" => row
-                    .try_get::<serde_json::Value, _>(name)
-                    .map(DbValue::Json)
-                    .unwrap_or(DbValue::Null),
-                "BYTEA" => row
-                    .try_get::<Vec<u8>, _>(name)
-                    .map(DbValue::Bytes)
-                    .unwrap_or(DbValue::Null),
-                "DATE" => row
-                    .try_get::<NaiveDate, _>(name)
-                    .map(DbValue::Date)
-                    .unwrap_or(DbValue::Null),
-                "TIMESTAMP" => row
-                    .try_get::<NaiveDateTime, _>(name)
-                    .map(DbValue::DateTime)
-                    .unwrap_or(DbValue::Null),
-                "TIMESTAMPTZ" => row
-                    .try_get::<DateTime<Utc>, _>(name)
-                    .map(DbValue::Timestamp)
-                    .unwrap_or(DbValue::Null),
-                _ => DbValue::Unsupported,
+                    .unwrap_or(DbValue::Null);
+                values.push(value);
+                continue;
+            }
+
+            // Arrays are told apart by their kind rather than their name, as a
+            // user defined type may start with `_` too.
+            let value = match col.type_info().kind() {
+                PgTypeKind::Array(element) => {
+                    array_value(&row, name, &element.to_string().to_uppercase())
+                }
+                _ => scalar_value(&row, name, &typ),
             };
 
             values.push(value);
@@ -86,15 +52,128 @@ impl From<sqlx::postgres::PgRow> for AnyRow {
     }
 }
 
+fn scalar_value(row: &PgRow, name: &str, typ: &str) -> DbValue {
+    match typ {
+        "INT2" => row
+            .try_get::<i16, _>(name)
+            .map(|v| DbValue::I64(v as i64))
+            .unwrap_or(DbValue::Null),
+        "INT4" => row
+            .try_get::<i32, _>(name)
+            .map(|v| DbValue::I64(v as i64))
+            .unwrap_or(DbValue::Null),
+        "INT8" => row
+            .try_get::<i64, _>(name)
+            .map(DbValue::I64)
+            .unwrap_or(DbValue::Null),
+        "FLOAT4" => row
+            .try_get::<f32, _>(name)
+            .map(|v| DbValue::F64(v as f64))
+            .unwrap_or(DbValue::Null),
+        "FLOAT8" => row
+            .try_get::<f64, _>(name)
+            .map(DbValue::F64)
+            .unwrap_or(DbValue::Null),
+        "BOOL" => row
+            .try_get::<bool, _>(name)
+            .map(DbValue::Bool)
+            .unwrap_or(DbValue::Null),
+        "TEXT" | "VARCHAR" | "CHAR" => row
+            .try_get::<String, _>(name)
+            .map(DbValue::String)
+            .unwrap_or(DbValue::Null),
+        "UUID" => row
+            .try_get::<Uuid, _>(name)
+            .map(DbValue::Uuid)
+            .unwrap_or(DbValue::Null),
+        "NUMERIC" => row
+            .try_get::<Decimal, _>(name)
+            .map(DbValue::Decimal)
+            .unwrap_or(DbValue::Null),
+        "JSON" | "JSONB" => row
+            .try_get::<serde_json::Value, _>(name)
+            .map(DbValue::Json)
+            .unwrap_or(DbValue::Null),
+        "BYTEA" => row
+            .try_get::<Vec<u8>, _>(name)
+            .map(DbValue::Bytes)
+            .unwrap_or(DbValue::Null),
+        "DATE" => row
+            .try_get::<NaiveDate, _>(name)
+            .map(DbValue::Date)
+            .unwrap_or(DbValue::Null),
+        "TIMESTAMP" => row
+            .try_get::<NaiveDateTime, _>(name)
+            .map(DbValue::DateTime)
+            .unwrap_or(DbValue::Null),
+        "TIMESTAMPTZ" => row
+            .try_get::<DateTime<Utc>, _>(name)
+            .map(DbValue::Timestamp)
+            .unwrap_or(DbValue::Null),
+        "INTERVAL" => row
+            .try_get::<PgInterval, _>(name)
+            .map(|v| DbValue::Interval {
+                months: v.months,
+                days: v.days,
+                microseconds: v.microseconds,
+            })
+            .unwrap_or(DbValue::Null),
+        // Anything else that decodes as text, e.g. `NAME` or extension types
+        // like `CITEXT`, is kept as a string.
+        _ => row
+            .try_get::<String, _>(name)
+            .map(DbValue::String)
+            .unwrap_or(DbValue::Unsupported),
+    }
+}
+
+fn array_value(row: &PgRow, name: &str, element: &str) -> DbValue {
+    match element {
+        "INT2" => get_array::<i16>(row, name, |v| DbValue::I64(v as i64)),
+        "INT4" => get_array::<i32>(row, name, |v| DbValue::I64(v as i64)),
+        "INT8" => get_array::<i64>(row, name, DbValue::I64),
+        "FLOAT4" => get_array::<f32>(row, name, |v| DbValue::F64(v as f64)),
+        "FLOAT8" => get_array::<f64>(row, name, DbValue::F64),
+        "BOOL" => get_array::<bool>(row, name, DbValue::Bool),
+        "TEXT" | "VARCHAR" | "CHAR" | "BPCHAR" => get_array::<String>(row, name, DbValue::String),
+        "UUID" => get_array::<Uuid>(row, name, DbValue::Uuid),
+        "NUMERIC" => get_array::<Decimal>(row, name, DbValue::Decimal),
+        "JSON" | "JSONB" => get_array::<serde_json::Value>(row, name, DbValue::Json),
+        "DATE" => get_array::<NaiveDate>(row, name, DbValue::Date),
+        "TIMESTAMP" => get_array::<NaiveDateTime>(row, name, DbValue::DateTime),
+        "TIMESTAMPTZ" => get_array::<DateTime<Utc>>(row, name, DbValue::Timestamp),
+        _ => DbValue::Unsupported,
+    }
+}
+
+/// Reads an array column, turning `NULL` elements into `DbValue::Null`.
+fn get_array<T>(row: &PgRow, name: &str, into: impl Fn(T) -> DbValue) -> DbValue
+where
+    T: for<'r> Decode<'r, Postgres> + Type<Postgres> + PgHasArrayType,
+{
+    row.try_get::<Vec<Option<T>>, _>(name)
+        .map(|values| {
+            DbValue::Array(
+                values
+                    .into_iter()
+                    .map(|v| v.map(&into).unwrap_or(DbValue::Null))
+                    .collect(),
+            )
+        })
+        .unwrap_or(DbValue::Null)
+}
+
 #[cfg(test)]
 mod test {
     use chrono::NaiveDate;
     use chrono::NaiveDateTime;
     use chrono::Utc;
+    use futures::StreamExt;
     use rust_decimal::Decimal;
     use serde_json::json;
     use sqlx::Executor;
     use sqlx::PgPool;
+    use sqlx::postgres::types::PgInterval;
     use uuid::Uuid;
 
     use crate::setup::database;
@@ -102,7 +181,7 @@ mod test {
 
     #[tokio::test]
     async fn postgres_type_test() {
-        let database = database::from_type("postgres".into(), None, None)
+        let database = database::from_type("postgres".into(), None, None, false)
             .await
             .unwrap();
 
@@ -125,12 +204,38 @@ mod test {
                 .all(|v| !matches!(v, DbValue::Null) && !matches!(v, DbValue::Unsupported))),
             "Vec contains a Null or Unsupported value!"
         );
+
+        let arrays = any_pool
+            .raw_sql("SELECT int_array_col, text_array_col FROM all_types")
+            .await
+            .unwrap();
+        assert_eq!(arrays[0].to_csv_line(), r#"{1,2,3},{a,"hello world"}"#);
+
+        let enum_and_interval = any_pool
+            .raw_sql("SELECT enum_col, interval_col FROM all_types")
+            .await
+            .unwrap();
+        assert_eq!(
+            enum_and_interval[0].to_csv_line(),
+            "happy,1 year 2 mons 3 days 04:05:06.5"
+        );
+
+        // Only the first of the generated rows is read.
+        let first = any_pool
+            .raw_sql_stream("SELECT n FROM generate_series(1, 1000000) AS n")
+            .next()
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(first.to_csv_line(), "1");
     }
 
     pub async fn setup_test_table(pool: &PgPool) -> sqlx::Result<()> {
         pool.execute(
             r#"
         DROP TABLE IF EXISTS all_types;
+        DROP TYPE IF EXISTS mood;
+        CREATE TYPE mood AS ENUM ('sad', 'ok', 'happy');
         CREATE TABLE all_types (
             id SERIAL PRIMARY KEY,
             smallint_col SMALLINT,
@@ -147,7 +252,11 @@ mod test {
             uuid_col UUID,
             jsonb_col JSONB,
             bytea_col BYTEA,
-            numeric_col NUMERIC
+            numeric_col NUMERIC,
+            int_array_col INTEGER[],
+            text_array_col TEXT[],
+            enum_col mood,
+            interval_col INTERVAL
         );
         "#,
         )
@@ -165,11 +274,13 @@ mod test {
         INSERT INTO all_types (
             smallint_col, integer_col, bigint_col, real_col, double_col,
             bool_col, text_col, varchar_col, date_col, timestamp_col,
-            timestamptz_col, uuid_col, jsonb_col, bytea_col, numeric_col
+            timestamptz_col, uuid_col, jsonb_col, bytea_col, numeric_col,
+            int_array_col, text_array_col, enum_col, interval_col
         ) VALUES (
             $1, $2, $3, $4, $5,
             $6, $7, $8, $9, $10,
-            $11, $12, $13, $14, $15
+            $11, $12, $13, $14, $15,
+            $16, $17, $18::mood, $19
         )
         "#,
         )
@@ -188,6 +299,14 @@ mod test {
         .bind(json_val)
         .bind(vec![1_u8, 2, 3, 4])
         .bind(Decimal::new(12345, 2))
+        .bind(vec![1_i32, 2, 3])
+        .bind(vec!["a", "hello world"])
+        .bind("happy")
+        .bind(PgInterval {
+            months: 14,
+            days: 3,
+            microseconds: 14_706_500_000,
+        })
         .execute(pool)
         .await?;
 
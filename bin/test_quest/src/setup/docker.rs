@@ -0,0 +1,250 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use thiserror::Error;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+use tracing::debug;
+
+/// How long the daemon gets to answer the ping.
+const PING_TIMEOUT: Duration = Duration::from_secs(5);
+
+const DOCKER_HOST: &str = "DOCKER_HOST";
+
+/// Set when the containers are reached somewhere other than where the daemon
+/// runs, a setup the check can't follow.
+const HOST_OVERRIDE: &str = "TESTCONTAINERS_HOST_OVERRIDE";
+
+/// testcontainers' own config file in the home directory.
+const PROPERTIES_FILE: &str = ".testcontainers.properties";
+
+const HINT: &str = "Start Docker (or Podman, e.g. with `systemctl --user start podman.socket`), \
+or point DOCKER_HOST at the daemon's socket. To use a database that is already running instead \
+of a container, set `url` in [db]. With the duckdb feature, db_type = \"duckdb\" runs without \
+a container too.";
+
+#[derive(Debug, Error)]
+pub enum DockerError {
+    #[error("Docker daemon not reachable at {host}: {reason}. Is Docker running? {HINT}")]
+    Unreachable { host: String, reason: String },
+
+    #[error("No Docker or Podman socket found. Is Docker running? {HINT}")]
+    NoSocket,
+
+    #[error(
+        "Found Podman's socket at {0}, but DOCKER_HOST doesn't point at it. Set DOCKER_HOST=unix://{0} so the database container can be started."
+    )]
+    PodmanNotExported(String),
+}
+
+/// Points `DOCKER_HOST` at Podman's socket when testcontainers has no host
+/// configured and no Docker socket exists, as testcontainers doesn't look for
+/// Podman's socket itself. Returns the host that was set.
+///
+/// # Safety
+/// Changes the environment, so it has to run before any other thread is
+/// started, e.g. at the top of `main` before the tokio runtime is built.
+pub unsafe fn use_podman_socket() -> Option<String> {
+    if configured_host().is_some() {
+        return None;
+    }
+    let (socket, true) = find_socket()? else {
+        return None;
+    };
+
+    let host = format!("unix://{}", socket.display());
+    // SAFETY: the caller guarantees no other thread is running yet.
+    unsafe { std::env::set_var(DOCKER_HOST, &host) };
+    Some(host)
+}
+
+/// Whether `TESTCONTAINERS_HOST_OVERRIDE` is set. A failed check is then only
+/// worth a warning, as the containers may well be reachable anyway.
+pub fn host_overridden() -> bool {
+    std::env::var_os(HOST_OVERRIDE).is_some_and(|host| !host.is_empty())
+}
+
+/// Finds the Docker or Podman daemon the database container will be started
+/// on and checks that it answers, so a daemon that isn't running is reported
+/// before testcontainers fails with a less helpful error. Returns the host it
+/// reached.
+///
+/// The host is looked up like testcontainers does: `tc.host` in
+/// `~/.testcontainers.properties`, then `DOCKER_HOST`, then `docker.host` in
+/// the properties file, then the usual Docker sockets. A Podman socket is only
+/// used once `use_podman_socket` pointed `DOCKER_HOST` at it.
+pub async fn check_daemon() -> Result<String, DockerError> {
+    let host = match configured_host() {
+        Some(host) => host,
+        None => match find_socket().ok_or(DockerError::NoSocket)? {
+            (socket, false) => format!("unix://{}", socket.display()),
+            (socket, true) => {
+                return Err(DockerError::PodmanNotExported(socket.display().to_string()));
+            }
+        },
+    };
+    debug!(host, "checking the docker daemon");
+
+    match tokio::time::timeout(PING_TIMEOUT, ping(&host)).await {
+        Ok(Ok(())) => Ok(host),
+        Ok(Err(reason)) => Err(DockerError::Unreachable { host, reason }),
+        Err(_) => Err(DockerError::Unreachable {
+            host,
+            reason: format!("no answer after {}s", PING_TIMEOUT.as_secs()),
+        }),
+    }
+}
+
+/// The host testcontainers is configured to use, if any, in the order it
+/// reads them.
+fn configured_host() -> Option<String> {
+    let properties = std::env::var_os("HOME")
+        .and_then(|home| std::fs::read_to_string(PathBuf::from(home).join(PROPERTIES_FILE)).ok())
+        .unwrap_or_default();
+    let env = std::env::var(DOCKER_HOST)
+        .ok()
+        .filter(|host| !host.trim().is_empty());
+
+    property(&properties, "tc.host")
+        .or(env)
+        .or_else(|| property(&properties, "docker.host"))
+}
+
+/// The value of `key` in a Java properties file, where keys are separated
+/// from their values by `=`, `:` or whitespace.
+fn property(properties: &str, key: &str) -> Option<String> {
+    properties.lines().find_map(|line| {
+        let line = line.trim_start();
+        if line.starts_with(['#', '!']) {
+            return None;
+        }
+        let end = line.find(|c: char| c == '=' || c == ':' || c.is_whitespace())?;
+        if &line[..end] != key {
+            return None;
+        }
+        let value = line[end..].trim_start();
+        let value = value.strip_prefix(['=', ':']).unwrap_or(value).trim();
+        (!value.is_empty()).then(|| value.to_string())
+    })
+}
+
+/// The first Docker or Podman socket that exists, and whether it is
+/// Podman's. Docker's are tried in the same order testcontainers does.
+fn find_socket() -> Option<(PathBuf, bool)> {
+    let runtime_dir = std::env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from);
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+
+    let docker = [
+        Some(PathBuf::from("/var/run/docker.sock")),
+        runtime_dir
+            .as_ref()
+            .map(|d| d.join(".docker/run/docker.sock")),
+        home.as_ref().map(|d| d.join(".docker/run/docker.sock")),
+        home.as_ref().map(|d| d.join(".docker/desktop/docker.sock")),
+    ];
+    let podman = [
+        runtime_dir.as_ref().map(|d| d.join("podman/podman.sock")),
+        Some(PathBuf::from("/run/podman/podman.sock")),
+    ];
+
+    docker
+        .into_iter()
+        .flatten()
+        .map(|path| (path, false))
+        .chain(podman.into_iter().flatten().map(|path| (path, true)))
+        .find(|(path, _)| path.exists())
+}
+
+/// Asks the daemon at `host` for `/_ping`. TCP hosts may expect TLS, so for
+/// those connecting is enough. Other hosts, like Windows named pipes, are
+/// left to testcontainers.
+async fn ping(host: &str) -> Result<(), String> {
+    if let Some(address) = host.strip_prefix("tcp://") {
+        TcpStream::connect(address.trim_end_matches('/'))
+            .await
+            .map_err(|e| e.to_string())?;
+        return Ok(());
+    }
+
+    #[cfg(unix)]
+    if let Some(path) = host.strip_prefix("unix://") {
+        let mut stream = tokio::net::UnixStream::connect(path)
+            .await
+            .map_err(|e| e.to_string())?;
+        stream
+            .write_all(b"GET /_ping HTTP/1.0\r\nHost: docker\r\n\r\n")
+            .await
+            .map_err(|e| e.to_string())?;
+        let mut response = String::new();
+        stream
+            .read_to_string(&mut response)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let status = response.lines().next().unwrap_or_default();
+        return match status.split_whitespace().nth(1) {
+            Some("200") => Ok(()),
+            _ => Err(format!("unexpected answer to ping: {status:?}")),
+        };
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::io::AsyncReadExt;
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::UnixListener;
+
+    use crate::setup::docker::ping;
+    use crate::setup::docker::property;
+
+    #[test]
+    fn properties_are_read_like_testcontainers_does() {
+        let properties = "# docker.host=unix:///commented.sock
+            tc.host = tcp://remote:2375
+            docker.host:unix:///var/run/docker.sock
+            docker.tls.verify 1";
+
+        assert_eq!(
+            property(properties, "tc.host").as_deref(),
+            Some("tcp://remote:2375")
+        );
+        assert_eq!(
+            property(properties, "docker.host").as_deref(),
+            Some("unix:///var/run/docker.sock")
+        );
+        assert_eq!(
+            property(properties, "docker.tls.verify").as_deref(),
+            Some("1")
+        );
+        assert_eq!(property(properties, "docker.cert.path"), None);
+    }
+
+    #[tokio::test]
+    async fn ping_asks_the_socket_for_ok() {
+        let dir = std::env::temp_dir().join(format!("tq-docker-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let socket = dir.join("docker.sock");
+        let listener = UnixListener::bind(&socket).unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = [0; 256];
+            let _ = stream.read(&mut request).await.unwrap();
+            stream
+                .write_all(b"HTTP/1.0 200 OK\r\nContent-Length: 2\r\n\r\nOK")
+                .await
+                .unwrap();
+        });
+
+        let host = format!("unix://{}", socket.display());
+        assert_eq!(ping(&host).await, Ok(()));
+
+        let missing = format!("unix://{}", dir.join("missing.sock").display());
+        assert!(ping(&missing).await.is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,31 +1,58 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
 use std::path::PathBuf;
 use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
 
+use cookie::SameSite;
 use miette::Diagnostic;
 use miette::NamedSource;
 use miette::SourceSpan;
+use regex::Regex;
 use reqwest::Method;
 use reqwest::Url;
+use reqwest::Version;
+use reqwest::header::ACCEPT_ENCODING;
 use reqwest::header::HeaderMap;
+use reqwest::header::HeaderName;
+use reqwest::header::HeaderValue;
 use thiserror::Error;
+use tracing::warn;
 
+mod json_path;
 mod parser_assertion;
 
+pub use json_path::JsonPath;
+
 use crate::parser;
 use crate::parser::Global;
 use crate::parser::Hook;
+use crate::parser::HookSql;
 use crate::parser::ImageRef;
+use crate::parser::NumberOrString;
+use crate::parser::Reset;
 use crate::parser::StringOrStrings;
 use crate::parser::TestQuest;
+use crate::setup::database::any_db::SqlRows;
+use crate::setup::database::db_types_for_url;
 
 // Error messages for parsing URLs
 const BASE_URL_ENDS_WITH: &str =
     "The base URL from setup can’t end with a /, and each URL in test must start with one";
-const PATH_URL_MISSING_SLASH: &str =
-    "The URL field in a test is required to begin with a leading /.";
+const PATH_URL_MISSING_SLASH: &str = "The URL field in a test is required to begin with a leading /, or to be a full http:// or https:// URL.";
+const BASE_URL_NOT_ABSOLUTE: &str = "The base URL from setup has to be an absolute URL with a scheme and host, like http://localhost:8080";
+
+/// How long the database gets to accept queries before setup gives up.
+const DEFAULT_DB_READY_TIMEOUT_SECS: u64 = 15;
 
-pub struct Validator {
-    test_quest: TestQuest,
+/// How long the app gets to exit after SIGTERM before it is force-killed.
+const DEFAULT_SHUTDOWN_GRACE_MS: u64 = 5000;
+
+pub struct Validator<'a> {
+    test_quest: &'a TestQuest,
     toml_src: String,
     file_name: String,
 }
@@ -33,45 +60,572 @@ pub struct Validator {
 #[derive(Debug, Clone)]
 pub enum Assertion {
     Status(i32),
-    Headers(HeaderMap),
+    Headers(Vec<HeaderAssertion>),
+    /// Like `Headers`, for the trailers that follow the body.
+    Trailers(Vec<HeaderAssertion>),
+    /// The body has to equal the one an earlier test stored as `var` with
+    /// `capture_json`. The runner fills in `captured` before the test runs,
+    /// it stays empty if no body was stored.
+    JsonEqualsVar {
+        var: String,
+        captured: Option<serde_json::Value>,
+    },
+    /// The header `name`, parsed as an integer, compared to `value`.
+    HeaderInt {
+        name: HeaderName,
+        op: Comparison,
+        value: i64,
+    },
+    /// The response has to come back over this HTTP version.
+    HttpVersion(Version),
+    /// The response has to be compressed with `encoding`. With `verify_body`
+    /// the body has to look like data in that encoding too.
+    ContentEncoding {
+        encoding: ContentEncoding,
+        verify_body: bool,
+    },
+    /// The rows `query` returns. `got` is filled in by the runner with the
+    /// rows, or why the query failed. With `poll` set the runner keeps
+    /// re-running the query until it matches.
     Sql {
         query: String,
         expect: StringOrStrings,
-        got: Option<Vec<String>>,
+        got: Option<Result<SqlRows, String>>,
+        epsilon: Option<f64>,
+        poll: Option<SqlPoll>,
     },
-    Json(serde_json::Value),
+    /// The single value `query` returns, compared to `value` with `op`. `got`
+    /// is filled in by the runner with the value, or why there is none.
+    SqlCompare {
+        query: String,
+        op: Comparison,
+        value: NumberOrString,
+        got: Option<Result<String, String>>,
+    },
+    Json {
+        expected: serde_json::Value,
+        epsilon: Option<f64>,
+        ignore_keys: Vec<String>,
+    },
+    /// The body has to equal one of these documents.
+    JsonAnyOf(Vec<serde_json::Value>),
+    JsonArrayLen {
+        path: JsonPath,
+        expected: usize,
+    },
+    /// Every path has to lead to a value other than null.
+    JsonHas(Vec<JsonPath>),
+    /// The values at two places in the body have to compare with `op`.
+    JsonRelation {
+        left: JsonOperand,
+        op: Comparison,
+        right: JsonOperand,
+    },
+    Cookie(ExpectedCookie),
+    EmptyBody,
+    ValidJson,
+    BodySize(BodySize),
+    /// The body has to equal the contents of `path`. With `update` the file is
+    /// overwritten with the body instead.
+    Snapshot {
+        path: PathBuf,
+        update: bool,
+    },
+    /// Where the redirects of the request lead. `got` is filled in by the
+    /// runner with every URL the request went through, starting with its own.
+    Redirect {
+        url: Option<String>,
+        hops: Option<usize>,
+        got: Option<Vec<Url>>,
+    },
+    /// The request has to fail with a matching error instead of getting a
+    /// response.
+    RequestError(ExpectedError),
+    /// The first message received over a WebSocket.
+    WsMessage(WsMessage),
+    /// The response can't have a 5xx status. Added to tests without
+    /// `assert_status` by `[setup] fail_on_server_error`.
+    NoServerError,
     RequestFailed,
+    /// The test hadn't finished when its group ran out of time. Added to the
+    /// tests a group `timeout_secs` aborted.
+    GroupTimeout(Duration),
+}
+
+/// What the first message of a WebSocket test has to look like.
+#[derive(Debug, Clone)]
+pub enum WsMessage {
+    Equals(String),
+    Contains(String),
+    Json(serde_json::Value),
+}
+
+impl fmt::Display for WsMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WsMessage::Equals(expected) => write!(f, "`{expected}`"),
+            WsMessage::Contains(expected) => write!(f, "containing `{expected}`"),
+            WsMessage::Json(expected) => write!(f, "matching {expected}"),
+        }
+    }
+}
+
+/// What a WebSocket test does once it is connected.
+#[derive(Debug, Clone, Default)]
+pub struct WsRequest {
+    /// Text message sent before waiting for one back.
+    pub send: Option<String>,
+}
+
+/// What the error of a request that is expected to fail has to look like.
+#[derive(Debug, Clone)]
+pub enum ExpectedError {
+    Contains(String),
+    Regex(Regex),
+}
+
+impl ExpectedError {
+    pub fn matches(&self, error: &str) -> bool {
+        match self {
+            ExpectedError::Contains(expected) => error.contains(expected.as_str()),
+            ExpectedError::Regex(regex) => regex.is_match(error),
+        }
+    }
+}
+
+impl fmt::Display for ExpectedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExpectedError::Contains(expected) => write!(f, "containing `{expected}`"),
+            ExpectedError::Regex(regex) => write!(f, "matching `{regex}`"),
+        }
+    }
+}
+
+/// How often and for how long a `poll_sql` query is re-run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SqlPoll {
+    pub interval: Duration,
+    pub timeout: Duration,
+}
+
+/// The expected size of the response body in bytes, from `assert_body_size`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BodySize {
+    Exact(usize),
+    /// At least one of the bounds is set, and `min` is at most `max`.
+    Range {
+        min: Option<usize>,
+        max: Option<usize>,
+    },
+}
+
+impl BodySize {
+    /// Whether a body of `bytes` bytes is within this size.
+    pub fn contains(&self, bytes: usize) -> bool {
+        match *self {
+            BodySize::Exact(expected) => bytes == expected,
+            BodySize::Range { min, max } => {
+                min.is_none_or(|min| bytes >= min) && max.is_none_or(|max| bytes <= max)
+            }
+        }
+    }
+}
+
+impl fmt::Display for BodySize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BodySize::Exact(bytes) => write!(f, "exactly {bytes} bytes"),
+            BodySize::Range {
+                min: Some(min),
+                max: Some(max),
+            } => write!(f, "between {min} and {max} bytes"),
+            BodySize::Range { min: Some(min), .. } => write!(f, "at least {min} bytes"),
+            BodySize::Range { max: Some(max), .. } => write!(f, "at most {max} bytes"),
+            BodySize::Range { .. } => write!(f, "any size"),
+        }
+    }
+}
+
+/// One side of `assert_json_relation`: the value at `path`, or its length.
+#[derive(Debug, Clone)]
+pub struct JsonOperand {
+    pub path: JsonPath,
+    pub length: bool,
+}
+
+impl JsonOperand {
+    /// Parses a JSON path, optionally followed by `.length()`.
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let (path, length) = match source.strip_suffix(".length()") {
+            Some(path) => (path, true),
+            None => (source, false),
+        };
+
+        Ok(Self {
+            path: JsonPath::parse(path)?,
+            length,
+        })
+    }
+
+    /// The value this operand stands for in `body`.
+    pub fn resolve(&self, body: &serde_json::Value) -> Result<serde_json::Value, String> {
+        let value = self
+            .path
+            .resolve(body)
+            .ok_or_else(|| format!("{} does not exist in the response body", self.path))?;
+
+        match (self.length, value) {
+            (false, value) => Ok(value.clone()),
+            (true, serde_json::Value::Array(items)) => Ok(items.len().into()),
+            (true, serde_json::Value::String(s)) => Ok(s.chars().count().into()),
+            (true, value) => Err(format!(
+                "{} is not an array or a string, got {value}",
+                self.path
+            )),
+        }
+    }
+}
+
+impl fmt::Display for JsonOperand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.path)?;
+        if self.length {
+            write!(f, ".length()")?;
+        }
+        Ok(())
+    }
+}
+
+/// How a value from the response is compared to the expected one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+impl Comparison {
+    /// Whether a value that is `ordering` compared to the expected one
+    /// satisfies this comparison.
+    pub fn holds(self, ordering: Ordering) -> bool {
+        match self {
+            Comparison::Eq => ordering.is_eq(),
+            Comparison::Ne => ordering.is_ne(),
+            Comparison::Gt => ordering.is_gt(),
+            Comparison::Ge => ordering.is_ge(),
+            Comparison::Lt => ordering.is_lt(),
+            Comparison::Le => ordering.is_le(),
+        }
+    }
+}
+
+impl FromStr for Comparison {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "==" => Ok(Comparison::Eq),
+            "!=" => Ok(Comparison::Ne),
+            ">" => Ok(Comparison::Gt),
+            ">=" => Ok(Comparison::Ge),
+            "<" => Ok(Comparison::Lt),
+            "<=" => Ok(Comparison::Le),
+            other => Err(format!(
+                "Expected `==`, `!=`, `>`, `>=`, `<` or `<=`, got `{other}`"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Comparison::Eq => write!(f, "=="),
+            Comparison::Ne => write!(f, "!="),
+            Comparison::Gt => write!(f, ">"),
+            Comparison::Ge => write!(f, ">="),
+            Comparison::Lt => write!(f, "<"),
+            Comparison::Le => write!(f, "<="),
+        }
+    }
+}
+
+/// A compression the response can be asserted to use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentEncoding {
+    Gzip,
+    Deflate,
+}
+
+impl ContentEncoding {
+    /// The name used in `Content-Encoding` and `Accept-Encoding`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ContentEncoding::Gzip => "gzip",
+            ContentEncoding::Deflate => "deflate",
+        }
+    }
+
+    /// Whether `body` starts with the header of data in this encoding, the
+    /// gzip magic bytes or a zlib header.
+    pub fn looks_encoded(self, body: &[u8]) -> bool {
+        match (self, body) {
+            (ContentEncoding::Gzip, [0x1f, 0x8b, ..]) => true,
+            (ContentEncoding::Deflate, [cmf, flg, ..]) => {
+                cmf & 0x0f == 8 && (u16::from(*cmf) << 8 | u16::from(*flg)) % 31 == 0
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for ContentEncoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "gzip" => Ok(ContentEncoding::Gzip),
+            "deflate" => Ok(ContentEncoding::Deflate),
+            _ => Err(format!("Expected `gzip` or `deflate`, got `{s}`")),
+        }
+    }
+}
+
+impl fmt::Display for ContentEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// The cookie from `assert_cookie`, with `samesite` parsed.
+#[derive(Debug, Clone)]
+pub struct ExpectedCookie {
+    pub name: String,
+    pub value: Option<String>,
+    pub http_only: Option<bool>,
+    pub secure: Option<bool>,
+    pub same_site: Option<SameSite>,
+    pub max_age: Option<i64>,
+}
+
+impl Assertion {
+    /// The key the assertion is written with in a test.
+    pub fn config_key(&self) -> Option<&'static str> {
+        Some(match self {
+            Assertion::Status(_) => "assert_status",
+            Assertion::Headers(_) => "assert_headers",
+            Assertion::Trailers(_) => "assert_trailers",
+            Assertion::JsonEqualsVar { .. } => "assert_json_equals_var",
+            Assertion::HeaderInt { .. } => "assert_header_int",
+            Assertion::HttpVersion(_) => "assert_http_version",
+            Assertion::ContentEncoding { .. } => "assert_content_encoding",
+            Assertion::Sql { poll: Some(_), .. } => "poll_sql",
+            Assertion::Sql { .. } => "assert_db_state",
+            Assertion::SqlCompare { .. } => "assert_db_compare",
+            Assertion::Json { .. } => "assert_json",
+            Assertion::JsonAnyOf(_) => "assert_json_any_of",
+            Assertion::JsonArrayLen { .. } => "assert_json_array_len",
+            Assertion::JsonHas(_) => "assert_json_has",
+            Assertion::JsonRelation { .. } => "assert_json_relation",
+            Assertion::Cookie(_) => "assert_cookie",
+            Assertion::EmptyBody => "assert_empty_body",
+            Assertion::ValidJson => "assert_valid_json",
+            Assertion::BodySize(_) => "assert_body_size",
+            Assertion::Snapshot { .. } => "assert_body_snapshot",
+            Assertion::Redirect { .. } => "assert_redirect",
+            Assertion::RequestError(_) => "assert_request_error",
+            Assertion::WsMessage(_) => "assert_ws_message",
+            Assertion::NoServerError | Assertion::RequestFailed | Assertion::GroupTimeout(_) => {
+                return None;
+            }
+        })
+    }
+
+    /// Whether checking this assertion requires the response body to be read.
+    pub fn needs_body(&self) -> bool {
+        match self {
+            Assertion::Json { .. }
+            | Assertion::JsonAnyOf(_)
+            | Assertion::JsonArrayLen { .. }
+            | Assertion::JsonHas(_)
+            | Assertion::JsonEqualsVar { .. }
+            | Assertion::JsonRelation { .. }
+            | Assertion::EmptyBody
+            | Assertion::ValidJson
+            | Assertion::BodySize(_)
+            | Assertion::Snapshot { .. }
+            | Assertion::WsMessage(_)
+            | Assertion::NoServerError
+            | Assertion::Trailers(_) => true,
+            Assertion::ContentEncoding { verify_body, .. } => *verify_body,
+            Assertion::Status(_)
+            | Assertion::Headers(_)
+            | Assertion::HeaderInt { .. }
+            | Assertion::HttpVersion(_)
+            | Assertion::Sql { .. }
+            | Assertion::SqlCompare { .. }
+            | Assertion::Cookie(_)
+            | Assertion::Redirect { .. }
+            | Assertion::RequestError(_)
+            | Assertion::RequestFailed
+            | Assertion::GroupTimeout(_) => false,
+        }
+    }
+
+    /// Whether this assertion looks at the body as the bytes that were sent,
+    /// which may not be text.
+    pub fn needs_raw_body(&self) -> bool {
+        matches!(
+            self,
+            Assertion::ContentEncoding {
+                verify_body: true,
+                ..
+            }
+        )
+    }
+}
+
+/// An expected response header from `assert_headers`.
+#[derive(Debug, Clone)]
+pub struct HeaderAssertion {
+    pub name: HeaderName,
+    pub value: String,
+    pub mode: HeaderMatch,
+    pub ignore_case: bool,
+}
+
+/// How a header value is compared against `HeaderAssertion::value`.
+#[derive(Debug, Clone)]
+pub enum HeaderMatch {
+    Exact,
+    Contains,
+    /// `value` compiled, with `ignore_case` already applied.
+    Regex(Regex),
+}
+
+impl HeaderAssertion {
+    /// Whether `actual`, one value of the header, satisfies this assertion.
+    pub fn matches(&self, actual: &str) -> bool {
+        match &self.mode {
+            HeaderMatch::Exact if self.ignore_case => actual.eq_ignore_ascii_case(&self.value),
+            HeaderMatch::Exact => actual == self.value,
+            HeaderMatch::Contains if self.ignore_case => {
+                actual.to_lowercase().contains(&self.value.to_lowercase())
+            }
+            HeaderMatch::Contains => actual.contains(&self.value),
+            HeaderMatch::Regex(regex) => regex.is_match(actual),
+        }
+    }
 }
 
+#[derive(Debug)]
 pub struct EnvSetup {
     pub base_url: String,
     pub command: String,
     pub args: Option<Vec<String>>,
+    pub cwd: Option<PathBuf>,
+    pub env: HashMap<String, String>,
     pub ready_when: String,
     pub db_type: String,
-    pub migration_dir: Option<String>,
+    pub migration_dirs: Vec<String>,
     pub db_port: Option<u16>,
     pub database_url_env: String,
     pub init_sql: Option<PathBuf>,
     pub image_ref: Option<ImageRef>,
+    pub db_ready_timeout_secs: u64,
+    pub db_warmup: bool,
+    pub db_reuse: bool,
+    /// A database that is already running, used instead of a container.
+    pub db_url: Option<String>,
+    pub shutdown_grace_ms: u64,
+    pub pre_commands: Vec<String>,
+    pub post_commands: Vec<String>,
+    pub before_all: Option<BeforeEach>,
+    pub after_all: Option<BeforeEach>,
 }
 
+#[derive(Clone)]
 pub struct IR {
     pub before_each_group: Option<BeforeEach>,
     pub tests: Vec<TestGroups>,
+    pub concurrency: usize,
+    pub client: ClientConfig,
 }
 
+impl IR {
+    /// Makes every `assert_body_snapshot` rewrite its file with the body it
+    /// gets instead of comparing against it.
+    pub fn update_snapshots(&mut self) {
+        let assertions = self
+            .tests
+            .iter_mut()
+            .flat_map(|group| &mut group.tests)
+            .flat_map(|test| Arc::make_mut(&mut test.assertions));
+
+        for assertion in assertions {
+            if let Assertion::Snapshot { update, .. } = assertion {
+                *update = true;
+            }
+        }
+    }
+}
+
+/// How the HTTP client that sends every request of a run is built.
+#[derive(Clone, Debug, Default)]
+pub struct ClientConfig {
+    pub http2: bool,
+    pub pool_idle_timeout: Option<Duration>,
+    pub pool_max_idle_per_host: Option<usize>,
+    pub accept_invalid_certs: bool,
+    pub ca_cert: Option<PathBuf>,
+    pub proxy: Option<String>,
+    pub no_proxy: Vec<String>,
+    pub user_agent: Option<HeaderValue>,
+    /// Sent with every request, unless the request sets the header itself.
+    pub default_headers: HeaderMap,
+}
+
+#[derive(Clone)]
 pub struct TestGroups {
     pub name: String,
     pub before_group: Option<BeforeEach>,
     pub before_each_test: Option<BeforeEach>,
+    pub timeout: Option<Duration>,
     pub tests: Vec<ValidatedTests>,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct BeforeEach {
-    pub reset_db: Option<bool>,
-    pub sql: Option<Vec<String>>,
+    pub reset_db: Option<DbReset>,
+    pub sql: Option<Vec<HookStatement>>,
+}
+
+/// The part of the database a hook resets.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DbReset {
+    All,
+    Tables(Vec<String>),
+}
+
+/// A hook statement and, if set, the number of rows it has to change.
+#[derive(Clone, Debug)]
+pub struct HookStatement {
+    pub query: String,
+    pub expect_affected: Option<u64>,
+}
+
+/// Where something was defined in the config file.
+#[derive(Debug, Clone)]
+pub struct SourceLocation {
+    /// Path of the config file, as it was loaded.
+    pub file: Arc<str>,
+    pub span: SourceSpan,
+    /// 1-based line number of the start of `span`.
+    pub line: usize,
 }
 
 #[derive(Clone)]
@@ -80,11 +634,47 @@ pub struct ValidatedTests {
     // before_run that makes the most sense
     pub before_run: Option<BeforeEach>,
     pub name: String,
+    /// Name of the test group the test belongs to.
+    pub group: String,
     pub method: Method,
     pub url: Url,
-    pub headers: HeaderMap,
-    pub body: Option<serde_json::Value>,
-    pub assertions: Vec<Assertion>,
+    /// The parts of a test that are only read are shared, so cloning the
+    /// `IR` for every run stays cheap.
+    pub headers: Arc<HeaderMap>,
+    pub body: Option<Arc<serde_json::Value>>,
+    /// Only cloned when the runner fills in what an assertion got.
+    pub assertions: Arc<Vec<Assertion>>,
+    pub timeout: Option<Duration>,
+    pub retries: u32,
+    pub follow_redirects: bool,
+    pub location: Option<SourceLocation>,
+    /// Where each of `assertions` was defined, in the same order.
+    pub assertion_locations: Vec<Option<SourceLocation>>,
+    /// Names of earlier tests in the group that have to pass for this test to
+    /// run.
+    pub depends_on: Vec<String>,
+    /// Stream the body to a temp file instead of reading it into memory.
+    pub capture_to_file: bool,
+    /// Name the JSON body of the response is stored under for later tests.
+    pub capture_json: Option<String>,
+    /// Set for tests that talk to a WebSocket instead of sending a request.
+    pub ws: Option<WsRequest>,
+}
+
+impl ValidatedTests {
+    /// Whether the test stores its body or compares it to a stored one,
+    /// which ties it to its place in the group.
+    pub fn uses_captures(&self) -> bool {
+        self.capture_json.is_some() || self.compared_captures().next().is_some()
+    }
+
+    /// The names of the stored bodies the test compares its body to.
+    pub fn compared_captures(&self) -> impl Iterator<Item = &str> {
+        self.assertions.iter().filter_map(|a| match a {
+            Assertion::JsonEqualsVar { var, .. } => Some(var.as_str()),
+            _ => None,
+        })
+    }
 }
 
 #[derive(Debug, Error, Diagnostic)]
@@ -112,10 +702,10 @@ macro_rules! validation_err {
     };
 }
 
-impl Validator {
-    pub fn new(test_quest: &TestQuest, toml_src: &str, file_name: &str) -> Self {
+impl<'a> Validator<'a> {
+    pub fn new(test_quest: &'a TestQuest, toml_src: &str, file_name: &str) -> Self {
         Self {
-            test_quest: test_quest.clone(),
+            test_quest,
             toml_src: toml_src.into(),
             file_name: file_name.into(),
         }
@@ -149,6 +739,7 @@ impl Validator {
                     .map(|test| {
                         self.create_test(
                             test,
+                            &name,
                             file_name.as_ref(),
                             toml_src.as_ref(),
                             &self.test_quest.setup.base_url,
@@ -157,10 +748,24 @@ impl Validator {
                     })
                     .collect::<Result<Vec<_>, ValidationError>>()?;
 
+                self.check_depends_on(&tests)?;
+                self.check_captures(&tests)?;
+                self.check_has_assertions(&tests)?;
+
+                if group.timeout_secs == Some(0) {
+                    return Err(validation_err!(
+                        format!("{name} - timeout_secs"),
+                        "timeout_secs has to be larger than 0",
+                        self,
+                        &name
+                    ));
+                }
+
                 Ok(TestGroups {
                     name,
                     before_each_test,
                     before_group,
+                    timeout: group.timeout_secs.map(Duration::from_secs),
                     tests,
                 })
             })
@@ -169,12 +774,134 @@ impl Validator {
         Ok(IR {
             before_each_group,
             tests: test_groups,
+            concurrency: self.test_quest.setup.concurrency.unwrap_or(1).max(1),
+            client: self.client_config()?,
+        })
+    }
+
+    /// Checks that every `depends_on` names a test defined before it in the
+    /// same group, so dependencies always run first.
+    fn check_depends_on(&self, tests: &[ValidatedTests]) -> Result<(), ValidationError> {
+        for (idx, test) in tests.iter().enumerate() {
+            for dependency in &test.depends_on {
+                if !tests[..idx].iter().any(|t| &t.name == dependency) {
+                    return Err(validation_err!(
+                        format!("{} - depends_on", test.name),
+                        format!(
+                            "`{dependency}` has to be a test defined before `{}` in the same group",
+                            test.name
+                        ),
+                        self,
+                        dependency
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks that every `assert_json_equals_var` names a body stored by a
+    /// test defined before it in the same group, so it is there by the time
+    /// it is compared.
+    fn check_captures(&self, tests: &[ValidatedTests]) -> Result<(), ValidationError> {
+        for (idx, test) in tests.iter().enumerate() {
+            if let Some(var) = &test.capture_json
+                && test.capture_to_file
+            {
+                return Err(validation_err!(
+                    format!("{} - capture_json", test.name),
+                    "a body streamed with capture_to_file isn't read, so it can't be stored",
+                    self,
+                    var
+                ));
+            }
+
+            let vars = test.assertions.iter().filter_map(|a| match a {
+                Assertion::JsonEqualsVar { var, .. } => Some(var),
+                _ => None,
+            });
+            for var in vars {
+                if !tests[..idx]
+                    .iter()
+                    .any(|t| t.capture_json.as_ref() == Some(var))
+                {
+                    return Err(validation_err!(
+                        format!("{} - assert_json_equals_var", test.name),
+                        format!(
+                            "`{var}` has to be stored with capture_json by a test defined before `{}` in the same group",
+                            test.name
+                        ),
+                        self,
+                        var
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// A test without assertions passes without checking anything. It is
+    /// rejected with `require_assertions` and only warned about otherwise.
+    fn check_has_assertions(&self, tests: &[ValidatedTests]) -> Result<(), ValidationError> {
+        let required = self.test_quest.setup.require_assertions.unwrap_or(false);
+
+        for test in tests.iter().filter(|t| t.assertions.is_empty()) {
+            if required {
+                return Err(validation_err!(
+                    format!("{} - name", test.name),
+                    "the test has no `assert_*` fields, so it can't fail",
+                    self,
+                    &test.name
+                ));
+            }
+            warn!(
+                test = %test.name,
+                group = %test.group,
+                "test has no assertions and passes without checking anything"
+            );
+        }
+
+        Ok(())
+    }
+
+    fn client_config(&self) -> Result<ClientConfig, ValidationError> {
+        let setup = &self.test_quest.setup;
+
+        let user_agent = setup
+            .user_agent
+            .as_deref()
+            .map(|agent| {
+                HeaderValue::from_str(agent)
+                    .map_err(|e| validation_err!("setup.user_agent", format!("{e}"), self, agent))
+            })
+            .transpose()?;
+        let default_headers = match &setup.default_headers {
+            Some(value) => parser_assertion::parse_header_map(
+                value,
+                Some(&(self.file_name.clone(), self.toml_src.clone())),
+            )?,
+            None => HeaderMap::new(),
+        };
+
+        Ok(ClientConfig {
+            http2: setup.http2.unwrap_or(false),
+            pool_idle_timeout: setup.pool_idle_timeout_ms.map(Duration::from_millis),
+            pool_max_idle_per_host: setup.pool_max_idle_per_host,
+            accept_invalid_certs: setup.accept_invalid_certs.unwrap_or(false),
+            ca_cert: setup.ca_cert.as_ref().map(PathBuf::from),
+            proxy: setup.proxy.clone(),
+            no_proxy: setup.no_proxy.clone().unwrap_or_default(),
+            user_agent,
+            default_headers,
         })
     }
 
     fn create_test(
         &self,
         test: &parser::Test,
+        group: &str,
         file_name: &str,
         toml_src: &str,
         base_url: &str,
@@ -188,6 +915,9 @@ impl Validator {
             ParseUrlError::SetupUrlEndsWithSlash => {
                 validation_err!("setup.base_url", BASE_URL_ENDS_WITH, self, &base_url)
             }
+            ParseUrlError::BaseUrlNotAbsolute => {
+                validation_err!("setup.base_url", BASE_URL_NOT_ABSOLUTE, self, &base_url)
+            }
 
             ParseUrlError::PathUrlMissingSlash => validation_err!(
                 format!("{}/url", test.name),
@@ -203,14 +933,27 @@ impl Validator {
             ),
         })?;
 
-        let body = test.body.clone();
+        let body = self.request_body(test)?.map(Arc::new);
+        let (url, ws) = self.websocket(test, &method, url)?;
         let name = test.name.clone();
         let before_run = self.create_before_each(&test.before_run)?;
 
+        // A test's own `timeout_ms`/`retries` take precedence over the defaults
+        // from `[setup]`.
+        let setup = &self.test_quest.setup;
+        let timeout = test
+            .timeout_ms
+            .or(setup.default_timeout_ms)
+            .map(Duration::from_millis);
+        let retries = test.retries.or(setup.default_retries).unwrap_or(0);
+
+        let location = find_test_location(group, &test.name, &self.toml_src, &self.file_name);
+
         // Start with the global headers if defined, and add them to the request's
         // HeaderMap. Then, merge the headers from the individual test. If a
         // header exists in both the global and test headers, the test header
-        // takes precedence.
+        // takes precedence. Both replace the `[setup] default_headers` the
+        // client adds.
         let mut headers = if let Some(global_value) = &global.headers {
             parser_assertion::parse_header_map(
                 global_value,
@@ -233,55 +976,311 @@ impl Validator {
             }
         }
 
-        let assertions = parser_assertion::parse_assertions(
-            &test.assert_status,
-            &test.assert_headers,
-            &test.assert_db_state,
-            &test.assert_json,
-            Some((file_name, toml_src)),
-        )?;
+        let mut assertions = parser_assertion::parse_assertions(test, Some((file_name, toml_src)))?;
+
+        // A test that asserts the status, or expects no response at all, has
+        // already said which statuses are fine.
+        if setup.fail_on_server_error.unwrap_or(false)
+            && !assertions
+                .iter()
+                .any(|a| matches!(a, Assertion::Status(_) | Assertion::RequestError(_)))
+        {
+            assertions.push(Assertion::NoServerError);
+        }
+
+        // Servers only compress when asked to, so ask for the asserted
+        // encoding unless the test already does.
+        if let Some(Assertion::ContentEncoding { encoding, .. }) = assertions
+            .iter()
+            .find(|a| matches!(a, Assertion::ContentEncoding { .. }))
+        {
+            headers
+                .entry(ACCEPT_ENCODING)
+                .or_insert(HeaderValue::from_static(encoding.as_str()));
+        }
+        let assertion_locations = assertions
+            .iter()
+            .map(|assertion| {
+                let test = location.as_ref()?;
+                find_key_location(assertion.config_key()?, test, &self.toml_src)
+            })
+            .collect();
 
         Ok(ValidatedTests {
             before_run,
             name,
+            group: group.to_string(),
             body,
             method,
-            headers,
+            headers: Arc::new(headers),
             url,
-            assertions,
+            assertions: Arc::new(assertions),
+            timeout,
+            retries,
+            follow_redirects: test.follow_redirects.unwrap_or(true),
+            location,
+            assertion_locations,
+            depends_on: test.depends_on.clone().unwrap_or_default(),
+            capture_to_file: test.capture_to_file.unwrap_or(false),
+            capture_json: test.capture_json.clone(),
+            ws,
         })
     }
 
+    /// Checks the WebSocket options of `test` against its `protocol`. For a
+    /// `ws` test, the scheme of `url` is turned into `ws` or `wss`.
+    fn websocket(
+        &self,
+        test: &parser::Test,
+        method: &Method,
+        mut url: Url,
+    ) -> Result<(Url, Option<WsRequest>), ValidationError> {
+        let field = |key: &str| format!("{} - {key}", test.name);
+        let is_ws = match test.protocol.as_deref() {
+            None | Some("http") => false,
+            Some("ws") => true,
+            Some(protocol) => {
+                return Err(validation_err!(
+                    field("protocol"),
+                    format!("Expected `http` or `ws`, got `{protocol}`"),
+                    self,
+                    protocol
+                ));
+            }
+        };
+
+        if !is_ws {
+            let ws_only = [
+                ("ws_send", test.ws_send.is_some()),
+                ("assert_ws_message", test.assert_ws_message.is_some()),
+                ("url", matches!(url.scheme(), "ws" | "wss")),
+            ];
+            if let Some((key, _)) = ws_only.iter().find(|(_, set)| *set) {
+                return Err(validation_err!(
+                    field(key),
+                    "only WebSocket tests can use this, set `protocol = \"ws\"`",
+                    self,
+                    if *key == "url" { &test.url } else { &test.name }
+                ));
+            }
+            return Ok((url, None));
+        }
+
+        if method != Method::GET {
+            return Err(validation_err!(
+                field("method"),
+                "a WebSocket handshake is a GET request",
+                self,
+                &test.method
+            ));
+        }
+
+        let unsupported = [
+            ("body", test.body.is_some() || test.body_file.is_some()),
+            ("assert_redirect", test.assert_redirect.is_some()),
+            (
+                "assert_content_encoding",
+                test.assert_content_encoding.is_some(),
+            ),
+            ("capture_to_file", test.capture_to_file == Some(true)),
+            ("assert_trailers", test.assert_trailers.is_some()),
+        ];
+        if let Some((key, _)) = unsupported.iter().find(|(_, set)| *set) {
+            return Err(validation_err!(
+                field(key),
+                "can't be used with `protocol = \"ws\"`",
+                self,
+                &test.name
+            ));
+        }
+
+        let scheme = match url.scheme() {
+            "http" => "ws",
+            "https" => "wss",
+            scheme => scheme,
+        }
+        .to_string();
+        // Switching between special schemes like these always succeeds.
+        let _ = url.set_scheme(&scheme);
+
+        Ok((
+            url,
+            Some(WsRequest {
+                send: test.ws_send.clone(),
+            }),
+        ))
+    }
+
+    /// The inline `body` of `test`, or the contents of its `body_file` read
+    /// relative to the config file.
+    fn request_body(
+        &self,
+        test: &parser::Test,
+    ) -> Result<Option<serde_json::Value>, ValidationError> {
+        let Some(body_file) = &test.body_file else {
+            return Ok(test.body.clone());
+        };
+        let invalid = |message: String| {
+            validation_err!(
+                format!("{} - body_file", test.name),
+                message,
+                self,
+                body_file
+            )
+        };
+
+        if test.body.is_some() {
+            return Err(invalid("`body` and `body_file` can't both be set".into()));
+        }
+
+        let dir = Path::new(&self.file_name).parent().unwrap_or(Path::new(""));
+        let path = dir.join(body_file);
+        let contents = std::fs::read_to_string(&path)
+            .map_err(|e| invalid(format!("couldn't read {}: {e}", path.display())))?;
+
+        let body = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => serde_json::from_str(&contents).map_err(|e| e.to_string()),
+            Some("yaml" | "yml") => serde_yaml::from_str(&contents).map_err(|e| e.to_string()),
+            _ => Err("has to be a .json, .yaml or .yml file".to_string()),
+        }
+        .map_err(|e| invalid(format!("{}: {e}", path.display())))?;
+
+        Ok(Some(body))
+    }
+
+    /// `migration_dir` followed by the entries of `migration_dirs`, in the
+    /// order they are run.
+    fn migration_dirs(&self) -> Vec<String> {
+        let db = &self.test_quest.db;
+
+        db.migration_dir
+            .iter()
+            .chain(db.migration_dirs.iter().flatten())
+            .cloned()
+            .collect()
+    }
+
     fn validate_setup(&self) -> Result<EnvSetup, ValidationError> {
         let path = self.test_quest.db.init_sql.as_ref().map(PathBuf::from);
+        if let Some(url) = &self.test_quest.db.url {
+            self.validate_db_url(url)?;
+        }
 
         Ok(EnvSetup {
             base_url: self.test_quest.setup.base_url.clone(),
             command: self.test_quest.setup.command.clone(),
             args: self.test_quest.setup.args.clone(),
+            cwd: self.test_quest.setup.cwd.as_ref().map(PathBuf::from),
+            env: self.test_quest.setup.env.clone().unwrap_or_default(),
             ready_when: self.test_quest.setup.ready_when.clone(),
             db_type: self.test_quest.db.db_type.clone(),
-            migration_dir: Some(self.test_quest.db.migration_dir.clone()),
+            migration_dirs: self.migration_dirs(),
             db_port: self.test_quest.db.port,
             init_sql: path,
             image_ref: self.test_quest.db.image_ref.clone(),
+            db_ready_timeout_secs: self
+                .test_quest
+                .db
+                .ready_timeout_secs
+                .unwrap_or(DEFAULT_DB_READY_TIMEOUT_SECS),
+            db_warmup: self.test_quest.db.warmup.unwrap_or(true),
+            db_reuse: self.test_quest.db.reuse.unwrap_or(false),
+            db_url: self.test_quest.db.url.clone(),
             database_url_env: self
                 .test_quest
                 .setup
                 .database_url_env
                 .clone()
                 .unwrap_or("DATABASE_URL".into()),
+            shutdown_grace_ms: self
+                .test_quest
+                .setup
+                .shutdown_grace_ms
+                .unwrap_or(DEFAULT_SHUTDOWN_GRACE_MS),
+            pre_commands: self
+                .test_quest
+                .setup
+                .pre_commands
+                .clone()
+                .unwrap_or_default(),
+            post_commands: self
+                .test_quest
+                .setup
+                .post_commands
+                .clone()
+                .unwrap_or_default(),
+            before_all: self.create_before_each(&self.test_quest.before_all)?,
+            after_all: self.create_before_each(&self.test_quest.after_all)?,
         })
     }
 
+    /// `[db] url` has to be a database `db_type` can connect to.
+    fn validate_db_url(&self, url: &str) -> Result<(), ValidationError> {
+        let db_type = &self.test_quest.db.db_type;
+        let db_types = db_types_for_url(url);
+        let message = if db_types.is_empty() {
+            "test_quest can't connect to a database with this URL's scheme".to_string()
+        } else if !db_types.contains(&db_type.as_str()) {
+            format!("the URL doesn't point to a {db_type} database, as db_type says")
+        } else {
+            return Ok(());
+        };
+
+        Err(validation_err!("db.url", message, self, url))
+    }
+
     fn create_before_each(
         &self,
         hook: &Option<Hook>,
     ) -> Result<Option<BeforeEach>, ValidationError> {
         if let Some(hook) = hook {
+            let reset_db = match &hook.reset {
+                None | Some(Reset::All(false)) => None,
+                // Truncating every table of a database test_quest didn't
+                // start could wipe data that has nothing to do with the tests.
+                Some(Reset::All(true)) if self.test_quest.db.url.is_some() => {
+                    return Err(validation_err!(
+                        "reset",
+                        "reset = true would empty every table of the database at [db] url, list the tables to reset instead",
+                        self,
+                        "reset"
+                    ));
+                }
+                Some(Reset::All(true)) => Some(DbReset::All),
+                Some(Reset::Tables(tables)) => {
+                    if let Some(table) = tables.iter().find(|t| t.trim().is_empty()) {
+                        return Err(validation_err!(
+                            "reset",
+                            "Table names in reset can't be empty",
+                            self,
+                            table
+                        ));
+                    }
+                    Some(DbReset::Tables(tables.clone()))
+                }
+            };
+
             Ok(Some(BeforeEach {
-                reset_db: Some(hook.reset.unwrap_or(false)),
-                sql: Some(hook.run_sql.clone().unwrap_or_default()),
+                reset_db,
+                sql: Some(
+                    hook.run_sql
+                        .iter()
+                        .flatten()
+                        .map(|sql| match sql {
+                            HookSql::Query(query) => HookStatement {
+                                query: query.clone(),
+                                expect_affected: None,
+                            },
+                            HookSql::Checked {
+                                query,
+                                expect_affected,
+                            } => HookStatement {
+                                query: query.clone(),
+                                expect_affected: Some(*expect_affected),
+                            },
+                        })
+                        .collect(),
+                ),
             }))
         } else {
             Ok(None)
@@ -294,6 +1293,8 @@ enum ParseUrlError {
     #[error("")]
     SetupUrlEndsWithSlash,
     #[error("")]
+    BaseUrlNotAbsolute,
+    #[error("")]
     PathUrlMissingSlash,
     #[error("Failed to parse URL: {0}")]
     ParseIntoUrlFailed(#[from] url::ParseError),
@@ -303,7 +1304,20 @@ fn parse_url(base_url: &str, path_url: &str, query: Option<&str>) -> Result<Url,
         return Err(ParseUrlError::SetupUrlEndsWithSlash);
     }
 
-    if !path_url.starts_with("/") {
+    // Without this, a base URL like `localhost:6969` parses with `localhost` as
+    // the scheme and fails confusingly once the path is appended.
+    if !Url::parse(base_url).is_ok_and(|url| url.has_host()) {
+        return Err(ParseUrlError::BaseUrlNotAbsolute);
+    }
+
+    // An absolute URL in a test is used as is, for tests against a host other
+    // than the app.
+    let is_absolute = ["http://", "https://", "ws://", "wss://"]
+        .iter()
+        .any(|scheme| path_url.starts_with(scheme));
+    let base_url = if is_absolute { "" } else { base_url };
+
+    if !is_absolute && !path_url.starts_with("/") {
         return Err(ParseUrlError::PathUrlMissingSlash);
     }
 
@@ -343,5 +1357,1474 @@ fn find_span(needle: &str, toml_src: &str) -> Option<SourceSpan> {
     let pattern = format!("\"{}\"", needle);
     toml_src
         .find(&pattern)
-        .map(|start| SourceSpan::new(start.into(), needle.len()))
+        .map(|start| SourceSpan::new((start + 1).into(), needle.len()))
+}
+
+/// Where the test `name` of `group` is defined, found by its `name = "…"`
+/// inside the group's `[[test_groups]]` table. Falls back to the first test
+/// with that name when the group can't be found, e.g. in an included file.
+fn find_test_location(
+    group: &str,
+    name: &str,
+    toml_src: &str,
+    file: &str,
+) -> Option<SourceLocation> {
+    let groups: Vec<usize> = toml_src
+        .match_indices("[[test_groups]]")
+        .map(|(start, _)| start)
+        .collect();
+    let section = groups
+        .iter()
+        .enumerate()
+        .find_map(|(idx, &start)| {
+            let end = groups.get(idx + 1).copied().unwrap_or(toml_src.len());
+            let header = &toml_src[start..end];
+            let header = &header[..header.find("[[test_groups.tests]]").unwrap_or(header.len())];
+            let group_name = find_name(group, header)?;
+            Some(start + group_name.end..end)
+        })
+        .unwrap_or(0..toml_src.len());
+
+    let span = find_name(name, &toml_src[section.clone()])?;
+    Some(location_at(
+        SourceSpan::new((section.start + span.start).into(), span.len()),
+        toml_src,
+        file.into(),
+    ))
+}
+
+/// The range of `value` in the first `name = "value"` in `toml_src`.
+fn find_name(value: &str, toml_src: &str) -> Option<std::ops::Range<usize>> {
+    let pattern = Regex::new(&format!(r#"\bname\s*=\s*"({})""#, regex::escape(value))).ok()?;
+
+    pattern.captures(toml_src)?.get(1).map(|m| m.range())
+}
+
+/// Where `key` is set in the test defined at `test`. Only the test's own
+/// table is searched, up to the next table header.
+fn find_key_location(key: &str, test: &SourceLocation, toml_src: &str) -> Option<SourceLocation> {
+    let table = &toml_src[test.span.offset()..];
+    let table = &table[..table.find("\n[").unwrap_or(table.len())];
+    let start = test.span.offset() + table.find(&format!("{key} "))?;
+
+    Some(location_at(
+        SourceSpan::new(start.into(), key.len()),
+        toml_src,
+        test.file.clone(),
+    ))
+}
+
+fn location_at(span: SourceSpan, toml_src: &str, file: Arc<str>) -> SourceLocation {
+    let line = toml_src[..span.offset()].matches('\n').count() + 1;
+
+    SourceLocation { file, span, line }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+    use std::time::Duration;
+
+    use reqwest::Version;
+
+    use crate::parser::NumberOrString;
+    use crate::parser::StringOrStrings;
+    use crate::parser::TestQuest;
+    use crate::validator::Assertion;
+    use crate::validator::BodySize;
+    use crate::validator::Comparison;
+    use crate::validator::ContentEncoding;
+    use crate::validator::DbReset;
+    use crate::validator::IR;
+    use crate::validator::ValidationError;
+    use crate::validator::Validator;
+    use crate::validator::WsMessage;
+
+    const SETUP: &str = r#"
+        [setup]
+        base_url = "http://localhost:6969"
+        command = "cargo"
+        ready_when = "/health"
+
+        [db]
+        db_type = "postgres"
+        migration_dir = "./migrations"
+
+        [global]
+    "#;
+
+    fn validate(tests: &str) -> Result<IR, ValidationError> {
+        let src = format!("{SETUP}\n{tests}");
+        let test_quest: TestQuest = toml::from_str(&src).unwrap();
+        Validator::new(&test_quest, &src, "test_quest.toml")
+            .validate()
+            .map(|(ir, _)| ir)
+    }
+
+    #[test]
+    fn assert_db_state_becomes_sql_assertion() {
+        let ir = validate(
+            r#"
+            [[test_groups]]
+            name = "users"
+
+            [[test_groups.tests]]
+            name = "GetUser"
+            method = "GET"
+            url = "/users/1"
+            assert_db_state = { query = "SELECT name FROM users WHERE id = 1;", expect = "Alice" }
+            "#,
+        )
+        .unwrap();
+
+        let assertions = &ir.tests[0].tests[0].assertions;
+        assert_eq!(assertions.len(), 1);
+        let Assertion::Sql {
+            query, expect, got, ..
+        } = &assertions[0]
+        else {
+            panic!("expected an SQL assertion, got {:?}", assertions[0]);
+        };
+        assert_eq!(query, "SELECT name FROM users WHERE id = 1;");
+        assert!(matches!(expect, StringOrStrings::Single(s) if s == "Alice"));
+        assert!(got.is_none());
+    }
+
+    #[test]
+    fn poll_sql_becomes_a_polled_sql_assertion() {
+        let ir = validate(
+            r#"
+            [[test_groups]]
+            name = "jobs"
+
+            [[test_groups.tests]]
+            name = "Enqueue"
+            method = "POST"
+            url = "/jobs"
+            poll_sql = { query = "SELECT state FROM jobs;", expect = "done", interval_ms = 50 }
+            "#,
+        )
+        .unwrap();
+
+        let assertion = &ir.tests[0].tests[0].assertions[0];
+        let Assertion::Sql {
+            poll: Some(poll), ..
+        } = assertion
+        else {
+            panic!("expected a polled SQL assertion, got {assertion:?}");
+        };
+        assert_eq!(poll.interval, Duration::from_millis(50));
+        assert_eq!(poll.timeout, Duration::from_millis(5000));
+        assert_eq!(assertion.config_key(), Some("poll_sql"));
+
+        let Err(error) = validate(
+            r#"
+            [[test_groups]]
+            name = "jobs"
+
+            [[test_groups.tests]]
+            name = "Enqueue"
+            method = "POST"
+            url = "/jobs"
+            poll_sql = { query = "SELECT state FROM jobs;", expect = "done", interval_ms = 0 }
+            "#,
+        ) else {
+            panic!("expected a zero interval to fail validation");
+        };
+        assert_eq!(error.field, "poll_sql");
+    }
+
+    #[test]
+    fn group_timeout_secs_has_to_be_positive() {
+        let group = |timeout_secs: u64| {
+            format!(
+                r#"
+                [[test_groups]]
+                name = "users"
+                timeout_secs = {timeout_secs}
+
+                [[test_groups.tests]]
+                name = "ListUsers"
+                method = "GET"
+                url = "/users"
+                assert_status = 200
+                "#
+            )
+        };
+
+        let ir = validate(&group(30)).unwrap();
+        assert_eq!(ir.tests[0].timeout, Some(Duration::from_secs(30)));
+
+        let Err(error) = validate(&group(0)) else {
+            panic!("expected a zero timeout to fail validation");
+        };
+        assert_eq!(error.field, "users - timeout_secs");
+    }
+
+    #[test]
+    fn assert_db_compare_parses_the_operator_and_value() {
+        let ir = validate(
+            r#"
+            [[test_groups]]
+            name = "users"
+
+            [[test_groups.tests]]
+            name = "CreateUser"
+            method = "POST"
+            url = "/users"
+            assert_db_compare = { query = "SELECT count(*) FROM users;", op = ">", value = 0 }
+
+            [[test_groups.tests]]
+            name = "DeleteUser"
+            method = "DELETE"
+            url = "/users/1"
+            assert_db_compare = { query = "SELECT state FROM users;", op = "!=", value = "active" }
+            "#,
+        )
+        .unwrap();
+
+        let tests = &ir.tests[0].tests;
+        assert!(matches!(
+            &tests[0].assertions[0],
+            Assertion::SqlCompare { op: Comparison::Gt, value: NumberOrString::Number(n), got: None, .. }
+                if *n == 0.0
+        ));
+        assert!(matches!(
+            &tests[1].assertions[0],
+            Assertion::SqlCompare { op: Comparison::Ne, value: NumberOrString::String(s), .. }
+                if s == "active"
+        ));
+
+        let Err(error) = validate(
+            r#"
+            [[test_groups]]
+            name = "users"
+
+            [[test_groups.tests]]
+            name = "CreateUser"
+            method = "POST"
+            url = "/users"
+            assert_db_compare = { query = "SELECT count(*) FROM users;", op = "=>", value = 0 }
+            "#,
+        ) else {
+            panic!("expected an unknown operator to fail validation");
+        };
+        assert_eq!(error.field, "assert_db_compare");
+    }
+
+    #[test]
+    fn timeout_and_retries_override_setup_defaults() {
+        let src = SETUP.replace(
+            "ready_when = \"/health\"",
+            "ready_when = \"/health\"\ndefault_timeout_ms = 1000\ndefault_retries = 2",
+        );
+        let tests = r#"
+            [[test_groups]]
+            name = "users"
+
+            [[test_groups.tests]]
+            name = "Defaults"
+            method = "GET"
+            url = "/users"
+
+            [[test_groups.tests]]
+            name = "Overrides"
+            method = "GET"
+            url = "/users"
+            timeout_ms = 50
+            retries = 0
+        "#;
+        let src = format!("{src}\n{tests}");
+        let test_quest: TestQuest = toml::from_str(&src).unwrap();
+        let (ir, _) = Validator::new(&test_quest, &src, "test_quest.toml")
+            .validate()
+            .unwrap();
+
+        let tests = &ir.tests[0].tests;
+        assert_eq!(tests[0].timeout, Some(Duration::from_millis(1000)));
+        assert_eq!(tests[0].retries, 2);
+        assert_eq!(tests[1].timeout, Some(Duration::from_millis(50)));
+        assert_eq!(tests[1].retries, 0);
+    }
+
+    #[test]
+    fn user_agent_and_default_headers_configure_the_client() {
+        let config = |options: &str| {
+            let setup = SETUP.replace(
+                "ready_when = \"/health\"",
+                &format!("ready_when = \"/health\"\n{options}"),
+            );
+            format!(
+                r#"{setup}
+            [[test_groups]]
+            name = "users"
+
+            [[test_groups.tests]]
+            name = "List"
+            method = "GET"
+            url = "/users"
+            "#
+            )
+        };
+
+        let src = config("user_agent = \"tq/1.0\"\ndefault_headers = { X-Api-Version = \"2\" }");
+        let test_quest: TestQuest = toml::from_str(&src).unwrap();
+        let (ir, _) = Validator::new(&test_quest, &src, "test_quest.toml")
+            .validate()
+            .unwrap();
+        assert_eq!(ir.client.user_agent.unwrap(), "tq/1.0");
+        assert_eq!(ir.client.default_headers["x-api-version"], "2");
+        // They stay on the client instead of being copied into every test.
+        assert!(!ir.tests[0].tests[0].headers.contains_key("x-api-version"));
+
+        let src = config("user_agent = \"tq\\n\"");
+        let test_quest: TestQuest = toml::from_str(&src).unwrap();
+        let Err(error) = Validator::new(&test_quest, &src, "test_quest.toml").validate() else {
+            panic!("expected a user agent with a newline to fail validation");
+        };
+        assert_eq!(error.field, "setup.user_agent");
+    }
+
+    #[test]
+    fn fail_on_server_error_checks_tests_without_a_status() {
+        let src = SETUP.replace(
+            "ready_when = \"/health\"",
+            "ready_when = \"/health\"\nfail_on_server_error = true",
+        );
+        let tests = r#"
+            [[test_groups]]
+            name = "users"
+
+            [[test_groups.tests]]
+            name = "Headers"
+            method = "GET"
+            url = "/users"
+            assert_headers = { Content-Type = "application/json" }
+
+            [[test_groups.tests]]
+            name = "Unavailable"
+            method = "GET"
+            url = "/users"
+            assert_status = 503
+        "#;
+        let src = format!("{src}\n{tests}");
+        let test_quest: TestQuest = toml::from_str(&src).unwrap();
+        let (ir, _) = Validator::new(&test_quest, &src, "test_quest.toml")
+            .validate()
+            .unwrap();
+
+        let tests = &ir.tests[0].tests;
+        assert!(matches!(
+            tests[0].assertions.last(),
+            Some(Assertion::NoServerError)
+        ));
+        assert!(
+            !tests[1]
+                .assertions
+                .iter()
+                .any(|a| matches!(a, Assertion::NoServerError))
+        );
+    }
+
+    #[test]
+    fn yaml_configs_validate_without_source_spans() {
+        let src = r#"
+setup:
+  base_url: http://localhost:6969
+  command: cargo
+  ready_when: /health
+db:
+  db_type: postgres
+  migration_dir: ./migrations
+global:
+  headers:
+    Authorization: api-key 1234
+test_groups:
+  - name: users
+    tests:
+      - name: GetUser
+        method: GET
+        url: /users/1
+        assert_headers:
+          Content-Type: application/json
+"#;
+        let test_quest: TestQuest = serde_yaml::from_str(src).unwrap();
+        let (ir, _) = Validator::new(&test_quest, src, "test_quest.yaml")
+            .validate()
+            .unwrap();
+
+        let test = &ir.tests[0].tests[0];
+        assert_eq!(test.headers["Authorization"], "api-key 1234");
+        assert!(matches!(test.assertions[0], Assertion::Headers(_)));
+        assert!(test.location.is_none());
+
+        let invalid = src.replace("method: GET", "method: NOT A METHOD");
+        let test_quest: TestQuest = serde_yaml::from_str(&invalid).unwrap();
+        let Err(error) = Validator::new(&test_quest, &invalid, "test_quest.yaml").validate() else {
+            panic!("expected an invalid method to fail validation");
+        };
+        assert!(error.span.is_none());
+    }
+
+    #[test]
+    fn db_url_has_to_match_db_type() {
+        let with_url =
+            |url: &str| SETUP.replace("[db]\n", &format!("[db]\n        url = \"{url}\"\n"));
+        let validate_setup = |src: &str| {
+            let src = format!("{src}\n[[test_groups]]\nname = \"users\"\ntests = []\n");
+            let test_quest: TestQuest = toml::from_str(&src).unwrap();
+            Validator::new(&test_quest, &src, "test_quest.toml")
+                .validate()
+                .map(|_| ())
+        };
+
+        assert!(validate_setup(&with_url("postgresql://app@localhost:5432/app")).is_ok());
+
+        let src = with_url("mysql://root@localhost:3306/app");
+        let Err(error) = validate_setup(&src) else {
+            panic!("expected a MySQL URL to fail with db_type = postgres");
+        };
+        assert_eq!(error.field, "db.url");
+        assert!(error.span.is_some());
+
+        let Err(error) = validate_setup(&with_url("sqlite://app.db")) else {
+            panic!("expected an unsupported scheme to fail validation");
+        };
+        assert_eq!(error.field, "db.url");
+    }
+
+    #[test]
+    fn reset_all_is_refused_for_an_external_database() {
+        let src = format!(
+            "{}\n[before_all]\nreset = true\n\n[[test_groups]]\nname = \"users\"\ntests = []\n",
+            SETUP.replace(
+                "[db]\n",
+                "[db]\n        url = \"postgres://app@localhost:5432/app\"\n"
+            )
+        );
+        let test_quest: TestQuest = toml::from_str(&src).unwrap();
+        let Err(error) = Validator::new(&test_quest, &src, "test_quest.toml").validate() else {
+            panic!("expected reset = true to fail with [db] url");
+        };
+        assert_eq!(error.field, "reset");
+
+        let src = src.replace("reset = true", "reset = [\"users\"]");
+        let test_quest: TestQuest = toml::from_str(&src).unwrap();
+        assert!(
+            Validator::new(&test_quest, &src, "test_quest.toml")
+                .validate()
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn base_url_without_a_scheme_is_rejected() {
+        let src = SETUP.replace("http://localhost:6969", "localhost:6969");
+        let tests = r#"
+            [[test_groups]]
+            name = "users"
+
+            [[test_groups.tests]]
+            name = "GetUser"
+            method = "GET"
+            url = "/users/1"
+        "#;
+        let src = format!("{src}\n{tests}");
+        let test_quest: TestQuest = toml::from_str(&src).unwrap();
+        let Err(error) = Validator::new(&test_quest, &src, "test_quest.toml").validate() else {
+            panic!("expected a base_url without a scheme to fail validation");
+        };
+
+        assert_eq!(error.field, "setup.base_url");
+        let span = error.span.unwrap();
+        assert!(src[span.offset()..].starts_with("localhost:6969\""));
+    }
+
+    #[test]
+    fn absolute_test_urls_skip_the_base_url() {
+        let ir = validate(
+            r#"
+            [[test_groups]]
+            name = "auth"
+
+            [[test_groups.tests]]
+            name = "Login"
+            method = "POST"
+            url = "/login"
+
+            [[test_groups.tests]]
+            name = "FetchToken"
+            method = "POST"
+            url = "https://auth.example.com/oauth/token"
+            "#,
+        )
+        .unwrap();
+
+        let tests = &ir.tests[0].tests;
+        assert_eq!(tests[0].url.as_str(), "http://localhost:6969/login");
+        assert_eq!(
+            tests[1].url.as_str(),
+            "https://auth.example.com/oauth/token"
+        );
+
+        let error = validate(
+            r#"
+            [[test_groups]]
+            name = "auth"
+
+            [[test_groups.tests]]
+            name = "Login"
+            method = "POST"
+            url = "login"
+            "#,
+        )
+        .err()
+        .unwrap();
+        assert_eq!(error.field, "Login/url");
+    }
+
+    #[test]
+    fn before_all_and_after_all_hooks_are_part_of_the_setup() {
+        let src = format!(
+            "{}\n{}",
+            SETUP.replace("[global]", ""),
+            r#"
+            [before_all]
+            run_sql = ["INSERT INTO countries (code) VALUES ('NO');"]
+
+            [after_all]
+            run_sql = [{ query = "DELETE FROM countries;", expect_affected = 1 }]
+
+            [global]
+
+            [[test_groups]]
+            name = "health"
+
+            [[test_groups.tests]]
+            name = "Health"
+            method = "GET"
+            url = "/health"
+            "#
+        );
+        let test_quest: TestQuest = toml::from_str(&src).unwrap();
+        let (_, setup) = Validator::new(&test_quest, &src, "test_quest.toml")
+            .validate()
+            .unwrap();
+
+        let before_all = setup.before_all.unwrap().sql.unwrap();
+        assert_eq!(
+            before_all[0].query,
+            "INSERT INTO countries (code) VALUES ('NO');"
+        );
+        assert_eq!(before_all[0].expect_affected, None);
+
+        let after_all = setup.after_all.unwrap().sql.unwrap();
+        assert_eq!(after_all[0].query, "DELETE FROM countries;");
+        assert_eq!(after_all[0].expect_affected, Some(1));
+    }
+
+    #[test]
+    fn reset_takes_a_bool_or_table_names() {
+        let ir = validate(
+            r#"
+            [[test_groups]]
+            name = "orders"
+
+            [test_groups.before_group]
+            reset = true
+
+            [test_groups.before_each_test]
+            reset = ["order_lines", "orders"]
+
+            [[test_groups.tests]]
+            name = "ListOrders"
+            method = "GET"
+            url = "/orders"
+
+            [test_groups.tests.before_run]
+            reset = false
+            "#,
+        )
+        .unwrap();
+
+        let group = &ir.tests[0];
+        assert_eq!(
+            group.before_group.as_ref().unwrap().reset_db,
+            Some(DbReset::All)
+        );
+        assert_eq!(
+            group.before_each_test.as_ref().unwrap().reset_db,
+            Some(DbReset::Tables(vec!["order_lines".into(), "orders".into()]))
+        );
+        assert_eq!(group.tests[0].before_run.as_ref().unwrap().reset_db, None);
+
+        let error = validate(
+            r#"
+            [[test_groups]]
+            name = "orders"
+
+            [test_groups.before_group]
+            reset = ["orders", ""]
+
+            [[test_groups.tests]]
+            name = "ListOrders"
+            method = "GET"
+            url = "/orders"
+            "#,
+        )
+        .err()
+        .unwrap();
+        assert_eq!(error.field, "reset");
+        assert!(error.span.is_some());
+    }
+
+    #[test]
+    fn assert_body_size_takes_an_exact_size_or_bounds() {
+        let ir = validate(
+            r#"
+            [[test_groups]]
+            name = "users"
+
+            [[test_groups.tests]]
+            name = "Exact"
+            method = "GET"
+            url = "/users/1"
+            assert_body_size = 42
+
+            [[test_groups.tests]]
+            name = "Bounded"
+            method = "GET"
+            url = "/users"
+            assert_body_size = { max = 4096 }
+            "#,
+        )
+        .unwrap();
+
+        let tests = &ir.tests[0].tests;
+        assert!(matches!(
+            tests[0].assertions[0],
+            Assertion::BodySize(BodySize::Exact(42))
+        ));
+        assert!(matches!(
+            tests[1].assertions[0],
+            Assertion::BodySize(BodySize::Range {
+                min: None,
+                max: Some(4096)
+            })
+        ));
+
+        let Err(error) = validate(
+            r#"
+            [[test_groups]]
+            name = "users"
+
+            [[test_groups.tests]]
+            name = "Backwards"
+            method = "GET"
+            url = "/users"
+            assert_body_size = { min = 10, max = 1 }
+            "#,
+        ) else {
+            panic!("expected min larger than max to fail validation");
+        };
+        assert_eq!(error.field, "assert_body_size");
+    }
+
+    #[test]
+    fn assert_header_int_parses_the_comparison() {
+        let ir = validate(
+            r#"
+            [[test_groups]]
+            name = "users"
+
+            [[test_groups.tests]]
+            name = "RateLimited"
+            method = "GET"
+            url = "/users"
+            assert_header_int = { name = "X-RateLimit-Remaining", op = ">=", value = 1 }
+            "#,
+        )
+        .unwrap();
+        assert!(matches!(
+            &ir.tests[0].tests[0].assertions[0],
+            Assertion::HeaderInt { name, op: Comparison::Ge, value: 1 }
+                if name == "x-ratelimit-remaining"
+        ));
+
+        let Err(error) = validate(
+            r#"
+            [[test_groups]]
+            name = "users"
+
+            [[test_groups.tests]]
+            name = "RateLimited"
+            method = "GET"
+            url = "/users"
+            assert_header_int = { name = "X-RateLimit-Remaining", op = "=>", value = 1 }
+            "#,
+        ) else {
+            panic!("expected an unknown comparison to fail validation");
+        };
+        assert_eq!(error.field, "assert_header_int");
+    }
+
+    #[test]
+    fn body_file_is_read_relative_to_the_config() {
+        let dir = std::env::temp_dir().join(format!("tq-body-file-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("bodies")).unwrap();
+        std::fs::write(
+            dir.join("bodies/user.yaml"),
+            "name: Alice\nroles: [admin]\n",
+        )
+        .unwrap();
+        std::fs::write(dir.join("bodies/broken.json"), r#"{"name": "#).unwrap();
+        let config = dir.join("test_quest.toml").to_string_lossy().into_owned();
+
+        let validate = |body_file: &str| {
+            let src = format!(
+                r#"{SETUP}
+                [[test_groups]]
+                name = "users"
+
+                [[test_groups.tests]]
+                name = "CreateUser"
+                method = "POST"
+                url = "/users"
+                body_file = "{body_file}"
+                assert_status = 201
+                "#
+            );
+            let test_quest: TestQuest = toml::from_str(&src).unwrap();
+            Validator::new(&test_quest, &src, &config)
+                .validate()
+                .map(|(ir, _)| ir)
+        };
+
+        let ir = validate("bodies/user.yaml").unwrap();
+        assert_eq!(
+            ir.tests[0].tests[0].body.as_deref(),
+            Some(&serde_json::json!({ "name": "Alice", "roles": ["admin"] }))
+        );
+
+        for body_file in ["bodies/broken.json", "bodies/missing.json"] {
+            let Err(error) = validate(body_file) else {
+                panic!("expected {body_file} to fail validation");
+            };
+            assert_eq!(error.field, "CreateUser - body_file");
+            assert!(error.span.is_some());
+        }
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn snapshots_are_relative_to_the_config() {
+        let src = format!(
+            r#"{SETUP}
+            [[test_groups]]
+            name = "users"
+
+            [[test_groups.tests]]
+            name = "ListUsers"
+            method = "GET"
+            url = "/users"
+            assert_body_snapshot = "snapshots/users.json"
+            "#
+        );
+        let test_quest: TestQuest = toml::from_str(&src).unwrap();
+        let (ir, _) = Validator::new(&test_quest, &src, "tests/api/test_quest.toml")
+            .validate()
+            .unwrap();
+
+        let Assertion::Snapshot { path, .. } = &ir.tests[0].tests[0].assertions[0] else {
+            panic!("expected a snapshot assertion");
+        };
+        assert_eq!(path, Path::new("tests/api/snapshots/users.json"));
+    }
+
+    #[test]
+    fn websocket_tests_connect_with_a_ws_url() {
+        let ir = validate(
+            r#"
+            [[test_groups]]
+            name = "realtime"
+
+            [[test_groups.tests]]
+            name = "Echo"
+            method = "GET"
+            url = "/echo"
+            protocol = "ws"
+            ws_send = "ping"
+            assert_ws_message = { contains = "pong" }
+            "#,
+        )
+        .unwrap();
+        let test = &ir.tests[0].tests[0];
+        assert_eq!(test.url.scheme(), "ws");
+        assert_eq!(test.ws.as_ref().unwrap().send.as_deref(), Some("ping"));
+        assert!(matches!(
+            &test.assertions[0],
+            Assertion::WsMessage(WsMessage::Contains(expected)) if expected == "pong"
+        ));
+
+        let Err(error) = validate(
+            r#"
+            [[test_groups]]
+            name = "realtime"
+
+            [[test_groups.tests]]
+            name = "Echo"
+            method = "GET"
+            url = "/echo"
+            ws_send = "ping"
+            "#,
+        ) else {
+            panic!("expected ws_send without protocol = \"ws\" to fail validation");
+        };
+        assert_eq!(error.field, "Echo - ws_send");
+
+        let Err(error) = validate(
+            r#"
+            [[test_groups]]
+            name = "realtime"
+
+            [[test_groups.tests]]
+            name = "Echo"
+            method = "POST"
+            url = "/echo"
+            protocol = "ws"
+            "#,
+        ) else {
+            panic!("expected a POST WebSocket test to fail validation");
+        };
+        assert_eq!(error.field, "Echo - method");
+    }
+
+    #[test]
+    fn assert_request_error_stands_alone() {
+        let ir = validate(
+            r#"
+            [[test_groups]]
+            name = "network"
+
+            [[test_groups.tests]]
+            name = "Unreachable"
+            method = "GET"
+            url = "http://localhost:1/"
+            assert_request_error = { regex = "(?i)connection refused" }
+            "#,
+        )
+        .unwrap();
+        let Assertion::RequestError(expected) = &ir.tests[0].tests[0].assertions[0] else {
+            panic!("expected a request error assertion");
+        };
+        assert!(expected.matches("tcp connect error: Connection refused"));
+
+        let Err(error) = validate(
+            r#"
+            [[test_groups]]
+            name = "network"
+
+            [[test_groups.tests]]
+            name = "Unreachable"
+            method = "GET"
+            url = "http://localhost:1/"
+            assert_status = 200
+            assert_request_error = "Connection refused"
+            "#,
+        ) else {
+            panic!("expected assert_request_error next to assert_status to fail validation");
+        };
+        assert_eq!(error.field, "assert_request_error");
+    }
+
+    #[test]
+    fn assert_json_any_of_needs_an_alternative() {
+        let ir = validate(
+            r#"
+            [[test_groups]]
+            name = "payments"
+
+            [[test_groups.tests]]
+            name = "GetPayment"
+            method = "GET"
+            url = "/payments/1"
+            assert_json_any_of = [{ type = "card" }, { type = "bank" }]
+            "#,
+        )
+        .unwrap();
+        assert!(matches!(
+            &ir.tests[0].tests[0].assertions[0],
+            Assertion::JsonAnyOf(alternatives) if alternatives.len() == 2
+        ));
+
+        let Err(error) = validate(
+            r#"
+            [[test_groups]]
+            name = "payments"
+
+            [[test_groups.tests]]
+            name = "GetPayment"
+            method = "GET"
+            url = "/payments/1"
+            assert_json_any_of = []
+            "#,
+        ) else {
+            panic!("expected an empty assert_json_any_of to fail validation");
+        };
+        assert_eq!(error.field, "assert_json_any_of");
+    }
+
+    #[test]
+    fn ignore_keys_needs_assert_json_and_valid_paths() {
+        let ir = validate(
+            r#"
+            [[test_groups]]
+            name = "users"
+
+            [[test_groups.tests]]
+            name = "GetUser"
+            method = "GET"
+            url = "/users/1"
+            assert_json = { name = "Alice" }
+            ignore_keys = ["createdAt", "profile.updatedAt"]
+            "#,
+        )
+        .unwrap();
+        let Assertion::Json { ignore_keys, .. } = &ir.tests[0].tests[0].assertions[0] else {
+            panic!("expected a JSON assertion");
+        };
+        assert_eq!(ignore_keys, &["createdAt", "profile.updatedAt"]);
+
+        for (ignore_keys, assert_json) in [
+            (
+                r#"["profile..updatedAt"]"#,
+                r#"assert_json = { name = "Alice" }"#,
+            ),
+            (r#"["createdAt"]"#, ""),
+        ] {
+            let Err(error) = validate(&format!(
+                r#"
+                [[test_groups]]
+                name = "users"
+
+                [[test_groups.tests]]
+                name = "GetUser"
+                method = "GET"
+                url = "/users/1"
+                {assert_json}
+                ignore_keys = {ignore_keys}
+                "#,
+            )) else {
+                panic!("expected ignore_keys = {ignore_keys} to fail validation");
+            };
+            assert_eq!(error.field, "ignore_keys");
+        }
+    }
+
+    #[test]
+    fn assert_http_version_takes_a_known_version() {
+        let ir = validate(
+            r#"
+            [[test_groups]]
+            name = "protocol"
+
+            [[test_groups.tests]]
+            name = "Negotiated"
+            method = "GET"
+            url = "/health"
+            assert_http_version = "HTTP/2"
+            "#,
+        )
+        .unwrap();
+        assert!(matches!(
+            ir.tests[0].tests[0].assertions[0],
+            Assertion::HttpVersion(Version::HTTP_2)
+        ));
+
+        let Err(error) = validate(
+            r#"
+            [[test_groups]]
+            name = "protocol"
+
+            [[test_groups.tests]]
+            name = "Negotiated"
+            method = "GET"
+            url = "/health"
+            assert_http_version = "HTTP/4"
+            "#,
+        ) else {
+            panic!("expected an unknown HTTP version to fail validation");
+        };
+        assert_eq!(error.field, "assert_http_version");
+    }
+
+    #[test]
+    fn assert_json_has_parses_every_path() {
+        let ir = validate(
+            r#"
+            [[test_groups]]
+            name = "users"
+
+            [[test_groups.tests]]
+            name = "CreateUser"
+            method = "POST"
+            url = "/users"
+            assert_json_has = ["$.id", "$.links.self"]
+            "#,
+        )
+        .unwrap();
+        assert!(matches!(
+            &ir.tests[0].tests[0].assertions[0],
+            Assertion::JsonHas(paths) if paths.len() == 2
+        ));
+
+        for paths in ["[]", r#"["$.id", "id"]"#] {
+            let Err(error) = validate(&format!(
+                r#"
+                [[test_groups]]
+                name = "users"
+
+                [[test_groups.tests]]
+                name = "CreateUser"
+                method = "POST"
+                url = "/users"
+                assert_json_has = {paths}
+                "#,
+            )) else {
+                panic!("expected assert_json_has = {paths} to fail validation");
+            };
+            assert_eq!(error.field, "assert_json_has");
+        }
+    }
+
+    #[test]
+    fn assert_json_relation_parses_both_sides() {
+        let ir = validate(
+            r#"
+            [[test_groups]]
+            name = "orders"
+
+            [[test_groups.tests]]
+            name = "Totals"
+            method = "GET"
+            url = "/orders/1"
+            assert_json_relation = { left = "$.total", op = "==", right = "$.items.length()" }
+            "#,
+        )
+        .unwrap();
+        let Assertion::JsonRelation { left, op, right } = &ir.tests[0].tests[0].assertions[0]
+        else {
+            panic!("expected a JSON relation");
+        };
+        assert!(!left.length);
+        assert!(right.length);
+        assert_eq!(*op, Comparison::Eq);
+        assert_eq!(right.to_string(), "$.items.length()");
+
+        let Err(error) = validate(
+            r#"
+            [[test_groups]]
+            name = "orders"
+
+            [[test_groups.tests]]
+            name = "Totals"
+            method = "GET"
+            url = "/orders/1"
+            assert_json_relation = { left = "total", op = "==", right = "$.count" }
+            "#,
+        ) else {
+            panic!("expected a path without `$` to fail validation");
+        };
+        assert_eq!(error.field, "assert_json_relation");
+    }
+
+    #[test]
+    fn assert_content_encoding_asks_for_the_encoding() {
+        let ir = validate(
+            r#"
+            [[test_groups]]
+            name = "users"
+
+            [[test_groups.tests]]
+            name = "Compressed"
+            method = "GET"
+            url = "/users"
+            assert_content_encoding = { encoding = "GZIP", verify_body = true }
+
+            [[test_groups.tests]]
+            name = "OwnAcceptEncoding"
+            method = "GET"
+            url = "/users"
+            headers = { Accept-Encoding = "gzip, deflate" }
+            assert_content_encoding = "deflate"
+            "#,
+        )
+        .unwrap();
+
+        let tests = &ir.tests[0].tests;
+        assert!(matches!(
+            tests[0].assertions[0],
+            Assertion::ContentEncoding {
+                encoding: ContentEncoding::Gzip,
+                verify_body: true
+            }
+        ));
+        assert_eq!(tests[0].headers["accept-encoding"], "gzip");
+        assert_eq!(tests[1].headers["accept-encoding"], "gzip, deflate");
+
+        let Err(error) = validate(
+            r#"
+            [[test_groups]]
+            name = "users"
+
+            [[test_groups.tests]]
+            name = "Compressed"
+            method = "GET"
+            url = "/users"
+            assert_content_encoding = "zstd"
+            "#,
+        ) else {
+            panic!("expected an unsupported encoding to fail validation");
+        };
+        assert_eq!(error.field, "assert_content_encoding");
+    }
+
+    #[test]
+    fn require_assertions_rejects_tests_without_assertions() {
+        let tests = r#"
+            [[test_groups]]
+            name = "users"
+
+            [[test_groups.tests]]
+            name = "Unchecked"
+            method = "GET"
+            url = "/users"
+            "#;
+        assert!(validate(tests).is_ok());
+
+        let src = format!(
+            "{}\n{tests}",
+            SETUP.replace(
+                "ready_when = \"/health\"",
+                "ready_when = \"/health\"\nrequire_assertions = true"
+            )
+        );
+        let test_quest: TestQuest = toml::from_str(&src).unwrap();
+        let Err(error) = Validator::new(&test_quest, &src, "test_quest.toml").validate() else {
+            panic!("expected a test without assertions to fail validation");
+        };
+        assert_eq!(error.field, "Unchecked - name");
+    }
+
+    #[test]
+    fn depends_on_has_to_name_an_earlier_test() {
+        let ir = validate(
+            r#"
+            [[test_groups]]
+            name = "users"
+
+            [[test_groups.tests]]
+            name = "Login"
+            method = "POST"
+            url = "/login"
+
+            [[test_groups.tests]]
+            name = "Profile"
+            method = "GET"
+            url = "/me"
+            depends_on = ["Login"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(ir.tests[0].tests[1].depends_on, ["Login"]);
+
+        let Err(error) = validate(
+            r#"
+            [[test_groups]]
+            name = "users"
+
+            [[test_groups.tests]]
+            name = "Profile"
+            method = "GET"
+            url = "/me"
+            depends_on = ["Login"]
+
+            [[test_groups.tests]]
+            name = "Login"
+            method = "POST"
+            url = "/login"
+            "#,
+        ) else {
+            panic!("expected a dependency defined later to fail validation");
+        };
+        assert_eq!(error.field, "Profile - depends_on");
+    }
+
+    #[test]
+    fn tests_record_the_line_they_are_defined_on() {
+        let src = format!(
+            "{SETUP}\n{}",
+            r#"
+[[test_groups]]
+name = "users"
+
+[[test_groups.tests]]
+name = "GetUser"
+method = "GET"
+url = "/users/1"
+assert_status = 200
+"#
+        );
+        let test_quest: TestQuest = toml::from_str(&src).unwrap();
+        let (ir, _) = Validator::new(&test_quest, &src, "test_quest.toml")
+            .validate()
+            .unwrap();
+
+        let test = &ir.tests[0].tests[0];
+        let location = test.location.as_ref().unwrap();
+        let expected_line = src.lines().position(|l| l == "name = \"GetUser\"").unwrap() + 1;
+        assert_eq!(location.line, expected_line);
+        assert_eq!(&*location.file, "test_quest.toml");
+
+        let status = test.assertion_locations[0].as_ref().unwrap();
+        let expected_line = src
+            .lines()
+            .position(|l| l == "assert_status = 200")
+            .unwrap()
+            + 1;
+        assert_eq!(status.line, expected_line);
+    }
+
+    #[test]
+    fn tests_are_located_within_their_group() {
+        let src = format!(
+            "{SETUP}\n{}",
+            r#"
+[[test_groups]]
+name = "Health"
+
+[[test_groups.tests]]
+name = "Health"
+method = "GET"
+url = "/health"
+
+[[test_groups.tests]]
+name = "List"
+method = "GET"
+url = "/health/checks"
+
+[[test_groups]]
+name = "users"
+
+[[test_groups.tests]]
+name = "List"
+method = "GET"
+url = "/users"
+"#
+        );
+        let test_quest: TestQuest = toml::from_str(&src).unwrap();
+        let (ir, _) = Validator::new(&test_quest, &src, "test_quest.toml")
+            .validate()
+            .unwrap();
+
+        let line =
+            |group: usize, test: usize| ir.tests[group].tests[test].location.as_ref().unwrap().line;
+        let lines: Vec<usize> = src
+            .lines()
+            .enumerate()
+            .filter(|(_, l)| *l == "name = \"Health\"" || *l == "name = \"List\"")
+            .map(|(idx, _)| idx + 1)
+            .collect();
+        assert_eq!(line(0, 0), lines[1]);
+        assert_eq!(line(0, 1), lines[2]);
+        assert_eq!(line(1, 0), lines[3]);
+    }
+
+    #[test]
+    fn migration_dirs_run_after_migration_dir() {
+        let src = SETUP.replace(
+            r#"migration_dir = "./migrations""#,
+            r#"migration_dir = "./schema"
+        migration_dirs = ["./seed", "./fixtures"]"#,
+        );
+        let tests = r#"
+            [[test_groups]]
+            name = "health"
+
+            [[test_groups.tests]]
+            name = "Health"
+            method = "GET"
+            url = "/health"
+        "#;
+        let src = format!("{src}\n{tests}");
+        let test_quest: TestQuest = toml::from_str(&src).unwrap();
+        let (_, setup) = Validator::new(&test_quest, &src, "test_quest.toml")
+            .validate()
+            .unwrap();
+
+        assert_eq!(setup.migration_dirs, ["./schema", "./seed", "./fixtures"]);
+    }
+
+    #[test]
+    fn run_sql_accepts_strings_and_expect_affected() {
+        let ir = validate(
+            r#"
+            [[test_groups]]
+            name = "users"
+
+            [[test_groups.tests]]
+            name = "DeleteUser"
+            method = "DELETE"
+            url = "/users/1"
+            before_run = { run_sql = [
+                "INSERT INTO users (id) VALUES (1);",
+                { query = "DELETE FROM users WHERE id = 2;", expect_affected = 1 },
+            ] }
+            "#,
+        )
+        .unwrap();
+
+        let before_run = ir.tests[0].tests[0].before_run.as_ref().unwrap();
+        let sql = before_run.sql.as_ref().unwrap();
+        assert_eq!(sql[0].query, "INSERT INTO users (id) VALUES (1);");
+        assert_eq!(sql[0].expect_affected, None);
+        assert_eq!(sql[1].query, "DELETE FROM users WHERE id = 2;");
+        assert_eq!(sql[1].expect_affected, Some(1));
+    }
+
+    #[test]
+    fn assert_headers_accepts_matching_modes() {
+        let ir = validate(
+            r#"
+            [[test_groups]]
+            name = "users"
+
+            [[test_groups.tests]]
+            name = "GetUser"
+            method = "GET"
+            url = "/users/1"
+            assert_headers = { Content-Type = { contains = "JSON", ignore_case = true }, Cache-Control = { regex = "max-age=\\d+" }, X-Id = "1" }
+            "#,
+        )
+        .unwrap();
+
+        let Assertion::Headers(headers) = &ir.tests[0].tests[0].assertions[0] else {
+            panic!("expected a header assertion");
+        };
+        let header = |name: &str| headers.iter().find(|h| h.name == name).unwrap();
+        assert!(header("content-type").matches("application/json; charset=utf-8"));
+        assert!(header("cache-control").matches("public, max-age=60"));
+        assert!(!header("cache-control").matches("no-store"));
+        assert!(header("x-id").matches("1"));
+        assert!(!header("x-id").matches("12"));
+
+        let invalid = validate(
+            r#"
+            [[test_groups]]
+            name = "users"
+
+            [[test_groups.tests]]
+            name = "GetUser"
+            method = "GET"
+            url = "/users/1"
+            assert_headers = { Content-Type = { starts_with = "application" } }
+            "#,
+        );
+        assert!(invalid.is_err());
+    }
+
+    #[test]
+    fn assert_json_equals_var_needs_an_earlier_capture() {
+        let config = |first: &str, second: &str| {
+            format!(
+                r#"
+            [[test_groups]]
+            name = "users"
+
+            [[test_groups.tests]]
+            name = "First"
+            method = "POST"
+            url = "/users"
+            {first}
+
+            [[test_groups.tests]]
+            name = "Second"
+            method = "GET"
+            url = "/users/1"
+            {second}
+            "#
+            )
+        };
+
+        let ir = validate(&config(
+            r#"capture_json = "user""#,
+            r#"assert_json_equals_var = "user""#,
+        ))
+        .unwrap();
+        let tests = &ir.tests[0].tests;
+        assert_eq!(tests[0].capture_json.as_deref(), Some("user"));
+        assert!(matches!(
+            &tests[1].assertions[0],
+            Assertion::JsonEqualsVar { var, captured: None } if var == "user"
+        ));
+        assert!(tests[0].uses_captures() && tests[1].uses_captures());
+
+        let Err(error) = validate(&config(
+            r#"assert_json_equals_var = "user""#,
+            r#"capture_json = "user""#,
+        )) else {
+            panic!("expected a var captured later to fail validation");
+        };
+        assert_eq!(error.field, "First - assert_json_equals_var");
+
+        let Err(error) = validate(&config(
+            "capture_json = \"user\"\n            capture_to_file = true",
+            "",
+        )) else {
+            panic!("expected capture_json with capture_to_file to fail validation");
+        };
+        assert_eq!(error.field, "First - capture_json");
+    }
+
+    #[test]
+    fn assert_trailers_reads_the_body() {
+        let ir = validate(
+            r#"
+            [[test_groups]]
+            name = "grpc"
+
+            [[test_groups.tests]]
+            name = "Call"
+            method = "POST"
+            url = "/rpc"
+            assert_trailers = { Grpc-Status = "0", Grpc-Message = { contains = "ok" } }
+            "#,
+        )
+        .unwrap();
+
+        let assertion = &ir.tests[0].tests[0].assertions[0];
+        let Assertion::Trailers(trailers) = assertion else {
+            panic!("expected a trailer assertion");
+        };
+        assert_eq!(trailers.len(), 2);
+        assert!(assertion.needs_body());
+
+        let Err(error) = validate(
+            r#"
+            [[test_groups]]
+            name = "grpc"
+
+            [[test_groups.tests]]
+            name = "Call"
+            method = "POST"
+            url = "/rpc"
+            capture_to_file = true
+            assert_trailers = { Grpc-Status = "0" }
+            "#,
+        ) else {
+            panic!("expected trailers with capture_to_file to fail validation");
+        };
+        assert_eq!(error.field, "capture_to_file");
+    }
 }
@@ -0,0 +1,113 @@
+use std::fmt;
+
+use serde_json::Value;
+
+/// A path into a JSON document, written as a subset of JSONPath: `$` followed
+/// by `.key`, `["key"]` and `[index]` steps, e.g. `$.data.items[0].tags`.
+#[derive(Debug, Clone)]
+pub struct JsonPath {
+    source: String,
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+impl JsonPath {
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let rest = source
+            .strip_prefix('$')
+            .ok_or_else(|| format!("JSON path `{source}` has to start with `$`"))?;
+        let mut chars = rest.chars().peekable();
+        let mut segments = vec![];
+
+        while let Some(c) = chars.next() {
+            match c {
+                '.' => {
+                    let mut key = String::new();
+                    while let Some(c) = chars.next_if(|c| *c != '.' && *c != '[') {
+                        key.push(c);
+                    }
+                    if key.is_empty() {
+                        return Err(format!("JSON path `{source}` has an empty key"));
+                    }
+                    segments.push(Segment::Key(key));
+                }
+                '[' => {
+                    let mut inner = String::new();
+                    for c in chars.by_ref() {
+                        if c == ']' {
+                            break;
+                        }
+                        inner.push(c);
+                    }
+                    let segment = if let Some(key) = inner
+                        .strip_prefix('"')
+                        .and_then(|key| key.strip_suffix('"'))
+                    {
+                        Segment::Key(key.to_string())
+                    } else {
+                        let index = inner.parse().map_err(|_| {
+                            format!("JSON path `{source}` has an invalid index `[{inner}]`")
+                        })?;
+                        Segment::Index(index)
+                    };
+                    segments.push(segment);
+                }
+                c => return Err(format!("JSON path `{source}` has an unexpected `{c}`")),
+            }
+        }
+
+        Ok(Self {
+            source: source.to_string(),
+            segments,
+        })
+    }
+
+    /// The value at this path in `value`, if every step exists.
+    pub fn resolve<'a>(&self, value: &'a Value) -> Option<&'a Value> {
+        self.segments
+            .iter()
+            .try_fold(value, |value, segment| match segment {
+                Segment::Key(key) => value.get(key),
+                Segment::Index(index) => value.get(index),
+            })
+    }
+}
+
+impl fmt::Display for JsonPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use crate::validator::json_path::JsonPath;
+
+    #[test]
+    fn resolves_keys_and_indexes() {
+        let value = json!({ "data": { "items": [{ "tags": ["a", "b"] }], "odd key": 1 } });
+
+        let path = JsonPath::parse("$.data.items[0].tags").unwrap();
+        assert_eq!(path.resolve(&value), Some(&json!(["a", "b"])));
+
+        let path = JsonPath::parse(r#"$.data["odd key"]"#).unwrap();
+        assert_eq!(path.resolve(&value), Some(&json!(1)));
+
+        let path = JsonPath::parse("$").unwrap();
+        assert_eq!(path.resolve(&value), Some(&value));
+
+        let path = JsonPath::parse("$.data.missing").unwrap();
+        assert_eq!(path.resolve(&value), None);
+
+        assert!(JsonPath::parse("data.items").is_err());
+        assert!(JsonPath::parse("$.items[x]").is_err());
+        assert!(JsonPath::parse("$..items").is_err());
+    }
+}
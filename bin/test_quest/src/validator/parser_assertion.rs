@@ -1,13 +1,45 @@
+use std::path::Path;
+use std::time::Duration;
+
+use cookie::SameSite;
 use miette::NamedSource;
 use miette::SourceSpan;
+use regex::Regex;
+use regex::RegexBuilder;
+use reqwest::Version;
 use reqwest::header::HeaderMap;
 use reqwest::header::HeaderName;
 use reqwest::header::HeaderValue;
 use toml::Value;
 
-use crate::parser::AssertSql;
+use crate::parser::AssertBodySize;
+use crate::parser::AssertContentEncoding;
+use crate::parser::AssertCookie;
+use crate::parser::AssertHeaderInt;
+use crate::parser::AssertJsonArrayLen;
+use crate::parser::AssertJsonRelation;
+use crate::parser::AssertRequestError;
+use crate::parser::AssertWsMessage;
+use crate::parser::Test;
 use crate::validator::Assertion;
+use crate::validator::BodySize;
+use crate::validator::Comparison;
+use crate::validator::ContentEncoding;
+use crate::validator::ExpectedCookie;
+use crate::validator::ExpectedError;
+use crate::validator::HeaderAssertion;
+use crate::validator::HeaderMatch;
+use crate::validator::JsonOperand;
+use crate::validator::JsonPath;
+use crate::validator::SqlPoll;
 use crate::validator::ValidationError;
+use crate::validator::WsMessage;
+
+/// How often a `poll_sql` query is re-run when it sets no `interval_ms`.
+const DEFAULT_POLL_INTERVAL_MS: u64 = 100;
+
+/// How long a `poll_sql` query is re-run when it sets no `timeout_ms`.
+const DEFAULT_POLL_TIMEOUT_MS: u64 = 5000;
 
 /// Helper function to find the span of a key in the source contents.
 fn find_key_span(src: Option<&(String, String)>, key: &str) -> Option<SourceSpan> {
@@ -99,37 +131,570 @@ pub fn parse_header_map(
     Ok(header_map)
 }
 
+/// Parses a single `assert_headers` entry. The value is either a string, which
+/// has to match exactly, or a table with one of `exact`, `contains` or `regex`
+/// and an optional `ignore_case`, e.g.
+/// `Content-Type = { contains = "application/json" }`.
+fn parse_header_assertion(
+    key: &str,
+    value: &Value,
+    src: Option<&(String, String)>,
+) -> Result<HeaderAssertion, ValidationError> {
+    let invalid = |message: String| validation_err!(src, key, message, find_key_span(src, key));
+
+    let name = HeaderName::from_bytes(key.as_bytes())
+        .map_err(|e| invalid(format!("Invalid header name `{key}`: {e}")))?;
+
+    let (mode, expected, ignore_case) = match value {
+        Value::String(s) => ("exact", s.clone(), false),
+        Value::Table(table) => {
+            let mut modes = table
+                .iter()
+                .filter(|(k, _)| matches!(k.as_str(), "exact" | "contains" | "regex"));
+            let (Some((mode, expected)), None) = (modes.next(), modes.next()) else {
+                return Err(invalid(format!(
+                    "Header `{key}` needs exactly one of `exact`, `contains` or `regex`"
+                )));
+            };
+            if let Some(unknown) = table
+                .keys()
+                .find(|k| !matches!(k.as_str(), "exact" | "contains" | "regex" | "ignore_case"))
+            {
+                return Err(invalid(format!(
+                    "Unknown key `{unknown}` in header `{key}`, expected `exact`, `contains`, `regex` or `ignore_case`"
+                )));
+            }
+            let expected = expected.as_str().ok_or_else(|| {
+                invalid(format!("Header value must be a string, got {expected:?}"))
+            })?;
+            let ignore_case = match table.get("ignore_case") {
+                None => false,
+                Some(Value::Boolean(b)) => *b,
+                Some(other) => {
+                    return Err(invalid(format!(
+                        "`ignore_case` must be a boolean, got {other:?}"
+                    )));
+                }
+            };
+            (mode.as_str(), expected.to_string(), ignore_case)
+        }
+        _ => {
+            return Err(invalid(format!(
+                "Header value must be a string or a table, got {value:?}"
+            )));
+        }
+    };
+
+    let mode = match mode {
+        "regex" => {
+            let regex = RegexBuilder::new(&expected)
+                .case_insensitive(ignore_case)
+                .build()
+                .map_err(|e| {
+                    validation_err!(
+                        src,
+                        key,
+                        format!("Invalid regex for `{key}`: {e}"),
+                        find_value_span(src, &expected)
+                    )
+                })?;
+            HeaderMatch::Regex(regex)
+        }
+        "contains" => HeaderMatch::Contains,
+        _ => HeaderMatch::Exact,
+    };
+
+    Ok(HeaderAssertion {
+        name,
+        value: expected,
+        mode,
+        ignore_case,
+    })
+}
+
+/// Parses the `assert_headers` or `assert_trailers` table, named by `field`,
+/// into one assertion per header.
+pub fn parse_header_assertions(
+    field: &str,
+    value: &Value,
+    src: Option<&(String, String)>,
+) -> Result<Vec<HeaderAssertion>, ValidationError> {
+    let map = value.as_table().ok_or_else(|| {
+        validation_err!(
+            src,
+            field,
+            format!("Expected a table for {field}, got {value:?}"),
+            None
+        )
+    })?;
+
+    map.iter()
+        .map(|(k, v)| parse_header_assertion(k, v, src))
+        .collect()
+}
+
+/// Parses `assert_header_int`, checking the header name and the comparison.
+fn parse_header_int(
+    header: &AssertHeaderInt,
+    src: Option<&(String, String)>,
+) -> Result<Assertion, ValidationError> {
+    let name = HeaderName::from_bytes(header.name.as_bytes()).map_err(|e| {
+        validation_err!(
+            src,
+            "assert_header_int",
+            format!("Invalid header name `{}`: {e}", header.name),
+            find_value_span(src, &header.name)
+        )
+    })?;
+    let op = header.op.parse::<Comparison>().map_err(|message| {
+        validation_err!(
+            src,
+            "assert_header_int",
+            message,
+            find_value_span(src, &header.op)
+        )
+    })?;
+
+    Ok(Assertion::HeaderInt {
+        name,
+        op,
+        value: header.value,
+    })
+}
+
+/// Parses `assert_http_version`, written the way `Version` prints, e.g.
+/// `HTTP/1.1` or `HTTP/2.0`. The `.0` of HTTP/2 and HTTP/3 can be left out.
+fn parse_http_version(version: &str) -> Result<Version, String> {
+    match version.to_ascii_uppercase().as_str() {
+        "HTTP/0.9" => Ok(Version::HTTP_09),
+        "HTTP/1.0" => Ok(Version::HTTP_10),
+        "HTTP/1.1" => Ok(Version::HTTP_11),
+        "HTTP/2" | "HTTP/2.0" => Ok(Version::HTTP_2),
+        "HTTP/3" | "HTTP/3.0" => Ok(Version::HTTP_3),
+        _ => Err(format!(
+            "Expected one of `HTTP/0.9`, `HTTP/1.0`, `HTTP/1.1`, `HTTP/2.0` or `HTTP/3.0`, got `{version}`"
+        )),
+    }
+}
+
+/// Parses both paths and the comparison of `assert_json_relation`.
+fn parse_json_relation(
+    relation: &AssertJsonRelation,
+    src: Option<&(String, String)>,
+) -> Result<Assertion, ValidationError> {
+    let invalid = |message: String, value: &str| {
+        validation_err!(
+            src,
+            "assert_json_relation",
+            message,
+            find_value_span(src, value)
+        )
+    };
+
+    let left = JsonOperand::parse(&relation.left).map_err(|e| invalid(e, &relation.left))?;
+    let right = JsonOperand::parse(&relation.right).map_err(|e| invalid(e, &relation.right))?;
+    let op = relation
+        .op
+        .parse::<Comparison>()
+        .map_err(|e| invalid(e, &relation.op))?;
+
+    Ok(Assertion::JsonRelation { left, op, right })
+}
+
+/// Parses `assert_cookie`, checking that `samesite` is one of the values
+/// browsers understand.
+fn parse_cookie(
+    cookie: &AssertCookie,
+    src: Option<&(String, String)>,
+) -> Result<ExpectedCookie, ValidationError> {
+    let same_site = match cookie.samesite.as_deref() {
+        None => None,
+        Some(s) if s.eq_ignore_ascii_case("strict") => Some(SameSite::Strict),
+        Some(s) if s.eq_ignore_ascii_case("lax") => Some(SameSite::Lax),
+        Some(s) if s.eq_ignore_ascii_case("none") => Some(SameSite::None),
+        Some(s) => {
+            return Err(validation_err!(
+                src,
+                "samesite",
+                format!("Expected `Strict`, `Lax` or `None` for samesite, got `{s}`"),
+                find_value_span(src, s)
+            ));
+        }
+    };
+
+    Ok(ExpectedCookie {
+        name: cookie.name.clone(),
+        value: cookie.value.clone(),
+        http_only: cookie.httponly,
+        secure: cookie.secure,
+        same_site,
+        max_age: cookie.max_age,
+    })
+}
+
 /// Parses all available assertion configurations (status, headers, etc.) into a
 /// Vec<Assertion>.
 pub fn parse_assertions(
-    assert_status: &Option<i32>,
-    assert_headers: &Option<Value>,
-    assert_sql: &Option<AssertSql>,
-    assert_json: &Option<serde_json::Value>,
+    test: &Test,
     src: Option<(&str, &str)>,
 ) -> Result<Vec<Assertion>, ValidationError> {
     let mut assert_vec = vec![];
     let src_ref = src.as_ref().map(|(n, c)| (n.to_string(), c.to_string()));
 
-    if let Some(status) = assert_status {
+    if let Some(epsilon) = test.epsilon
+        && !(epsilon.is_finite() && epsilon >= 0.0)
+    {
+        return Err(validation_err!(
+            src_ref,
+            "epsilon",
+            format!("epsilon has to be a positive number, got {epsilon}"),
+            find_key_span(src_ref.as_ref(), "epsilon")
+        ));
+    }
+
+    if let Some(status) = &test.assert_status {
         assert_vec.push(Assertion::Status(*status));
     }
 
-    if let Some(value) = assert_headers {
-        let header_map = parse_header_map(value, src_ref.as_ref())?;
-        assert_vec.push(Assertion::Headers(header_map));
+    if let Some(value) = &test.assert_headers {
+        let headers = parse_header_assertions("assert_headers", value, src_ref.as_ref())?;
+        assert_vec.push(Assertion::Headers(headers));
     }
 
-    if let Some(sql) = assert_sql {
+    if let Some(value) = &test.assert_trailers {
+        let trailers = parse_header_assertions("assert_trailers", value, src_ref.as_ref())?;
+        assert_vec.push(Assertion::Trailers(trailers));
+    }
+
+    if let Some(var) = &test.assert_json_equals_var {
+        if var.trim().is_empty() {
+            return Err(validation_err!(
+                src_ref,
+                "assert_json_equals_var",
+                "Expected the name of a body stored with capture_json".to_string(),
+                find_key_span(src_ref.as_ref(), "assert_json_equals_var")
+            ));
+        }
+        assert_vec.push(Assertion::JsonEqualsVar {
+            var: var.clone(),
+            captured: None,
+        });
+    }
+
+    if let Some(header) = &test.assert_header_int {
+        assert_vec.push(parse_header_int(header, src_ref.as_ref())?);
+    }
+
+    if let Some(version) = &test.assert_http_version {
+        let version = parse_http_version(version).map_err(|message| {
+            validation_err!(
+                src_ref,
+                "assert_http_version",
+                message,
+                find_value_span(src_ref.as_ref(), version)
+            )
+        })?;
+        assert_vec.push(Assertion::HttpVersion(version));
+    }
+
+    if let Some(content_encoding) = &test.assert_content_encoding {
+        let (encoding, verify_body) = match content_encoding {
+            AssertContentEncoding::Encoding(encoding) => (encoding, false),
+            AssertContentEncoding::Detailed {
+                encoding,
+                verify_body,
+            } => (encoding, verify_body.unwrap_or(false)),
+        };
+        let encoding = encoding.parse::<ContentEncoding>().map_err(|message| {
+            validation_err!(
+                src_ref,
+                "assert_content_encoding",
+                message,
+                find_value_span(src_ref.as_ref(), encoding)
+            )
+        })?;
+        assert_vec.push(Assertion::ContentEncoding {
+            encoding,
+            verify_body,
+        });
+    }
+
+    if let Some(sql) = &test.assert_db_state {
         assert_vec.push(Assertion::Sql {
             query: sql.query.clone(),
             expect: sql.expect.clone(),
             got: None,
+            epsilon: test.epsilon,
+            poll: None,
+        });
+    }
+
+    if let Some(sql) = &test.poll_sql {
+        if sql.interval_ms == Some(0) {
+            return Err(validation_err!(
+                src_ref,
+                "poll_sql",
+                "poll_sql `interval_ms` has to be larger than 0".to_string(),
+                find_key_span(src_ref.as_ref(), "interval_ms")
+            ));
+        }
+        assert_vec.push(Assertion::Sql {
+            query: sql.query.clone(),
+            expect: sql.expect.clone(),
+            got: None,
+            epsilon: test.epsilon,
+            poll: Some(SqlPoll {
+                interval: Duration::from_millis(
+                    sql.interval_ms.unwrap_or(DEFAULT_POLL_INTERVAL_MS),
+                ),
+                timeout: Duration::from_millis(sql.timeout_ms.unwrap_or(DEFAULT_POLL_TIMEOUT_MS)),
+            }),
+        });
+    }
+
+    if let Some(sql) = &test.assert_db_compare {
+        let op = sql.op.parse::<Comparison>().map_err(|message| {
+            validation_err!(
+                src_ref,
+                "assert_db_compare",
+                message,
+                find_value_span(src_ref.as_ref(), &sql.op)
+            )
+        })?;
+        assert_vec.push(Assertion::SqlCompare {
+            query: sql.query.clone(),
+            op,
+            value: sql.value.clone(),
+            got: None,
+        });
+    }
+
+    let ignore_keys = test.ignore_keys.clone().unwrap_or_default();
+    if let Some(key) = ignore_keys
+        .iter()
+        .find(|key| key.split('.').any(str::is_empty))
+    {
+        return Err(validation_err!(
+            src_ref,
+            "ignore_keys",
+            format!("`{key}` in ignore_keys is not a key or a dotted path of keys"),
+            find_value_span(src_ref.as_ref(), key)
+        ));
+    }
+
+    if let Some(json) = &test.assert_json {
+        assert_vec.push(Assertion::Json {
+            expected: json.clone(),
+            epsilon: test.epsilon,
+            ignore_keys,
+        });
+    } else if !ignore_keys.is_empty() {
+        return Err(validation_err!(
+            src_ref,
+            "ignore_keys",
+            "ignore_keys only applies to assert_json, which this test doesn't have".to_string(),
+            find_key_span(src_ref.as_ref(), "ignore_keys")
+        ));
+    }
+
+    if let Some(alternatives) = &test.assert_json_any_of {
+        if alternatives.is_empty() {
+            return Err(validation_err!(
+                src_ref,
+                "assert_json_any_of",
+                "assert_json_any_of needs at least one JSON document".to_string(),
+                find_key_span(src_ref.as_ref(), "assert_json_any_of")
+            ));
+        }
+        assert_vec.push(Assertion::JsonAnyOf(alternatives.clone()));
+    }
+
+    if let Some(array_len) = &test.assert_json_array_len {
+        let (path, expected) = match array_len {
+            AssertJsonArrayLen::Body(expected) => ("$", *expected),
+            AssertJsonArrayLen::AtPath { path, expect } => (path.as_str(), *expect),
+        };
+        let path = JsonPath::parse(path).map_err(|message| {
+            validation_err!(
+                src_ref,
+                "assert_json_array_len",
+                message,
+                find_value_span(src_ref.as_ref(), path)
+            )
+        })?;
+        assert_vec.push(Assertion::JsonArrayLen { path, expected });
+    }
+
+    if let Some(paths) = &test.assert_json_has {
+        if paths.is_empty() {
+            return Err(validation_err!(
+                src_ref,
+                "assert_json_has",
+                "assert_json_has needs at least one JSON path".to_string(),
+                find_key_span(src_ref.as_ref(), "assert_json_has")
+            ));
+        }
+        let paths = paths
+            .iter()
+            .map(|path| {
+                JsonPath::parse(path).map_err(|message| {
+                    validation_err!(
+                        src_ref,
+                        "assert_json_has",
+                        message,
+                        find_value_span(src_ref.as_ref(), path)
+                    )
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        assert_vec.push(Assertion::JsonHas(paths));
+    }
+
+    if let Some(relation) = &test.assert_json_relation {
+        assert_vec.push(parse_json_relation(relation, src_ref.as_ref())?);
+    }
+
+    if let Some(cookie) = &test.assert_cookie {
+        assert_vec.push(Assertion::Cookie(parse_cookie(cookie, src_ref.as_ref())?));
+    }
+
+    if test.assert_empty_body == Some(true) {
+        assert_vec.push(Assertion::EmptyBody);
+    }
+
+    if test.assert_valid_json == Some(true) {
+        assert_vec.push(Assertion::ValidJson);
+    }
+
+    if let Some(path) = &test.assert_body_snapshot {
+        // Relative to the config file, like `body_file`.
+        let dir = src.map_or(Path::new(""), |(file_name, _)| {
+            Path::new(file_name).parent().unwrap_or(Path::new(""))
+        });
+        assert_vec.push(Assertion::Snapshot {
+            path: dir.join(path),
+            update: false,
+        });
+    }
+
+    if let Some(redirect) = &test.assert_redirect {
+        let invalid = |message: &str| {
+            validation_err!(
+                src_ref,
+                "assert_redirect",
+                message.to_string(),
+                find_key_span(src_ref.as_ref(), "assert_redirect")
+            )
+        };
+        if redirect.url.is_none() && redirect.hops.is_none() {
+            return Err(invalid(
+                "assert_redirect needs at least one of `url` or `hops`",
+            ));
+        }
+        if test.follow_redirects == Some(false) {
+            return Err(invalid(
+                "assert_redirect can't be used with follow_redirects = false",
+            ));
+        }
+        assert_vec.push(Assertion::Redirect {
+            url: redirect.url.clone(),
+            hops: redirect.hops,
+            got: None,
         });
     }
 
-    if let Some(json) = assert_json {
-        assert_vec.push(Assertion::Json(json.clone()));
+    if let Some(size) = &test.assert_body_size {
+        let invalid = |message: &str| {
+            validation_err!(
+                src_ref,
+                "assert_body_size",
+                message.to_string(),
+                find_key_span(src_ref.as_ref(), "assert_body_size")
+            )
+        };
+        let size = match *size {
+            AssertBodySize::Exact(bytes) => BodySize::Exact(bytes),
+            AssertBodySize::Range {
+                min: None,
+                max: None,
+            } => {
+                return Err(invalid(
+                    "assert_body_size needs at least one of `min` or `max`",
+                ));
+            }
+            AssertBodySize::Range {
+                min: Some(min),
+                max: Some(max),
+            } if min > max => {
+                return Err(invalid(&format!(
+                    "assert_body_size `min` ({min}) can't be larger than `max` ({max})"
+                )));
+            }
+            AssertBodySize::Range { min, max } => BodySize::Range { min, max },
+        };
+        assert_vec.push(Assertion::BodySize(size));
+    }
+
+    if let Some(message) = &test.assert_ws_message {
+        assert_vec.push(Assertion::WsMessage(match message {
+            AssertWsMessage::Equals(expected) => WsMessage::Equals(expected.clone()),
+            AssertWsMessage::Contains { contains } => WsMessage::Contains(contains.clone()),
+            AssertWsMessage::Json { json } => WsMessage::Json(json.clone()),
+        }));
+    }
+
+    if let Some(error) = &test.assert_request_error {
+        let expected = match error {
+            AssertRequestError::Contains(text) => ExpectedError::Contains(text.clone()),
+            AssertRequestError::Regex { regex } => {
+                ExpectedError::Regex(Regex::new(regex).map_err(|e| {
+                    validation_err!(
+                        src_ref,
+                        "assert_request_error",
+                        format!("Invalid regex for `assert_request_error`: {e}"),
+                        find_value_span(src_ref.as_ref(), regex)
+                    )
+                })?)
+            }
+        };
+
+        // A request that fails has no response the other assertions could
+        // check.
+        if let Some(key) = assert_vec.iter().find_map(Assertion::config_key) {
+            return Err(validation_err!(
+                src_ref,
+                "assert_request_error",
+                format!("assert_request_error can't be combined with {key}"),
+                find_key_span(src_ref.as_ref(), "assert_request_error")
+            ));
+        }
+        assert_vec.push(Assertion::RequestError(expected));
+    }
+
+    // A body captured to a file is never read into memory, so only its size
+    // and contents as a whole can be checked.
+    if test.capture_to_file == Some(true)
+        && let Some(key) = assert_vec
+            .iter()
+            .filter(|a| a.needs_body())
+            .filter(|a| {
+                !matches!(
+                    a,
+                    Assertion::BodySize(_)
+                        | Assertion::Snapshot { .. }
+                        | Assertion::ContentEncoding { .. }
+                )
+            })
+            .find_map(Assertion::config_key)
+    {
+        return Err(validation_err!(
+            src_ref,
+            "capture_to_file",
+            format!(
+                "capture_to_file only works with assert_body_size, assert_body_snapshot and assert_content_encoding, not {key}"
+            ),
+            find_key_span(src_ref.as_ref(), "capture_to_file")
+        ));
     }
 
     Ok(assert_vec)
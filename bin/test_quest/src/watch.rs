@@ -0,0 +1,90 @@
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use flume::Receiver;
+use notify::Event;
+use notify::RecommendedWatcher;
+use notify::RecursiveMode;
+use notify::Watcher;
+
+/// How long the files have to stay untouched before a batch of changes is
+/// reported. Editors often write a file several times when saving.
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// Watches the config file and the files it references for changes.
+pub struct FileWatcher {
+    // Kept alive for as long as we want to receive events.
+    _watcher: RecommendedWatcher,
+    rx: Receiver<notify::Result<Event>>,
+    targets: Vec<PathBuf>,
+}
+
+impl FileWatcher {
+    /// Starts watching `paths`, which may be files or directories.
+    ///
+    /// Files are watched through their parent directory, so editors that save
+    /// by replacing the file are still picked up.
+    pub fn new(paths: &[PathBuf]) -> Result<Self, notify::Error> {
+        let (tx, rx) = flume::unbounded();
+        let mut watcher = notify::recommended_watcher(move |event| {
+            let _ = tx.send(event);
+        })?;
+
+        let mut targets = Vec::with_capacity(paths.len());
+        for path in paths {
+            let path = path.canonicalize()?;
+
+            if path.is_dir() {
+                watcher.watch(&path, RecursiveMode::Recursive)?;
+            } else {
+                let parent = path.parent().unwrap_or(Path::new("/"));
+                watcher.watch(parent, RecursiveMode::NonRecursive)?;
+            }
+
+            targets.push(path);
+        }
+
+        Ok(Self {
+            _watcher: watcher,
+            rx,
+            targets,
+        })
+    }
+
+    /// Waits until one of the watched paths changes, then keeps collecting
+    /// events until none arrived for `DEBOUNCE`. Returns the watched paths
+    /// that changed.
+    pub async fn changed(&self) -> Vec<PathBuf> {
+        let mut changed = vec![];
+
+        while changed.is_empty() {
+            let Ok(event) = self.rx.recv_async().await else {
+                return changed;
+            };
+            self.collect(event, &mut changed);
+        }
+
+        while let Ok(Ok(event)) = tokio::time::timeout(DEBOUNCE, self.rx.recv_async()).await {
+            self.collect(event, &mut changed);
+        }
+
+        changed
+    }
+
+    fn collect(&self, event: notify::Result<Event>, changed: &mut Vec<PathBuf>) {
+        let Ok(event) = event else {
+            return;
+        };
+
+        if event.kind.is_access() {
+            return;
+        }
+
+        for target in &self.targets {
+            if event.paths.iter().any(|p| p.starts_with(target)) && !changed.contains(target) {
+                changed.push(target.clone());
+            }
+        }
+    }
+}
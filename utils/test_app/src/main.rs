@@ -8,6 +8,7 @@ use axum::extract::Request;
 use axum::extract::State;
 use axum::http::HeaderMap;
 use axum::http::StatusCode;
+use axum::http::header;
 use axum::middleware::Next;
 use axum::response::IntoResponse;
 use axum::response::Response;
@@ -54,6 +55,7 @@ async fn main() -> anyhow::Result<()> {
 
     let app = Router::new()
         .route("/health", get(health))
+        .route("/healthz", get(healthz))
         .route("/login", post(login))
         .route("/login/password/change", patch(change_password))
         .route("/ready", get(ready))
@@ -118,6 +120,10 @@ async fn health() -> &'static str {
     "ok"
 }
 
+async fn healthz() -> impl IntoResponse {
+    (StatusCode::FOUND, [(header::LOCATION, "/health")])
+}
+
 async fn ready() -> &'static str {
     "ok"
 }